@@ -1,22 +1,36 @@
 #![allow(unused_imports)]
 #[macro_use]
 extern crate log;
-use actix_web::{App, post, HttpServer, middleware::Logger, web, HttpResponse, Responder};
+use actix_web::{App, post, HttpServer, middleware::Logger, web, HttpResponse, Responder, ResponseError};
 use anyhow::Result;
 use deadpool_postgres::{Pool as PGPool};
-use dotenv::dotenv;
+use lightning_rs_webhook::config::Config;
 use lightning_rs_webhook::db::pg_pool_from_url;
-use lightning_rs_webhook::error::ServiceError;
+use lightning_rs_webhook::error::{HandlerResult, HandlerResultExt, ServiceError};
+use lightning_rs_webhook::health::{LNbitsCheck, PostgresCheck, Readiness};
 use lightning_rs_webhook::lnbits::lnbits_models::WebhookPayload;
+use lightning_rs_webhook::metrics;
+use lightning_rs_webhook::migrations;
+use lightning_rs_webhook::nostr::{zap::Zapper, Keys};
 use lightning_rs_webhook::routes;
+use tracing::Instrument;
 
 #[allow(unused_variables)]
-async fn webhook_handler(pg_pool: &PGPool, payload: WebhookPayload) -> Result<()> {
+async fn webhook_handler(app_data: &AppData, payload: WebhookPayload) -> HandlerResult {
+    let pg_pool = &app_data.pg_pool;
+
     match payload {
 
         // A webhook Payment Event
         WebhookPayload::Payment(payment) => {
-            debug!("PaymentEvent: {payment:?}");
+            debug!("PaymentEvent: {}", payment.payment_hash);
+
+            // If the payment was a Nostr zap (NIP-57) to our Lightning address, publish the zap receipt
+            if let Some(zapper) = &app_data.zapper {
+                if let Some(receipt) = zapper.process_payment(&payment).await? {
+                    info!("Published zap receipt: {}", receipt.id);
+                }
+            }
 
             // NOTE: It doesn't appear that LNbits has any HMAC check regarding webhook data. You may want to perform
             //       extra validation.
@@ -54,37 +68,78 @@ async fn webhook_handler(pg_pool: &PGPool, payload: WebhookPayload) -> Result<()
 #[post("/lnbits/webhook")]
 pub async fn lnbits_webhook_handler(payload: web::Json<WebhookPayload>, app_data: web::Data<AppData>) -> impl Responder {
 
-    dbg!(&payload);
+    // LNbits does not sign webhooks, so every request that parses is counted
+    let (event_type, payment_hash) = match &*payload {
+        WebhookPayload::Payment(payment) => ("payment", Some(payment.payment_hash.clone())),
+        WebhookPayload::Unsupported => ("unsupported", None),
+    };
+    metrics::webhook_received("lnbits", event_type);
+
+    let span = tracing::info_span!("lnbits.webhook", provider = "lnbits", event_type, payment_hash);
+    let handled = metrics::observe_handler("lnbits", webhook_handler(&app_data, payload.into_inner())).instrument(span);
 
-    match webhook_handler(&app_data.pg_pool, payload.into_inner()).await  {
+    match handled.await {
         Ok(_) => {
             // Webhook caller is expecting a 200 response
             HttpResponse::Ok().finish()
         },
+        // Permanent failures are acknowledged, so the provider stops redelivering them
+        Err(err) if err.is_retryable() => {
+            error!("Error: {err}");
+            err.error_response()
+        },
         Err(err) => {
-            error!("Error: {err:?}");
-            HttpResponse::InternalServerError().json("Internal Server Error")
+            warn!("Webhook not processed: {err}");
+            err.error_response()
         }
     }
 }
 
 pub struct AppData {
     pub pg_pool: PGPool,
+    pub zapper: Option<Zapper>,
 }
 
 #[actix_web::main]
 async fn main() -> Result<()> {
 
+    // With the telemetry feature, logs and spans go through tracing (JSON logs and OTLP export are optional)
+    #[cfg(feature = "telemetry")]
+    let _telemetry = lightning_rs_webhook::telemetry::init(lightning_rs_webhook::telemetry::TelemetryConfig::from_env())?;
+    #[cfg(not(feature = "telemetry"))]
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    dotenv().ok();
+    // Reads CONFIG_FILE (TOML), .env and the environment, reporting every problem before starting
+    let config = Config::load()?;
 
-    let host: String = std::env::var("HOST").expect("HOST must be set");
-    let port: String = std::env::var("PORT").expect("PORT must be set");
+    let pg_pool = pg_pool_from_url(config.postgres.url.expose())?;
 
-    let pg_address: String = std::env::var("POSTGRES_ADDRESS").expect("POSTGRES_ADDRESS must be set.");
-    let pg_pool = pg_pool_from_url(&pg_address)?;
+    // Bring the library's tables (inbox, relay, etc.) up to date
+    if config.postgres.migrate {
+        for migration in migrations::migrate(&pg_pool).await? {
+            info!("Applied migration {} ({})", migration.version, migration.name);
+        }
+    }
 
+    // Optional: the server key used to sign NIP-57 zap receipts, for zaps to the configured recipients
+    let zapper = match &config.nostr.secret_key {
+        Some(secret_key) if config.nostr.recipients.is_empty() => Some(Zapper::new(Keys::from_secret_hex(secret_key.expose())?)),
+        Some(secret_key) => Some(Zapper::new(Keys::from_secret_hex(secret_key.expose())?).with_recipients(&config.nostr.recipients)),
+        None => None,
+    };
+
+    // Checks behind /health/ready, one per configured wallet
+    let mut readiness = Readiness::new().with_check(PostgresCheck::new(pg_pool.clone()));
+    if let Some(lnbits) = &config.lnbits {
+        for wallet in &lnbits.wallets {
+            readiness = readiness.with_check(
+                LNbitsCheck::new(&lnbits.host, wallet.api_key.expose()).with_name(&format!("lnbits:{}", wallet.id))
+            );
+        }
+    }
+    let readiness = web::Data::new(readiness);
+
+    let (host, port) = config.bind_address();
     println!("Running LNbits Webhook Server on {host}:{port}");
 
     HttpServer::new(move || {
@@ -92,16 +147,26 @@ async fn main() -> Result<()> {
         let logger = Logger::default();
 
         let app_data = AppData {
-            pg_pool: pg_pool.clone()
+            pg_pool: pg_pool.clone(),
+            zapper: zapper.clone(),
         };
 
-        App::new()
+        let app = App::new()
             .wrap(logger)
             .app_data(web::Data::new(app_data))
+            .app_data(readiness.clone())
             .service(routes::health_handler)
-            .service(lnbits_webhook_handler)
+            .service(routes::health_live_handler)
+            .service(routes::health_ready_handler)
+            .service(lnbits_webhook_handler);
+
+        // Built with `--features metrics`
+        #[cfg(feature = "metrics")]
+        let app = app.service(routes::metrics_handler);
+
+        app
     })
-    .bind(format!("{host}:{port}"))?
+    .bind((host, port))?
     .run()
     .await?;
 
@@ -138,7 +203,8 @@ mod tests {
         let pg_pool = pg_pool_from_url(&pg_address).unwrap();
 
         let app_data = AppData {
-            pg_pool: pg_pool.clone()
+            pg_pool: pg_pool.clone(),
+            zapper: None,
         };
 
         let app = test::init_service(App::new()
@@ -180,7 +246,8 @@ mod tests {
         let pg_pool = pg_pool_from_url(&pg_address).unwrap();
 
         let app_data = AppData {
-            pg_pool: pg_pool.clone()
+            pg_pool: pg_pool.clone(),
+            zapper: None,
         };
 
         let app = test::init_service(App::new()
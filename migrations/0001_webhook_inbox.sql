@@ -0,0 +1,20 @@
+-- Verified webhook requests, stored before processing so they can be acknowledged at once
+CREATE TABLE IF NOT EXISTS webhook_inbox (
+    id              BIGSERIAL PRIMARY KEY,
+    provider        TEXT NOT NULL,
+    event_type      TEXT,
+    delivery_id     TEXT,
+    invoice_id      TEXT,
+    headers         JSONB NOT NULL,
+    body            TEXT NOT NULL,
+    status          TEXT NOT NULL DEFAULT 'pending',
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    last_error      TEXT,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    received_at     TIMESTAMPTZ NOT NULL DEFAULT now(),
+    processed_at    TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS webhook_inbox_pending_idx
+    ON webhook_inbox (next_attempt_at)
+    WHERE status = 'pending';
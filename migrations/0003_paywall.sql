@@ -0,0 +1,25 @@
+-- Paywall access grants. A grant links an identity (Nostr pubkey) to a piece of content,
+-- and records the payment event that granted it.
+
+CREATE TABLE IF NOT EXISTS identities (
+    id         SERIAL PRIMARY KEY,
+    pubkey     TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS content (
+    id         SERIAL PRIMARY KEY,
+    content_id TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS grants (
+    id              BIGSERIAL PRIMARY KEY,
+    identity_id     INTEGER NOT NULL REFERENCES identities (id) ON DELETE CASCADE,
+    content_id      INTEGER NOT NULL REFERENCES content (id) ON DELETE CASCADE,
+    provider        TEXT NOT NULL,
+    source_event_id TEXT NOT NULL,
+    invoice_id      TEXT,
+    granted_at      TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (identity_id, content_id)
+);
@@ -0,0 +1,3 @@
+-- Index the delivery log's foreign key, so cascading deletes of relay deliveries do not scan the log
+CREATE INDEX IF NOT EXISTS relay_delivery_log_delivery_idx
+    ON relay_delivery_log (delivery_id);
@@ -0,0 +1,10 @@
+-- Raw requests kept by `recorder::Recorder`, for reproducing production payloads
+CREATE TABLE IF NOT EXISTS webhook_recordings (
+    id          BIGSERIAL PRIMARY KEY,
+    -- Unix milliseconds, as recorded
+    recorded_at BIGINT NOT NULL,
+    method      TEXT NOT NULL,
+    path        TEXT NOT NULL,
+    headers     JSONB NOT NULL,
+    body        BYTEA NOT NULL
+);
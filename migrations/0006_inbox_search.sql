@@ -0,0 +1,17 @@
+-- Lookup columns and an attempt log for browsing the inbox, see `admin`
+ALTER TABLE webhook_inbox ADD COLUMN IF NOT EXISTS payment_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS webhook_inbox_received_idx ON webhook_inbox (received_at);
+CREATE INDEX IF NOT EXISTS webhook_inbox_invoice_idx ON webhook_inbox (invoice_id);
+CREATE INDEX IF NOT EXISTS webhook_inbox_payment_hash_idx ON webhook_inbox (payment_hash);
+
+-- One row per processing attempt of an inbox message
+CREATE TABLE IF NOT EXISTS webhook_inbox_attempts (
+    id           BIGSERIAL PRIMARY KEY,
+    inbox_id     BIGINT NOT NULL REFERENCES webhook_inbox (id) ON DELETE CASCADE,
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    outcome      TEXT NOT NULL,
+    error        TEXT
+);
+
+CREATE INDEX IF NOT EXISTS webhook_inbox_attempts_inbox_idx ON webhook_inbox_attempts (inbox_id);
@@ -0,0 +1,9 @@
+-- Webhook requests rejected before processing (eg. bad signatures), counted per hour
+CREATE TABLE IF NOT EXISTS webhook_verification_failures (
+    provider TEXT NOT NULL,
+    reason   TEXT NOT NULL,
+    -- Start of the hour, unix seconds
+    hour     BIGINT NOT NULL,
+    count    BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (provider, reason, hour)
+);
@@ -0,0 +1,3 @@
+-- Whether a verifier accepted the request, as the relay only signs events from verified requests.
+-- Messages stored before this are not known to be verified.
+ALTER TABLE webhook_inbox ADD COLUMN IF NOT EXISTS verified BOOLEAN NOT NULL DEFAULT false;
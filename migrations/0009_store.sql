@@ -0,0 +1,26 @@
+-- Idempotency keys, a delivery log and checkpoints used by `store::PostgresStore`. Its delivery log
+-- is kept apart from `relay_delivery_log`, which references the relay's own queue.
+CREATE TABLE IF NOT EXISTS webhook_idempotency_keys (
+    scope      TEXT NOT NULL,
+    key        TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (scope, key)
+);
+
+CREATE TABLE IF NOT EXISTS webhook_delivery_log (
+    id           BIGSERIAL PRIMARY KEY,
+    delivery_id  BIGINT NOT NULL,
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    status_code  INTEGER,
+    error        TEXT,
+    duration_ms  INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS webhook_delivery_log_delivery_idx
+    ON webhook_delivery_log (delivery_id);
+
+CREATE TABLE IF NOT EXISTS webhook_checkpoints (
+    name       TEXT PRIMARY KEY,
+    value      TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
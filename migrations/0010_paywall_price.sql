@@ -0,0 +1,3 @@
+-- The price of each piece of content, as paywall grants need the payment to cover it.
+-- Content registered before this is free, so existing grants keep working.
+ALTER TABLE content ADD COLUMN IF NOT EXISTS price_msat BIGINT NOT NULL DEFAULT 0;
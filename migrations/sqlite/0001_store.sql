@@ -0,0 +1,19 @@
+-- Tables used by `store::SqliteStore`. Timestamps are unix milliseconds.
+CREATE TABLE IF NOT EXISTS webhook_inbox (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    provider        TEXT NOT NULL,
+    event_type      TEXT,
+    delivery_id     TEXT,
+    invoice_id      TEXT,
+    headers         TEXT NOT NULL,
+    body            TEXT NOT NULL,
+    status          TEXT NOT NULL DEFAULT 'pending',
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    last_error      TEXT,
+    next_attempt_at INTEGER NOT NULL,
+    received_at     INTEGER NOT NULL,
+    processed_at    INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS webhook_inbox_pending_idx
+    ON webhook_inbox (status, next_attempt_at);
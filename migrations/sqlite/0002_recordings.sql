@@ -0,0 +1,9 @@
+-- Raw requests kept by `recorder::Recorder`
+CREATE TABLE IF NOT EXISTS webhook_recordings (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    recorded_at INTEGER NOT NULL,
+    method      TEXT NOT NULL,
+    path        TEXT NOT NULL,
+    headers     TEXT NOT NULL,
+    body        BLOB NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Lookup columns and an attempt log for browsing the inbox
+ALTER TABLE webhook_inbox ADD COLUMN payment_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS webhook_inbox_received_idx ON webhook_inbox (received_at);
+CREATE INDEX IF NOT EXISTS webhook_inbox_invoice_idx ON webhook_inbox (invoice_id);
+CREATE INDEX IF NOT EXISTS webhook_inbox_payment_hash_idx ON webhook_inbox (payment_hash);
+
+CREATE TABLE IF NOT EXISTS webhook_inbox_attempts (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    inbox_id     INTEGER NOT NULL,
+    attempted_at INTEGER NOT NULL,
+    outcome      TEXT NOT NULL,
+    error        TEXT
+);
+
+CREATE INDEX IF NOT EXISTS webhook_inbox_attempts_inbox_idx ON webhook_inbox_attempts (inbox_id);
@@ -0,0 +1,8 @@
+-- Webhook requests rejected before processing, counted per hour
+CREATE TABLE IF NOT EXISTS webhook_verification_failures (
+    provider TEXT NOT NULL,
+    reason   TEXT NOT NULL,
+    hour     INTEGER NOT NULL,
+    count    INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (provider, reason, hour)
+);
@@ -0,0 +1,2 @@
+-- Whether a verifier accepted the request, as the relay only signs events from verified requests
+ALTER TABLE webhook_inbox ADD COLUMN verified INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,25 @@
+-- Idempotency keys, a delivery log and checkpoints
+CREATE TABLE IF NOT EXISTS webhook_idempotency_keys (
+    scope      TEXT NOT NULL,
+    key        TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (scope, key)
+);
+
+CREATE TABLE IF NOT EXISTS webhook_delivery_log (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    delivery_id  INTEGER NOT NULL,
+    attempted_at INTEGER NOT NULL,
+    status_code  INTEGER,
+    error        TEXT,
+    duration_ms  INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS webhook_delivery_log_delivery_idx
+    ON webhook_delivery_log (delivery_id);
+
+CREATE TABLE IF NOT EXISTS webhook_checkpoints (
+    name       TEXT PRIMARY KEY,
+    value      TEXT NOT NULL,
+    updated_at INTEGER NOT NULL
+);
@@ -0,0 +1,461 @@
+// Admin API for browsing received deliveries (the webhook inbox) and reprocessing them. It is
+// mounted apart from the webhook routes and protected by bearer tokens, each granted read and/or
+// write scope. Timestamps are unix seconds.
+
+use actix_web::{
+    http::{header::{self, HeaderValue}, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError, Scope,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::problem_response;
+use crate::inbox::InboxStatus;
+use crate::store::{InboxAttempt, InboxFilter, InboxRecord, WebhookStore};
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminScope {
+    /// Browse deliveries
+    Read,
+    /// Reprocess deliveries. Also grants read.
+    Write,
+}
+
+impl AdminScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminScope::Read => "read",
+            AdminScope::Write => "write",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct AdminToken {
+    name: String,
+    token: String,
+    scopes: Vec<AdminScope>,
+}
+
+impl AdminToken {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn grants(&self, scope: AdminScope) -> bool {
+        self.scopes.contains(&scope) || self.scopes.contains(&AdminScope::Write)
+    }
+
+    /// HMAC-SHA256 of `message` keyed with the token, eg. for a session that must not carry the token
+    pub(crate) fn sign(&self, message: &[u8]) -> ring::hmac::Tag {
+        ring::hmac::sign(&ring::hmac::Key::new(ring::hmac::HMAC_SHA256, self.token.as_bytes()), message)
+    }
+
+    /// Check a `sign` tag in constant time
+    pub(crate) fn verify(&self, message: &[u8], tag: &[u8]) -> bool {
+        ring::hmac::verify(&ring::hmac::Key::new(ring::hmac::HMAC_SHA256, self.token.as_bytes()), message, tag).is_ok()
+    }
+}
+
+/// Bearer tokens accepted by the admin API
+#[derive(Clone, Default)]
+pub struct AdminTokens {
+    tokens: Arc<Vec<AdminToken>>,
+}
+
+impl AdminTokens {
+    pub fn new() -> Self {
+        AdminTokens::default()
+    }
+
+    /// Accept `token`, logging requests made with it under `name`
+    pub fn with_token(mut self, name: &str, token: &str, scopes: &[AdminScope]) -> Self {
+        Arc::make_mut(&mut self.tokens).push(AdminToken {
+            name: name.to_string(),
+            token: token.to_string(),
+            scopes: scopes.to_vec(),
+        });
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub(crate) fn named(&self, name: &str) -> Option<&AdminToken> {
+        self.tokens.iter().find(|token| token.name == name)
+    }
+
+    /// The token matching a presented one, compared in constant time
+    pub(crate) fn lookup(&self, presented: &str) -> Option<&AdminToken> {
+        self.tokens.iter().find(|token| {
+            ring::constant_time::verify_slices_are_equal(token.token.as_bytes(), presented.as_bytes()).is_ok()
+        })
+    }
+
+    /// The name of the request's token, if it grants `scope`
+    fn authorize(&self, req: &HttpRequest, scope: AdminScope) -> Result<&str, AdminError> {
+        let bearer = req.headers().get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+
+        match bearer.and_then(|bearer| self.lookup(bearer)) {
+            None => Err(AdminError::Unauthorized),
+            Some(token) if token.grants(scope) => Ok(token.name()),
+            Some(_) => Err(AdminError::Forbidden(scope)),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum AdminError {
+    Unauthorized,
+    Forbidden(AdminScope),
+    BadRequest(String),
+    NotFound,
+    Conflict(&'static str),
+    Internal(anyhow::Error),
+}
+
+impl From<anyhow::Error> for AdminError {
+    fn from(err: anyhow::Error) -> Self {
+        AdminError::Internal(err)
+    }
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminError::Unauthorized => f.write_str("a valid bearer token is required"),
+            AdminError::Forbidden(scope) => write!(f, "token lacks the {} scope", scope.as_str()),
+            AdminError::BadRequest(detail) => f.write_str(detail),
+            AdminError::NotFound => f.write_str("no such delivery"),
+            AdminError::Conflict(detail) => f.write_str(detail),
+            AdminError::Internal(err) => write!(f, "{err:#}"),
+        }
+    }
+}
+
+impl ResponseError for AdminError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AdminError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AdminError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AdminError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AdminError::NotFound => StatusCode::NOT_FOUND,
+            AdminError::Conflict(_) => StatusCode::CONFLICT,
+            AdminError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AdminError::Internal(err) => {
+                error!("Error: {err:?}");
+                problem_response(self.status_code(), None)
+            },
+            AdminError::Unauthorized => {
+                let mut response = problem_response(self.status_code(), Some(&self.to_string()));
+                response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer realm=\"admin\""));
+                response
+            },
+            _ => problem_response(self.status_code(), Some(&self.to_string())),
+        }
+    }
+}
+
+struct Admin {
+    store: Arc<dyn WebhookStore>,
+    tokens: AdminTokens,
+}
+
+/// The admin API, mounted at `path`:
+///
+/// - `GET {path}/deliveries` lists deliveries, newest first, filtered by `provider`, `event_type`,
+///   `invoice_id`, `payment_hash`, `status`, `since`, `until`, paged with `before_id` and `limit`
+/// - `GET {path}/deliveries/{id}` returns a delivery with its payload, headers and attempts
+/// - `POST {path}/deliveries/{id}/reprocess` queues a processed or dead-lettered delivery again
+/// - `POST {path}/dead-letters/requeue` queues every dead-lettered delivery matching the same filters,
+///   a page of `limit` at a time
+///
+/// ```ignore
+/// App::new()
+///     .service(admin::scope("/admin", store.clone(), tokens))
+/// ```
+pub fn scope(path: &str, store: Arc<dyn WebhookStore>, tokens: AdminTokens) -> Scope {
+    web::scope(path)
+        .app_data(web::Data::new(Admin { store, tokens }))
+        .route("/deliveries", web::get().to(list_deliveries))
+        .route("/deliveries/{id}", web::get().to(get_delivery))
+        .route("/deliveries/{id}/reprocess", web::post().to(reprocess_delivery))
+        .route("/dead-letters/requeue", web::post().to(requeue_dead_letters))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DeliveryQuery {
+    provider: Option<String>,
+    event_type: Option<String>,
+    invoice_id: Option<String>,
+    payment_hash: Option<String>,
+    status: Option<String>,
+    since: Option<u64>,
+    until: Option<u64>,
+    before_id: Option<i64>,
+    limit: Option<i64>,
+}
+
+impl DeliveryQuery {
+    fn parse(req: &HttpRequest) -> Result<InboxFilter, AdminError> {
+        let query = web::Query::<DeliveryQuery>::from_query(req.query_string())
+            .map_err(|err| AdminError::BadRequest(err.to_string()))?
+            .into_inner();
+
+        let status = query.status
+            .map(|status| status.parse::<InboxStatus>())
+            .transpose()
+            .map_err(|err| AdminError::BadRequest(err.to_string()))?;
+
+        Ok(InboxFilter {
+            provider: query.provider,
+            event_type: query.event_type,
+            invoice_id: query.invoice_id,
+            payment_hash: query.payment_hash,
+            status,
+            since: query.since.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            until: query.until.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            before_id: query.before_id,
+            limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+        })
+    }
+}
+
+type AdminResult = Result<HttpResponse, AdminError>;
+
+async fn list_deliveries(req: HttpRequest, admin: web::Data<Admin>) -> AdminResult {
+    admin.tokens.authorize(&req, AdminScope::Read)?;
+    let filter = DeliveryQuery::parse(&req)?;
+
+    let records = admin.store.inbox_search(&filter).await?;
+
+    // Only offered when the page is full, so an empty next page is rare
+    let next_before_id = match records.len() as i64 == filter.limit {
+        true => records.last().map(|record| record.message.id),
+        false => None,
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "deliveries": records.iter().map(summary).collect::<Vec<_>>(),
+        "next_before_id": next_before_id,
+    })))
+}
+
+async fn get_delivery(req: HttpRequest, id: web::Path<i64>, admin: web::Data<Admin>) -> AdminResult {
+    admin.tokens.authorize(&req, AdminScope::Read)?;
+
+    let record = admin.store.inbox_record(*id).await?.ok_or(AdminError::NotFound)?;
+    let attempts = admin.store.inbox_attempts(*id).await?;
+
+    Ok(HttpResponse::Ok().json(detail(&record, &attempts)))
+}
+
+async fn reprocess_delivery(req: HttpRequest, id: web::Path<i64>, admin: web::Data<Admin>) -> AdminResult {
+    let token = admin.tokens.authorize(&req, AdminScope::Write)?;
+
+    match admin.store.inbox_status(*id).await? {
+        None => return Err(AdminError::NotFound),
+        Some(InboxStatus::Pending) => return Err(AdminError::Conflict("delivery is already pending")),
+        Some(_) => {},
+    }
+
+    admin.store.inbox_requeue(*id).await?;
+    info!("Admin token {token} queued delivery {id} for reprocessing");
+
+    Ok(HttpResponse::Accepted().json(json!({ "id": *id, "status": InboxStatus::Pending.as_str() })))
+}
+
+async fn requeue_dead_letters(req: HttpRequest, admin: web::Data<Admin>) -> AdminResult {
+    let token = admin.tokens.authorize(&req, AdminScope::Write)?;
+    let mut filter = InboxFilter { status: Some(InboxStatus::Dead), ..DeliveryQuery::parse(&req)? };
+
+    let mut requeued = Vec::new();
+    loop {
+        let records = admin.store.inbox_search(&filter).await?;
+        for record in &records {
+            admin.store.inbox_requeue(record.message.id).await?;
+            requeued.push(record.message.id);
+        }
+
+        match records.last() {
+            Some(last) if records.len() as i64 == filter.limit => filter.before_id = Some(last.message.id),
+            _ => break,
+        }
+    }
+
+    info!("Admin token {token} requeued {} dead-lettered deliveries", requeued.len());
+    Ok(HttpResponse::Ok().json(json!({ "requeued": requeued })))
+}
+
+fn summary(record: &InboxRecord) -> Value {
+    let message = &record.message;
+    json!({
+        "id": message.id,
+        "provider": message.provider,
+        "event_type": message.event_type,
+        "delivery_id": message.delivery_id,
+        "invoice_id": message.invoice_id,
+        "payment_hash": message.payment_hash,
+        "status": record.status.as_str(),
+        "attempts": message.attempts,
+        "last_error": record.last_error,
+        "received_at": unix(message.received_at),
+        "processed_at": record.processed_at.map(unix),
+    })
+}
+
+fn detail(record: &InboxRecord, attempts: &[InboxAttempt]) -> Value {
+    let mut detail = summary(record);
+
+    // Bodies are JSON for every provider so far, but are kept verbatim when they are not
+    let payload = serde_json::from_str(&record.message.body)
+        .unwrap_or_else(|_| Value::String(record.message.body.clone()));
+
+    detail["headers"] = record.message.headers.clone();
+    detail["payload"] = payload;
+    detail["attempt_log"] = attempts.iter().map(|attempt| json!({
+        "attempted_at": unix(attempt.attempted_at),
+        "outcome": attempt.outcome,
+        "error": attempt.error,
+    })).collect();
+
+    detail
+}
+
+fn unix(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        test::{call_and_read_body_json, call_service, init_service, TestRequest},
+        App,
+    };
+
+    use crate::store::{InboxOutcome, MemoryStore, NewInboxMessage};
+
+    const READ_TOKEN: &str = "read-token-3b6f0c";
+    const WRITE_TOKEN: &str = "write-token-91d2aa";
+
+    fn message(provider: &str, invoice_id: Option<&str>, payment_hash: Option<&str>) -> NewInboxMessage {
+        NewInboxMessage {
+            provider: provider.to_string(),
+            event_type: Some("InvoiceSettled".to_string()),
+            delivery_id: None,
+            invoice_id: invoice_id.map(str::to_string),
+            payment_hash: payment_hash.map(str::to_string),
+            verified: true,
+            headers: json!({"content-type": "application/json"}),
+            body: r#"{"amount": 2100}"#.to_string(),
+        }
+    }
+
+    fn get(uri: &str, token: &str) -> actix_http::Request {
+        TestRequest::get().uri(uri).insert_header((header::AUTHORIZATION, format!("Bearer {token}"))).to_request()
+    }
+
+    fn post(uri: &str, token: &str) -> actix_http::Request {
+        TestRequest::post().uri(uri).insert_header((header::AUTHORIZATION, format!("Bearer {token}"))).to_request()
+    }
+
+    #[actix_web::test]
+    async fn test_browse_and_reprocess() {
+        let store = Arc::new(MemoryStore::new());
+        let settled = store.inbox_push(message("btcpay", Some("6wmoR7p5UFVzCYuwyiViKX"), None)).await.unwrap();
+        let paid = store.inbox_push(message("lnbits", None, Some("0b5fc8ee"))).await.unwrap();
+
+        store.inbox_claim(Duration::ZERO).await.unwrap();
+        store.inbox_finish(settled, InboxOutcome::Dead { error: "missing posData".to_string() }).await.unwrap();
+
+        let tokens = AdminTokens::new()
+            .with_token("dashboard", READ_TOKEN, &[AdminScope::Read])
+            .with_token("ops", WRITE_TOKEN, &[AdminScope::Write]);
+        let app = init_service(App::new().service(scope("/admin", store.clone(), tokens))).await;
+
+        let all: Value = call_and_read_body_json(&app, get("/admin/deliveries", READ_TOKEN)).await;
+        assert_eq!(all["deliveries"].as_array().unwrap().len(), 2);
+        assert_eq!(all["deliveries"][0]["id"], paid);
+        assert_eq!(all["next_before_id"], Value::Null);
+
+        let found: Value = call_and_read_body_json(&app, get("/admin/deliveries?payment_hash=0b5fc8ee&status=pending", READ_TOKEN)).await;
+        assert_eq!(found["deliveries"][0]["provider"], "lnbits");
+
+        let page: Value = call_and_read_body_json(&app, get("/admin/deliveries?limit=1", READ_TOKEN)).await;
+        assert_eq!(page["next_before_id"], paid);
+
+        let dead: Value = call_and_read_body_json(&app, get(&format!("/admin/deliveries/{settled}"), READ_TOKEN)).await;
+        assert_eq!(dead["status"], "dead");
+        assert_eq!(dead["last_error"], "missing posData");
+        assert_eq!(dead["payload"]["amount"], 2100);
+        assert_eq!(dead["headers"]["content-type"], "application/json");
+        assert_eq!(dead["attempt_log"][0]["outcome"], "dead");
+
+        let response = call_service(&app, get("/admin/deliveries?status=stuck", READ_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = call_service(&app, get("/admin/deliveries/99", READ_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Read tokens cannot reprocess
+        let response = call_service(&app, post(&format!("/admin/deliveries/{settled}/reprocess"), READ_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = call_service(&app, post(&format!("/admin/deliveries/{paid}/reprocess"), WRITE_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = call_service(&app, post(&format!("/admin/deliveries/{settled}/reprocess"), WRITE_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(store.inbox_status(settled).await.unwrap(), Some(InboxStatus::Pending));
+
+        store.inbox_finish(settled, InboxOutcome::Dead { error: "missing posData".to_string() }).await.unwrap();
+
+        let requeued: Value = call_and_read_body_json(&app, post("/admin/dead-letters/requeue?provider=btcpay", WRITE_TOKEN)).await;
+        assert_eq!(requeued["requeued"], json!([settled]));
+        assert_eq!(store.inbox_status(settled).await.unwrap(), Some(InboxStatus::Pending));
+
+        // Every match is requeued, not only the first page
+        for id in [settled, paid] {
+            store.inbox_finish(id, InboxOutcome::Dead { error: "unknown wallet".to_string() }).await.unwrap();
+        }
+        let requeued: Value = call_and_read_body_json(&app, post("/admin/dead-letters/requeue?limit=1", WRITE_TOKEN)).await;
+        assert_eq!(requeued["requeued"], json!([paid, settled]));
+        assert_eq!(store.inbox_status(paid).await.unwrap(), Some(InboxStatus::Pending));
+    }
+
+    #[actix_web::test]
+    async fn test_requires_token() {
+        let tokens = AdminTokens::new().with_token("ops", WRITE_TOKEN, &[AdminScope::Write]);
+        let app = init_service(App::new().service(scope("/admin", Arc::new(MemoryStore::new()), tokens))).await;
+
+        let response = call_service(&app, TestRequest::get().uri("/admin/deliveries").to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers().get(header::WWW_AUTHENTICATE).unwrap(), "Bearer realm=\"admin\"");
+
+        let response = call_service(&app, get("/admin/deliveries", "write-token")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // Write tokens can also read
+        let response = call_service(&app, get("/admin/deliveries", WRITE_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
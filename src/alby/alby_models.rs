@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// REF: https://guides.getalby.com/developer-guide/alby-wallet-api/reference/api-reference/webhooks
+
+pub const INVOICE_INCOMING_SETTLED: &str = "invoice.incoming.settled";
+pub const INVOICE_OUTGOING_SETTLED: &str = "invoice.outgoing.settled";
+
+/// The invoice Alby posts for `invoice.incoming.settled` and `invoice.outgoing.settled`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invoice {
+    pub identifier: Option<String>,
+    /// `incoming` or `outgoing`
+    #[serde(rename = "type")]
+    pub invoice_type: String,
+    /// eg. `SETTLED`
+    pub state: Option<String>,
+    #[serde(default)]
+    pub settled: bool,
+    /// Satoshis
+    pub amount: u64,
+    pub currency: Option<String>,
+    pub fiat_currency: Option<String>,
+    pub fiat_in_cents: Option<u64>,
+    pub memo: Option<String>,
+    pub comment: Option<String>,
+    pub payer_name: Option<String>,
+    pub payer_email: Option<String>,
+    pub payer_pubkey: Option<String>,
+    pub payment_hash: String,
+    pub payment_request: Option<String>,
+    pub preimage: Option<String>,
+    /// eg. `2023-05-02T17:49:15.000Z`
+    pub created_at: Option<String>,
+    pub settled_at: Option<String>,
+    /// Unix seconds
+    pub creation_date: Option<i64>,
+    #[serde(default)]
+    pub metadata: Value,
+    #[serde(default)]
+    pub custom_records: Value,
+    #[serde(default)]
+    pub boostagram: Value,
+}
+
+impl Invoice {
+    /// The subscribed event this invoice was posted for, eg. `invoice.incoming.settled`
+    pub fn event_type(&self) -> String {
+        let state = match &self.state {
+            _ if self.settled => "settled".to_string(),
+            Some(state) => state.to_lowercase(),
+            None => "unknown".to_string(),
+        };
+        format!("invoice.{}.{}", self.invoice_type, state)
+    }
+}
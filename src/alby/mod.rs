@@ -0,0 +1,216 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::event::{parse_timestamp, EventType, NormalizedEvent, Provider};
+use crate::metrics::VerifyFailure;
+use crate::verifier::{secret_from_env, svix::SvixSecret, Headers, WebhookVerifier};
+
+pub mod alby_models;
+
+pub use alby_models::Invoice;
+
+/// Checks Alby webhooks, which are delivered through Svix
+#[derive(Clone)]
+pub struct AlbyVerifier {
+    secret: SvixSecret,
+}
+
+impl AlbyVerifier {
+    /// From the endpoint secret Alby returns when the webhook is created, eg. `whsec_...`
+    pub fn new(secret: &str) -> Result<Self> {
+        Ok(AlbyVerifier { secret: SvixSecret::new(secret)? })
+    }
+
+    /// For a secret with a non-default timestamp tolerance
+    pub fn with_secret(secret: SvixSecret) -> Self {
+        AlbyVerifier { secret }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        AlbyVerifier::new(&secret_from_env("ALBY_WEBHOOK_SECRET")?)
+    }
+}
+
+impl WebhookVerifier for AlbyVerifier {
+    type Payload = Invoice;
+
+    fn provider(&self) -> &'static str {
+        "alby"
+    }
+
+    fn verify(&self, headers: &dyn Headers, body: &[u8]) -> Result<Invoice, VerifyFailure> {
+        self.secret.verify(headers, body)?;
+        serde_json::from_slice(body).map_err(|_| VerifyFailure::BadBody)
+    }
+
+    fn event_type(&self, invoice: &Invoice) -> String {
+        invoice.event_type()
+    }
+}
+
+impl Invoice {
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        if self.invoice_type != "incoming" || !self.settled {
+            return None;
+        }
+
+        let metadata = match &self.metadata {
+            Value::Object(metadata) if !metadata.is_empty() => self.metadata.clone(),
+            _ => match (&self.memo, &self.comment, &self.payer_name) {
+                (None, None, None) => Value::Null,
+                (memo, comment, payer_name) => json!({"memo": memo, "comment": comment, "payer_name": payer_name}),
+            },
+        };
+
+        Some(NormalizedEvent {
+            id: format!("{}:settled", self.payment_hash),
+            provider: Provider::Alby,
+            event_type: EventType::InvoiceSettled,
+            store_id: None,
+            invoice_id: self.identifier.clone(),
+            payment_hash: Some(self.payment_hash.clone()),
+            amount_msat: self.amount.checked_mul(1000),
+            timestamp: self.settled_at.as_deref().and_then(parse_timestamp).or(self.creation_date),
+            metadata,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifier::{self, svix, test_headers};
+
+    const SECRET: &str = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+
+    const INCOMING_SETTLED: &str = r#"{
+  "amount": 2100,
+  "boostagram": null,
+  "comment": "great article",
+  "created_at": "2023-05-02T17:48:01.000Z",
+  "creation_date": 1683049681,
+  "currency": "BTC",
+  "custom_records": {},
+  "description_hash": null,
+  "expires_at": "2023-05-03T17:48:01.000Z",
+  "expiry": 86400,
+  "fiat_currency": "USD",
+  "fiat_in_cents": 60,
+  "identifier": "Fu8AYU8kXeYbhPe5BrGBnFfQ",
+  "keysend_message": null,
+  "memo": "Article a1",
+  "metadata": {},
+  "payer_name": "satoshi",
+  "payer_email": null,
+  "payer_pubkey": null,
+  "payment_hash": "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2",
+  "payment_request": "lnbc21u1...",
+  "preimage": "a6f2c1b0",
+  "r_hash_str": "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2",
+  "settled": true,
+  "settled_at": "2023-05-02T17:49:15.000Z",
+  "state": "SETTLED",
+  "type": "incoming",
+  "value": 2100
+}"#;
+
+    fn signed_headers(body: &[u8], timestamp: i64) -> Vec<(String, String)> {
+        let signature = SvixSecret::new(SECRET).unwrap().sign("msg_2a4d1c", timestamp, body);
+        test_headers(&[
+            (svix::SVIX_ID_HEADER, "msg_2a4d1c"),
+            (svix::SVIX_TIMESTAMP_HEADER, &timestamp.to_string()),
+            (svix::SVIX_SIGNATURE_HEADER, &signature),
+        ])
+    }
+
+    fn now() -> i64 {
+        std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64
+    }
+
+    #[test]
+    fn test_alby_verifier() {
+        let verifier = AlbyVerifier::new(SECRET).unwrap();
+
+        let invoice = verifier::verify(&verifier, &signed_headers(INCOMING_SETTLED.as_bytes(), now()), INCOMING_SETTLED.as_bytes()).unwrap();
+        assert_eq!(invoice.event_type(), alby_models::INVOICE_INCOMING_SETTLED);
+
+        // A replay of an old delivery
+        let stale = signed_headers(INCOMING_SETTLED.as_bytes(), now() - 3600);
+        assert_eq!(verifier::verify(&verifier, &stale, INCOMING_SETTLED.as_bytes()).unwrap_err(), VerifyFailure::StaleTimestamp);
+        let lenient = AlbyVerifier::with_secret(SvixSecret::new(SECRET).unwrap().with_tolerance(std::time::Duration::from_secs(7200)));
+        assert!(verifier::verify(&lenient, &stale, INCOMING_SETTLED.as_bytes()).is_ok());
+
+        assert_eq!(verifier::verify(&verifier, &signed_headers(b"{}", now()), b"{}").unwrap_err(), VerifyFailure::BadBody);
+        assert_eq!(verifier::verify(&verifier, &Vec::new(), INCOMING_SETTLED.as_bytes()).unwrap_err(), VerifyFailure::MissingHeader);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let invoice: Invoice = serde_json::from_str(INCOMING_SETTLED).unwrap();
+        let event = invoice.normalize().unwrap();
+        assert_eq!(event.id, "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2:settled");
+        assert_eq!(event.event_type, EventType::InvoiceSettled);
+        assert_eq!(event.invoice_id.as_deref(), Some("Fu8AYU8kXeYbhPe5BrGBnFfQ"));
+        assert_eq!(event.amount_msat, Some(2_100_000));
+        assert_eq!(event.timestamp, Some(1683049755));
+        assert_eq!(event.metadata, json!({"memo": "Article a1", "comment": "great article", "payer_name": "satoshi"}));
+        assert_eq!(crate::event::normalize(Provider::Alby, INCOMING_SETTLED).unwrap(), Some(event));
+
+        let overflow = INCOMING_SETTLED.replace(r#""amount": 2100"#, &format!(r#""amount": {}"#, u64::MAX));
+        let invoice: Invoice = serde_json::from_str(&overflow).unwrap();
+        assert_eq!(invoice.normalize().unwrap().amount_msat, None);
+
+        let outgoing = INCOMING_SETTLED.replace(r#""type": "incoming""#, r#""type": "outgoing""#);
+        let invoice: Invoice = serde_json::from_str(&outgoing).unwrap();
+        assert_eq!(invoice.event_type(), alby_models::INVOICE_OUTGOING_SETTLED);
+        assert!(invoice.normalize().is_none());
+    }
+
+    // The same pattern as `BTCPayHeaderVerify` and the BTCPay extractors
+    #[cfg(feature = "actix")]
+    #[actix_web::test]
+    async fn test_verify_webhook_middleware() {
+        use crate::verifier::VerifyWebhook;
+        use actix_web::{test, web, App, HttpResponse};
+
+        let app = test::init_service(App::new()
+            .service(web::scope("/alby").wrap(VerifyWebhook::new(AlbyVerifier::new(SECRET).unwrap())).route("/webhook", web::post().to(
+                |invoice: web::ReqData<Invoice>| async move { HttpResponse::Ok().body(invoice.payment_hash.clone()) },
+            )))).await;
+
+        let mut req = test::TestRequest::post().uri("/alby/webhook").set_payload(INCOMING_SETTLED);
+        for header in signed_headers(INCOMING_SETTLED.as_bytes(), now()) {
+            req = req.insert_header(header);
+        }
+        assert_eq!(test::call_and_read_body(&app, req.to_request()).await, "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2");
+
+        let mut req = test::TestRequest::post().uri("/alby/webhook").set_payload(INCOMING_SETTLED);
+        for header in signed_headers(INCOMING_SETTLED.as_bytes(), now() - 3600) {
+            req = req.insert_header(header);
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert_eq!(test::read_body(resp).await, r#"{"type":"about:blank","title":"Unauthorized","status":401,"detail":"stale_timestamp"}"#);
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_verified_extractor() {
+        use crate::verifier::Verified;
+        use axum::{routing::post, Extension, Router};
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route("/alby/webhook", post(|Verified(invoice): Verified<AlbyVerifier>| async move { invoice.event_type() }))
+            .layer(Extension(AlbyVerifier::new(SECRET).unwrap()));
+
+        let mut request = http::Request::post("/alby/webhook");
+        for (name, value) in signed_headers(INCOMING_SETTLED.as_bytes(), now()) {
+            request = request.header(name, value);
+        }
+        let response = app.oneshot(request.body(axum::body::Body::from(INCOMING_SETTLED)).unwrap()).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, alby_models::INVOICE_INCOMING_SETTLED);
+    }
+}
@@ -0,0 +1,497 @@
+#[macro_use]
+extern crate log;
+use actix_web::{middleware::Logger, web, App, HttpServer};
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use lightning_rs_webhook::{admin, dashboard};
+use lightning_rs_webhook::btcpay::{btcpay_middleware::{self, BTCPAY_SIG_HEADER}, sign_payload, verify_signature};
+use lightning_rs_webhook::config::{Config, RecorderConfig};
+use lightning_rs_webhook::db::pg_pool_from_url;
+use lightning_rs_webhook::health::{BTCPayCheck, InboxBacklogCheck, LNbitsCheck, PostgresCheck, Readiness};
+use lightning_rs_webhook::inbox::{self, Inbox, InboxMessage, InboxStatus, WorkerConfig};
+use lightning_rs_webhook::lnbits::LNbitsConfirm;
+use lightning_rs_webhook::migrations;
+use lightning_rs_webhook::recorder::{read_jsonl, ReplaySignature, HOP_HEADERS};
+use lightning_rs_webhook::relay::{self, Relay};
+use lightning_rs_webhook::routes;
+use lightning_rs_webhook::store::{PostgresStore, WebhookStore};
+use serde_json::Value;
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+/// Serve, sign, verify and replay Lightning payment webhooks
+#[derive(Parser)]
+#[command(name = "lnwebhook", version)]
+struct Cli {
+    /// TOML config file, see config.example.toml. Defaults to CONFIG_FILE, then the environment only.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the webhook server. Webhooks are stored in the inbox and relayed to `[relay]` subscribers.
+    /// The admin API (and dashboard) is served too when `[admin]` is configured.
+    Serve,
+    /// Print the BTCPay-Sig header for a payload. The file is signed byte for byte, trailing newline included.
+    Sign {
+        /// Payload file, or - for stdin
+        payload: PathBuf,
+        #[command(flatten)]
+        secret: SecretArgs,
+    },
+    /// Check the signature of a captured request. Exits with 1 when it does not match.
+    Verify {
+        /// A raw HTTP request (headers and body) or just the body, or - for stdin
+        request: PathBuf,
+        /// Signature to check, eg. sha256=<hex>. Read from the request's BTCPay-Sig header when omitted.
+        #[arg(long)]
+        signature: Option<String>,
+        #[command(flatten)]
+        secret: SecretArgs,
+    },
+    /// POST a signed payload to a webhook URL, eg. a local server under development
+    Send {
+        url: String,
+        /// Payload file, or - for stdin
+        payload: PathBuf,
+        #[command(flatten)]
+        secret: SecretArgs,
+    },
+    /// Resend stored inbox messages - requeued for the workers, or POSTed to a URL with their original headers
+    Replay {
+        /// Messages to replay. Defaults to every message with --status.
+        #[arg(long = "id")]
+        ids: Vec<i64>,
+        #[arg(long, default_value = "dead", conflicts_with = "ids")]
+        status: InboxStatus,
+        #[arg(long, default_value_t = 100)]
+        limit: i64,
+        /// POST each body here instead of requeuing it
+        #[arg(long)]
+        to: Option<String>,
+        /// List the messages without replaying them
+        #[arg(long)]
+        dry_run: bool,
+        /// Requeue messages that are not dead-letters too, ie. done or in progress
+        #[arg(long)]
+        force: bool,
+    },
+    /// Send requests from a recorder JSONL file to a server, eg. http://localhost:4040
+    ReplayRecording {
+        recording: PathBuf,
+        #[arg(long)]
+        to: String,
+        /// Re-sign BTCPay-Sig with this secret, rather than sending the recorded signature
+        #[arg(long)]
+        resign: Option<String>,
+    },
+    /// Apply the crate's schema migrations
+    Migrate {
+        /// List pending migrations without applying them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct SecretArgs {
+    /// Webhook secret
+    #[arg(long)]
+    secret: Option<String>,
+    /// File containing the webhook secret
+    #[arg(long)]
+    secret_file: Option<PathBuf>,
+}
+
+impl SecretArgs {
+    fn resolve(&self) -> Result<String> {
+        match (&self.secret, &self.secret_file) {
+            (Some(secret), _) => Ok(secret.clone()),
+            (None, Some(path)) => Ok(std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read secret file {}", path.display()))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string()),
+            (None, None) => bail!("--secret or --secret-file is required"),
+        }
+    }
+}
+
+#[actix_web::main]
+async fn main() -> Result<ExitCode> {
+    let cli = Cli::parse();
+
+    #[cfg(feature = "telemetry")]
+    let _telemetry = lightning_rs_webhook::telemetry::init(lightning_rs_webhook::telemetry::TelemetryConfig::from_env())?;
+    #[cfg(not(feature = "telemetry"))]
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+
+    match cli.command {
+        Command::Serve => serve(load_config(&cli.config)?).await,
+        Command::Sign { payload, secret } => {
+            println!("{}", sign_payload(&read_input(&payload)?, &secret.resolve()?));
+            Ok(ExitCode::SUCCESS)
+        },
+        Command::Verify { request, signature, secret } => verify(&read_input(&request)?, signature, &secret.resolve()?),
+        Command::Send { url, payload, secret } => send(&url, &read_input(&payload)?, &secret.resolve()?).await,
+        Command::Replay { ids, status, limit, to, dry_run, force } => {
+            replay(&load_config(&cli.config)?, ids, status, limit, to, dry_run, force).await
+        },
+        Command::ReplayRecording { recording, to, resign } => replay_recording(&recording, &to, resign).await,
+        Command::Migrate { dry_run } => migrate(&load_config(&cli.config)?, dry_run).await,
+    }
+}
+
+fn load_config(path: &Option<PathBuf>) -> Result<Config> {
+    match path {
+        Some(path) => Config::load_from(path),
+        None => Config::load(),
+    }
+}
+
+// Reads a file, or stdin for `-`
+fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        return Ok(input);
+    }
+
+    std::fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+async fn serve(config: Config) -> Result<ExitCode> {
+    if config.btcpay.is_none() && config.lnbits.is_none() {
+        bail!("nothing to serve, configure btcpay and/or lnbits");
+    }
+
+    let pg_pool = pg_pool_from_url(config.postgres.url.expose())?;
+
+    if config.postgres.migrate {
+        for migration in migrations::migrate(&pg_pool).await? {
+            info!("Applied migration {} ({})", migration.version, migration.name);
+        }
+    }
+
+    // Every webhook is acknowledged once stored, then normalized and queued for the relay subscribers
+    let inbox = Inbox::new(pg_pool.clone());
+    let relay = Relay::new(pg_pool.clone(), config.relay.subscribers())?;
+    inbox::spawn_workers(inbox.clone(), Arc::new(relay.clone()), WorkerConfig::default());
+    relay::spawn_workers(relay, WorkerConfig::default());
+
+    let mut readiness = Readiness::new()
+        .with_check(PostgresCheck::new(pg_pool.clone()))
+        .with_check(InboxBacklogCheck::new(inbox.clone(), 1000));
+    if let Some(btcpay) = &config.btcpay {
+        readiness = readiness.with_check(BTCPayCheck::new(btcpay.client()));
+    }
+    if let Some(lnbits) = &config.lnbits {
+        for wallet in &lnbits.wallets {
+            readiness = readiness.with_check(
+                LNbitsCheck::new(&lnbits.host, wallet.api_key.expose()).with_name(&format!("lnbits:{}", wallet.id))
+            );
+        }
+    }
+    let readiness = web::Data::new(readiness);
+
+    let webhook_secrets = config.btcpay.as_ref().map(|btcpay| web::Data::new(btcpay.webhook_secrets()));
+    // LNbits webhooks are unsigned, so each payment is confirmed with the wallets before it is stored
+    let lnbits_confirm = config.lnbits.as_ref().map(|lnbits| LNbitsConfirm::new(lnbits.client()));
+    let recorder = config.recorder.as_ref().map(RecorderConfig::recorder).transpose()?;
+    // Built once, so every worker shares each route's rate limit buckets
+    let btcpay_firewall = config.firewall.firewall();
+    let lnbits_firewall = config.firewall.firewall();
+    let admin_config = config.admin.clone();
+    let admin_store: Arc<dyn WebhookStore> = Arc::new(PostgresStore::new(pg_pool.clone()));
+
+    let (host, port) = config.bind_address();
+    info!("Listening on {host}:{port}");
+
+    HttpServer::new(move || {
+        let app = App::new()
+            .wrap(Logger::default())
+            .app_data(web::Data::new(inbox.clone()))
+            .app_data(readiness.clone())
+            .service(routes::health_handler)
+            .service(routes::health_live_handler)
+            .service(routes::health_ready_handler);
+
+        #[cfg(feature = "metrics")]
+        let app = app.service(routes::metrics_handler);
+
+        let app = match &admin_config {
+            Some(admin_config) => {
+                let app = app.service(admin::scope(&admin_config.path, admin_store.clone(), admin_config.tokens()));
+                match &admin_config.dashboard_path {
+                    Some(path) => app.service(dashboard::scope(path, admin_store.clone(), admin_config.tokens())),
+                    None => app,
+                }
+            },
+            None => app,
+        };
+
+        // Recorded before verification, so rejected requests can be looked at too. The firewall
+        // comes first of all, turning away unwanted sources before anything reads the body.
+        let app = match &webhook_secrets {
+            Some(webhook_secrets) => {
+                let scope = web::scope("/btcpay")
+                    .wrap(btcpay_middleware::BTCPayHeaderVerify)
+                    .route("/webhook", inbox::route("btcpay"));
+                let app = app.app_data(webhook_secrets.clone());
+                match &recorder {
+                    Some(recorder) => app.service(scope.wrap(recorder.clone()).wrap(btcpay_firewall.clone())),
+                    None => app.service(scope.wrap(btcpay_firewall.clone())),
+                }
+            },
+            None => app,
+        };
+
+        match &lnbits_confirm {
+            Some(lnbits_confirm) => {
+                let scope = web::scope("/lnbits")
+                    .wrap(lnbits_confirm.clone())
+                    .route("/webhook", inbox::route("lnbits"));
+                match &recorder {
+                    Some(recorder) => app.service(scope.wrap(recorder.clone()).wrap(lnbits_firewall.clone())),
+                    None => app.service(scope.wrap(lnbits_firewall.clone())),
+                }
+            },
+            None => app,
+        }
+    })
+    .bind((host, port))?
+    .run()
+    .await?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify(input: &str, signature: Option<String>, secret: &str) -> Result<ExitCode> {
+    let captured = parse_captured(input);
+
+    let signature = match signature.or(captured.signature) {
+        Some(signature) => signature,
+        None => bail!("no {BTCPAY_SIG_HEADER} header in the request, pass --signature"),
+    };
+
+    if verify_signature(captured.body, secret, &signature) {
+        println!("valid");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("invalid, expected {}", sign_payload(captured.body, secret));
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+struct Captured<'a> {
+    signature: Option<String>,
+    body: &'a str,
+}
+
+// Splits a raw HTTP request (eg. from `nc -l` or a proxy log) into its signature header and body.
+// Anything not starting with a request line is taken to be the body.
+fn parse_captured(input: &str) -> Captured<'_> {
+    let request_line = input.lines().next().unwrap_or_default();
+    let is_request = request_line.split_whitespace().nth(2).is_some_and(|version| version.starts_with("HTTP/"));
+
+    if !is_request {
+        return Captured { signature: None, body: input };
+    }
+
+    let (head, body) = match (input.find("\r\n\r\n"), input.find("\n\n")) {
+        (Some(crlf), Some(lf)) if lf < crlf => (&input[..lf], &input[lf + 2..]),
+        (Some(crlf), _) => (&input[..crlf], &input[crlf + 4..]),
+        (None, Some(lf)) => (&input[..lf], &input[lf + 2..]),
+        (None, None) => (input, ""),
+    };
+
+    let signature = head.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim().eq_ignore_ascii_case(BTCPAY_SIG_HEADER).then(|| value.trim().to_string())
+    });
+
+    Captured { signature, body }
+}
+
+async fn send(url: &str, payload: &str, secret: &str) -> Result<ExitCode> {
+    let response = reqwest::Client::new()
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(BTCPAY_SIG_HEADER, sign_payload(payload, secret))
+        .body(payload.to_string())
+        .send()
+        .await?;
+
+    let status = response.status();
+    println!("{status}");
+
+    let body = response.text().await?;
+    if !body.is_empty() {
+        println!("{body}");
+    }
+
+    Ok(if status.is_success() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+async fn replay(config: &Config, ids: Vec<i64>, status: InboxStatus, limit: i64, to: Option<String>, dry_run: bool, force: bool) -> Result<ExitCode> {
+    let store = PostgresStore::new(pg_pool_from_url(config.postgres.url.expose())?);
+
+    let messages = match ids.is_empty() {
+        true => store.inbox_list(status, limit).await?.into_iter().map(|message| (message, status)).collect(),
+        false => {
+            let mut messages = Vec::new();
+            for id in ids {
+                let record = store.inbox_record(id).await?.with_context(|| format!("no inbox message {id}"))?;
+                messages.push((record.message, record.status));
+            }
+            messages
+        },
+    };
+
+    let client = reqwest::Client::new();
+    let mut failed = 0;
+
+    for (message, status) in &messages {
+        let event_type = message.event_type.as_deref().unwrap_or("-");
+        println!("{} {} {} attempts={}", message.id, message.provider, event_type, message.attempts);
+
+        if dry_run {
+            continue;
+        }
+
+        match &to {
+            Some(url) => match resend(&client, url, message).await {
+                Ok(status) if status.is_success() => println!("  {status}"),
+                Ok(status) => {
+                    println!("  {status}");
+                    failed += 1;
+                },
+                Err(err) => {
+                    println!("  error: {err}");
+                    failed += 1;
+                },
+            },
+            // Requeuing a done message would process it again, and a pending one may be leased by a worker
+            None if *status != InboxStatus::Dead && !force => {
+                println!("  skipped, {} (requeue with --force)", status.as_str());
+                failed += 1;
+            },
+            None => {
+                store.inbox_requeue(message.id).await?;
+                println!("  requeued");
+            },
+        }
+    }
+
+    println!("{} messages, {failed} failed", messages.len());
+
+    Ok(if failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+// POST a stored body with the headers it arrived with, so signatures still verify
+async fn resend(client: &reqwest::Client, url: &str, message: &InboxMessage) -> Result<reqwest::StatusCode> {
+    let mut request = client.post(url).body(message.body.clone());
+
+    if let Value::Object(headers) = &message.headers {
+        for (name, value) in headers {
+            if HOP_HEADERS.contains(&name.as_str()) {
+                continue;
+            }
+            if let Some(value) = value.as_str() {
+                request = request.header(name, value);
+            }
+        }
+    }
+
+    Ok(request.send().await?.status())
+}
+
+async fn replay_recording(path: &Path, base_url: &str, resign: Option<String>) -> Result<ExitCode> {
+    let signature = match resign {
+        Some(secret) => ReplaySignature::Resign(secret),
+        None => ReplaySignature::Original,
+    };
+
+    let client = reqwest::Client::new();
+    let recordings = read_jsonl(path)?;
+    let mut failed = 0;
+
+    for recorded in &recordings {
+        match recorded.replay_to(&client, base_url, &signature).await {
+            Ok(status) => {
+                println!("{} {} {status}", recorded.method, recorded.path);
+                if !status.is_success() {
+                    failed += 1;
+                }
+            },
+            Err(err) => {
+                println!("{} {} error: {err}", recorded.method, recorded.path);
+                failed += 1;
+            },
+        }
+    }
+
+    println!("{} requests, {failed} failed", recordings.len());
+
+    Ok(if failed == 0 { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+async fn migrate(config: &Config, dry_run: bool) -> Result<ExitCode> {
+    let pg_pool = pg_pool_from_url(config.postgres.url.expose())?;
+
+    let migrations = match dry_run {
+        true => migrations::pending(&pg_pool).await?,
+        false => migrations::migrate(&pg_pool).await?,
+    };
+
+    let action = if dry_run { "Pending" } else { "Applied" };
+    for migration in &migrations {
+        println!("{action} migration {} ({})", migration.version, migration.name);
+    }
+    if migrations.is_empty() {
+        println!("Schema is up to date");
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_parse_captured_request() {
+        let raw = "POST /btcpay/webhook HTTP/1.1\r\nHost: localhost\r\nbtcpay-sig: sha256=abc\r\n\r\n{\"type\":\"InvoiceSettled\"}";
+        let captured = parse_captured(raw);
+        assert_eq!(captured.signature.as_deref(), Some("sha256=abc"));
+        assert_eq!(captured.body, "{\"type\":\"InvoiceSettled\"}");
+
+        let captured = parse_captured("{\"type\":\"InvoiceSettled\"}\n");
+        assert_eq!(captured.signature, None);
+        assert_eq!(captured.body, "{\"type\":\"InvoiceSettled\"}\n");
+    }
+
+    #[test]
+    fn test_verify_round_trip() {
+        let body = r#"{"type":"InvoiceSettled"}"#;
+        let raw = format!("POST /btcpay/webhook HTTP/1.1\nBTCPay-Sig: {}\n\n{body}", sign_payload(body, "secret"));
+
+        assert_eq!(verify(&raw, None, "secret").unwrap(), ExitCode::SUCCESS);
+        assert_eq!(verify(&raw, None, "other").unwrap(), ExitCode::FAILURE);
+        assert!(verify(body, None, "secret").is_err());
+    }
+
+    #[test]
+    fn test_cli_args() {
+        Cli::command().debug_assert();
+    }
+}
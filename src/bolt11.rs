@@ -0,0 +1,217 @@
+use anyhow::{anyhow, bail, Result};
+use hex::encode;
+
+// Minimal BOLT11 decoding - enough to read the amount, payment hash and description (hash).
+// The invoice signature is not checked, as invoices arriving here came from our own node.
+// REF: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+const SIGNATURE_LEN: usize = 104;
+const TIMESTAMP_LEN: usize = 7;
+
+const TAG_PAYMENT_HASH: u8 = 1;
+const TAG_DESCRIPTION: u8 = 13;
+const TAG_DESCRIPTION_HASH: u8 = 23;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invoice {
+    /// Currency prefix, eg. `bc` for mainnet or `tb` for testnet
+    pub currency: String,
+    pub amount_msat: Option<u64>,
+    pub timestamp: u64,
+    /// Hex encoded payment hash
+    pub payment_hash: Option<String>,
+    pub description: Option<String>,
+    /// Hex encoded SHA256 of the full description, used instead of `description` for long descriptions
+    pub description_hash: Option<String>,
+}
+
+pub fn decode(invoice: &str) -> Result<Invoice> {
+    let (hrp, data) = bech32_decode(invoice)?;
+
+    let hrp = hrp.strip_prefix("ln").ok_or(anyhow!("invoice prefix is not ln"))?;
+    let amount_start = hrp.find(|c: char| c.is_ascii_digit()).unwrap_or(hrp.len());
+    let currency = hrp[..amount_start].to_string();
+    let amount_msat = parse_amount(&hrp[amount_start..])?;
+
+    if data.len() < TIMESTAMP_LEN + SIGNATURE_LEN {
+        bail!("invoice is too short");
+    }
+
+    let timestamp = data[..TIMESTAMP_LEN].iter().fold(0u64, |acc, v| acc << 5 | *v as u64);
+
+    let mut decoded = Invoice {
+        currency,
+        amount_msat,
+        timestamp,
+        payment_hash: None,
+        description: None,
+        description_hash: None,
+    };
+
+    let mut fields = &data[TIMESTAMP_LEN..data.len() - SIGNATURE_LEN];
+    while !fields.is_empty() {
+        if fields.len() < 3 {
+            bail!("invoice has a truncated tagged field");
+        }
+
+        let tag = fields[0];
+        let len = (fields[1] as usize) << 5 | fields[2] as usize;
+        let value = fields.get(3..3 + len).ok_or(anyhow!("invoice has a truncated tagged field"))?;
+
+        match tag {
+            // Readers must skip payment hash and description hash fields that are the wrong length
+            TAG_PAYMENT_HASH if len == 52 => decoded.payment_hash = Some(encode(to_bytes(value))),
+            TAG_DESCRIPTION_HASH if len == 52 => decoded.description_hash = Some(encode(to_bytes(value))),
+            TAG_DESCRIPTION => decoded.description = Some(String::from_utf8(to_bytes(value))?),
+            _ => {},
+        }
+
+        fields = &fields[3 + len..];
+    }
+
+    Ok(decoded)
+}
+
+fn parse_amount(amount: &str) -> Result<Option<u64>> {
+    if amount.is_empty() {
+        return Ok(None);
+    }
+
+    let (digits, multiplier) = match amount.chars().last() {
+        Some(c) if c.is_ascii_digit() => (amount, None),
+        Some(c) => (&amount[..amount.len() - 1], Some(c)),
+        None => unreachable!(),
+    };
+
+    let value: u64 = digits.parse().map_err(|_| anyhow!("invalid invoice amount: {amount}"))?;
+
+    // 1 BTC = 100,000,000,000 msat
+    let msat = match multiplier {
+        None => value.checked_mul(100_000_000_000),
+        Some('m') => value.checked_mul(100_000_000),
+        Some('u') => value.checked_mul(100_000),
+        Some('n') => value.checked_mul(100),
+        Some('p') if value.is_multiple_of(10) => Some(value / 10),
+        _ => None,
+    };
+
+    msat.map(Some).ok_or(anyhow!("invalid invoice amount: {amount}"))
+}
+
+// Convert 5 bit groups to bytes, dropping any trailing padding
+fn to_bytes(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len() * 5 / 8);
+    let mut acc: u32 = 0;
+    let mut bits = 0;
+
+    for value in data {
+        acc = (acc << 5 | *value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((acc >> bits) as u8);
+        }
+    }
+
+    bytes
+}
+
+fn polymod(values: impl Iterator<Item = u8>) -> u32 {
+    let mut chk: u32 = 1;
+    for value in values {
+        let top = chk >> 25;
+        chk = (chk & 0x1ffffff) << 5 ^ value as u32;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    hrp.bytes().map(|b| b >> 5)
+        .chain(std::iter::once(0))
+        .chain(hrp.bytes().map(|b| b & 31))
+        .collect()
+}
+
+// BOLT11 uses bech32 without the 90 character length limit
+fn bech32_decode(s: &str) -> Result<(String, Vec<u8>)> {
+    if s.chars().any(|c| c.is_ascii_lowercase()) && s.chars().any(|c| c.is_ascii_uppercase()) {
+        bail!("invoice has mixed case");
+    }
+    let s = s.to_ascii_lowercase();
+
+    let separator = s.rfind('1').ok_or(anyhow!("invoice has no separator"))?;
+    let (hrp, data) = (&s[..separator], &s[separator + 1..]);
+
+    if hrp.is_empty() || data.len() < 6 {
+        bail!("invoice is too short");
+    }
+
+    let data = data.bytes()
+        .map(|c| CHARSET.iter().position(|x| *x == c).map(|v| v as u8))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or(anyhow!("invoice has an invalid character"))?;
+
+    if polymod(hrp_expand(hrp).into_iter().chain(data.iter().copied())) != 1 {
+        bail!("invoice checksum is invalid");
+    }
+
+    Ok((hrp.to_string(), data[..data.len() - 6].to_vec()))
+}
+
+// Builds invoices for the zap tests
+#[cfg(all(test, feature = "nostr", feature = "actix"))]
+pub(crate) fn bech32_encode(hrp: &str, data: &[u8]) -> String {
+    let checksum = polymod(hrp_expand(hrp).into_iter().chain(data.iter().copied()).chain([0u8; 6])) ^ 1;
+
+    let mut s = format!("{hrp}1");
+    for value in data.iter().copied().chain((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8)) {
+        s.push(CHARSET[value as usize] as char);
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // From the LNbits example payload
+    const INVOICE: &str = "lnbc100n1pjyl0qfsp533xd0y5zfakhm0ytyauhlgd0jvh0c03ucw7c7eeynaljgzh6yz9spp5t4lw8tmkuft80gpwesfjurjj064cllyjkzhj4ew0z64wwurwew3qdq5w3jhxapqwajky6r0da4sxqzjccqpjrzjqwz34f2ec60uwx0cfhmvfq9lw4j52ct98jr4p5nqwqluynewq7qkszl3wgqq9jqqqqqqqqqqqqqqqqcqjq9qyysgq70qtyljrcp64m7q8lfzezxp2zfasun9flx7mg6aej262gqxsrw94uj0w2y5664ymkapuwrv0gmdzctrjfx0j3xu9qjyeeze5yw0jkhcpqwasks";
+
+    #[test]
+    fn test_decode_lnbits_invoice() {
+        let invoice = decode(INVOICE).unwrap();
+
+        assert_eq!(invoice.currency, "bc");
+        assert_eq!(invoice.amount_msat, Some(10_000));
+        assert_eq!(invoice.timestamp, 1682947081);
+        assert_eq!(invoice.payment_hash.as_deref(), Some("5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2"));
+        assert_eq!(invoice.description.as_deref(), Some("test webhook"));
+        assert_eq!(invoice.description_hash, None);
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_checksum() {
+        let mut invoice = INVOICE.to_string();
+        invoice.replace_range(invoice.len() - 1.., "q");
+        assert!(decode(&invoice).is_err());
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("").unwrap(), None);
+        assert_eq!(parse_amount("1").unwrap(), Some(100_000_000_000));
+        assert_eq!(parse_amount("2500u").unwrap(), Some(250_000_000));
+        assert_eq!(parse_amount("210n").unwrap(), Some(21_000));
+        assert_eq!(parse_amount("10p").unwrap(), Some(1));
+        assert!(parse_amount("11p").is_err());
+        assert!(parse_amount("10x").is_err());
+    }
+}
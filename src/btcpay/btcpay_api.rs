@@ -0,0 +1,102 @@
+use anyhow::{anyhow, bail, Result};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::time::Instant;
+
+use crate::btcpay::InvoiceData;
+use crate::{metrics, telemetry};
+
+/// Client for the BTCPay Greenfield API
+#[derive(Debug, Clone)]
+pub struct BTCPayClient {
+    host: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct HealthResponse {
+    synchronized: bool,
+}
+
+impl BTCPayClient {
+    pub fn new(host: &str, api_key: &str) -> Self {
+        BTCPayClient {
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Build a client from the `BTCPAY_HOST` and `BTCPAY_API_KEY` environment variables
+    pub fn from_env() -> Result<Self> {
+        let host = std::env::var("BTCPAY_HOST").map_err(|_| anyhow!("BTCPAY_HOST must be set"))?;
+        let api_key = std::env::var("BTCPAY_API_KEY").map_err(|_| anyhow!("BTCPAY_API_KEY must be set"))?;
+        Ok(BTCPayClient::new(&host, &api_key))
+    }
+
+    /// Whether the server's nodes are fully synchronized (`GET /api/v1/health`)
+    #[tracing::instrument(name = "btcpay.health", skip_all)]
+    pub async fn health(&self) -> Result<bool> {
+        let request = self.client.get(format!("{}/api/v1/health", self.host));
+
+        let response = send("health", request)
+            .await?
+            .error_for_status()?
+            .json::<HealthResponse>()
+            .await?;
+
+        Ok(response.synchronized)
+    }
+
+    /// Check the API key is still accepted, eg. that it has not been revoked
+    #[tracing::instrument(name = "btcpay.validate_api_key", skip_all)]
+    pub async fn validate_api_key(&self) -> Result<()> {
+        let request = self.client
+            .get(format!("{}/api/v1/api-keys/current", self.host))
+            .header(reqwest::header::AUTHORIZATION, format!("token {}", self.api_key));
+
+        let response = send("api_key", request).await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => bail!("API key was rejected"),
+            status => bail!("API key check failed with {status}"),
+        }
+    }
+
+    #[tracing::instrument(name = "btcpay.get_invoice_data", skip(self))]
+    pub async fn get_invoice_data(&self, store_id: &str, invoice_id: &str) -> Result<InvoiceData> {
+
+        // Build the URL for the API endpoint
+        let url = format!("{}/api/v1/stores/{store_id}/invoices/{invoice_id}", self.host);
+
+        // Send the GET request with the API key in the headers and deserialize the response JSON into an InvoiceData struct
+        let request = self.client
+            .get(&url)
+            .header(reqwest::header::AUTHORIZATION, format!("token {}", self.api_key));
+
+        // Error bodies (eg. invoice-not-found) would otherwise deserialize as an empty invoice
+        let response = send("invoice", request)
+            .await?
+            .error_for_status()?
+            .json::<InvoiceData>()
+            .await?;
+
+        Ok(response)
+    }
+}
+
+// Send a request with the current trace context, recording its latency by endpoint and status
+async fn send(endpoint: &str, request: RequestBuilder) -> Result<Response> {
+    let started = Instant::now();
+    let result = telemetry::inject_trace_context(request).send().await;
+
+    let status = match &result {
+        Ok(response) => response.status().as_u16().to_string(),
+        Err(_) => "error".to_string(),
+    };
+    metrics::btcpay_api_call(endpoint, &status, started.elapsed());
+
+    Ok(result?)
+}
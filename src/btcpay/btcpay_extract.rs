@@ -0,0 +1,65 @@
+// The axum adapter for `webhook::Verifier`
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{FromRequest, Request},
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+
+use crate::btcpay::WebhookPayload;
+use crate::error::{handler_response, HandlerError};
+use crate::webhook::{Rejection, Verifier, Webhook, WebhookSecrets, BTCPAY_SIG_HEADER, MAX_BODY_BYTES};
+
+/// Verifies the request, unless `BTCPayVerifyLayer` already did. Secrets come from a `WebhookSecrets`
+/// extension (eg. `.layer(Extension(secrets))`), or else `BTCPAY_WEBHOOK_SECRET`.
+impl<S: Send + Sync> FromRequest<S> for Webhook {
+    type Rejection = Rejection;
+
+    async fn from_request(request: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(webhook) = request.extensions().get::<Webhook>() {
+            return Ok(webhook.clone());
+        }
+
+        let verifier = match request.extensions().get::<WebhookSecrets>() {
+            Some(secrets) => Verifier::new(secrets.clone()),
+            None => Verifier::from_env(),
+        };
+
+        let signature = request.headers().get(BTCPAY_SIG_HEADER).map(|value| value.as_bytes().to_vec());
+
+        // Only read the body when there is a signature to check it against
+        let body = match signature {
+            Some(_) => to_bytes(request.into_body(), MAX_BODY_BYTES).await.ok(),
+            None => None,
+        };
+
+        verifier.verify(signature.as_deref(), body)
+    }
+}
+
+/// A verified webhook's parsed body, the axum counterpart of `BTCPayHeaderVerify` with `web::Json<WebhookPayload>`
+#[derive(Debug)]
+pub struct BTCPayWebhook<T = WebhookPayload>(pub T);
+
+impl<S: Send + Sync, T: DeserializeOwned> FromRequest<S> for BTCPayWebhook<T> {
+    type Rejection = Rejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let webhook = Webhook::from_request(request, state).await?;
+        webhook.payload().map(BTCPayWebhook)
+    }
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        Rejection::into_response(self).map(Body::from)
+    }
+}
+
+/// Answers like the actix routes, so handlers can return `Result<(), HandlerError>`
+impl IntoResponse for HandlerError {
+    fn into_response(self) -> Response {
+        handler_response(&Err(self)).map(Body::from)
+    }
+}
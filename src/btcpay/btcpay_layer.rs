@@ -0,0 +1,100 @@
+// The tower adapter for `webhook::Verifier`, for tower based servers like axum whose request body can
+// be rebuilt from `Bytes`
+
+use bytes::Bytes;
+use http::{Request, Response};
+use http_body::Body;
+use http_body_util::{BodyExt, Limited};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+use tracing::Instrument;
+
+use crate::webhook::{self, Verifier, WebhookSecrets, BTCPAY_SIG_HEADER, MAX_BODY_BYTES};
+
+/// Checks the `BTCPay-Sig` header before the inner service, like `BTCPayHeaderVerify`. Failures are
+/// answered with the same problem responses, and verified requests get the `webhook::Webhook` in
+/// their extensions.
+///
+/// The buffered body is handed on as `B::from(Bytes)`, so the request body type has to implement
+/// `From<Bytes>`, as axum's `Body` and `http_body_util::Full<Bytes>` do. Hyper's `Incoming` does not,
+/// map it with `axum::body::Body::new` or similar before the layer.
+#[derive(Debug, Clone, Default)]
+pub struct BTCPayVerifyLayer {
+    verifier: Verifier,
+}
+
+impl BTCPayVerifyLayer {
+    pub fn new(secrets: impl Into<Arc<WebhookSecrets>>) -> Self {
+        BTCPayVerifyLayer { verifier: Verifier::new(secrets) }
+    }
+
+    /// Use `BTCPAY_WEBHOOK_SECRET` for every store
+    pub fn from_env() -> Self {
+        BTCPayVerifyLayer::default()
+    }
+}
+
+impl<S> Layer<S> for BTCPayVerifyLayer {
+    type Service = BTCPayVerify<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BTCPayVerify { inner, verifier: self.verifier.clone() }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BTCPayVerify<S> {
+    inner: S,
+    verifier: Verifier,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for BTCPayVerify<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    B: Body + From<Bytes> + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    ResBody: From<Bytes>,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The clone may not be ready, so call the one that was polled
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let verifier = self.verifier.clone();
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            let signature = parts.headers.get(BTCPAY_SIG_HEADER).map(|value| value.as_bytes().to_vec());
+
+            // Only read the body when there is a signature to check it against
+            let body = match signature {
+                Some(_) => Limited::new(body, MAX_BODY_BYTES).collect().await.ok().map(|body| body.to_bytes()),
+                None => None,
+            };
+
+            match verifier.verify(signature.as_deref(), body) {
+                Ok(verified) => {
+                    let body = B::from(verified.body.clone());
+                    parts.extensions.insert(verified);
+                    inner.call(Request::from_parts(parts, body)).await
+                },
+                Err(rejection) => Ok(rejection.into_response().map(ResBody::from)),
+            }
+        }.instrument(webhook::span()))
+    }
+}
@@ -1,30 +1,25 @@
+// The actix adapter for `webhook::Verifier`
+
 use anyhow::Result;
-use actix_http::h1;
 use actix_web::{
     dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
-    web, Error, HttpResponse, body::EitherBody,
+    web, Error, HttpMessage, body::EitherBody,
 };
-use crate::btcpay::verify_signature;
+use crate::error::problem_response;
+use crate::inbox;
+use crate::webhook::{self, Rejection, Verifier};
+pub use crate::recorder::bytes_to_payload;
+pub use crate::webhook::{WebhookSecrets, BTCPAY_SIG_HEADER};
 use futures_util::future::LocalBoxFuture;
-use lazy_static::lazy_static;
 use std::{
     future::{ready, Ready},
     rc::Rc,
 };
+use tracing::Instrument;
 
 
-lazy_static! {
-    static ref BTCPAY_WEBHOOK_SECRET: String = std::env::var("BTCPAY_WEBHOOK_SECRET").expect("BTCPAY_WEBHOOK_SECRET must be set");
-}
-
-pub const BTCPAY_SIG_HEADER: &str = "BTCPay-Sig";
-
-pub fn bytes_to_payload(buf: web::Bytes) -> dev::Payload {
-    let (_, mut pl) = h1::Payload::create(true);
-    pl.unread_data(buf);
-    dev::Payload::from(pl)
-}
-
+/// Checks the `BTCPay-Sig` header with the `web::Data<WebhookSecrets>` registered on the app, or else
+/// `BTCPAY_WEBHOOK_SECRET`. Verified requests also get the `webhook::Webhook` in their extensions.
 pub struct BTCPayHeaderVerify;
 
 impl<S: 'static, B> Transform<S, ServiceRequest> for BTCPayHeaderVerify
@@ -68,53 +63,45 @@ where
     fn call(&self, mut req: ServiceRequest) -> Self::Future {
         let svc = self.service.clone();
 
-        Box::pin(async move {
+        let verifier = match req.app_data::<web::Data<WebhookSecrets>>() {
+            Some(secrets) => Verifier::new(secrets.clone().into_inner()),
+            None => Verifier::from_env(),
+        };
 
-            let headers = req.headers().clone();
-
-            let btcpay_sig_header = if let Some(header_value) = headers.get(BTCPAY_SIG_HEADER) {
-                match header_value.to_str() {
-                    Ok(value) => value,
-                    // Invalid header value
-                    Err(_) => return Ok(return_unauthorized(req)),
-                }
-            } else {
-                // Missing header
-                return Ok(return_unauthorized(req));
-            };
+        Box::pin(async move {
 
-            debug!("Header: {BTCPAY_SIG_HEADER}: {btcpay_sig_header}");
+            let signature = req.headers().get(BTCPAY_SIG_HEADER).map(|value| value.as_bytes().to_vec());
 
-            // Borrow request body bytes
-            let body_bytes = match req.extract::<web::Bytes>().await {
-                Ok(body_bytes) => body_bytes,
-                Err(_) => return Ok(return_unauthorized(req)),
+            // Only read the body when there is a signature to check it against
+            let body = match signature {
+                Some(_) => req.extract::<web::Bytes>().await.ok(),
+                None => None,
             };
 
             // Re-insert body bytes back into request
-            req.set_payload(bytes_to_payload(body_bytes.clone()));
-
-            // Convery body from bytes to utf8 string
-            let body_str = match std::str::from_utf8(&body_bytes) {
-                Ok(body_str) => body_str,
-                Err(_) => return Ok(return_unauthorized(req)),
-            };
-
-            debug!("Body: {body_str}");
-
-            if let false = verify_signature(body_str, &BTCPAY_WEBHOOK_SECRET, &btcpay_sig_header) {
-                error!("Bad signature. Check webhook secret, or unauthorised request.");
-                return Ok(return_unauthorized(req))
+            if let Some(body) = &body {
+                req.set_payload(bytes_to_payload(body.clone()));
             }
 
-            let res = svc.call(req).await?;
-            Ok(res.map_into_left_body())
-        })
+            match verifier.verify(signature.as_deref(), body) {
+                Ok(verified) => {
+                    req.extensions_mut().insert(verified);
+                    req.extensions_mut().insert(inbox::Verified);
+                    let res = svc.call(req).await?;
+                    Ok(res.map_into_left_body())
+                },
+                Err(rejection) => Ok(reject(req, rejection)),
+            }
+        }.instrument(webhook::span()))
     }
 }
 
-fn return_unauthorized<L>(req: ServiceRequest) -> ServiceResponse<EitherBody<L>> {
+fn reject<L>(req: ServiceRequest, rejection: Rejection) -> ServiceResponse<EitherBody<L>> {
+    if let Some(reason) = rejection.reason() {
+        inbox::count_verification_failure(req.request(), "btcpay", reason);
+    }
+
     let (request, _pl) = req.into_parts();
-    let response = HttpResponse::Unauthorized().finish().map_into_right_body::<L>();
+    let response = problem_response(rejection.status(), Some(rejection.detail())).map_into_right_body::<L>();
     ServiceResponse::new(request, response)
 }
@@ -1,7 +1,8 @@
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 
-// We needed to add the metadata property here as it was missing from the btcpay-client crate
+// Webhook payloads, following the btcpay-client crate's generated models. We needed to add the
+// metadata property as it was missing there.
 // REF: https://github.com/AE9999/btcpay-rust
 #[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
 pub struct WebhookInvoiceSettledEvent {
@@ -108,3 +109,192 @@ pub struct WebhookInvoiceCreatedEvent {
     #[serde(rename = "metadata", skip_serializing_if = "Option::is_none")]
     pub metadata: Option<Box<InvoiceMetadata>>,
 }
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct WebhookInvoiceReceivedPaymentEvent {
+    /// The delivery id of the webhook
+    #[serde(rename = "deliveryId", skip_serializing_if = "Option::is_none")]
+    pub delivery_id: Option<String>,
+    /// The id of the webhook
+    #[serde(rename = "webhookId", skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<String>,
+    /// If this delivery is a redelivery, the is the delivery id of the original delivery.
+    #[serde(rename = "originalDeliveryId", skip_serializing_if = "Option::is_none")]
+    pub original_delivery_id: Option<String>,
+    /// True if this delivery is a redelivery
+    #[serde(rename = "isRedelivery", skip_serializing_if = "Option::is_none")]
+    pub is_redelivery: Option<bool>,
+    /// The type of this event, which is always "InvoiceReceivedPayment" for this struct
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub _type: Option<String>,
+    /// The timestamp when this delivery has been created
+    #[serde(rename = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f32>,
+    /// The store id of the invoice's event
+    #[serde(rename = "storeId", skip_serializing_if = "Option::is_none")]
+    pub store_id: Option<String>,
+    /// The invoice id of the invoice's event
+    #[serde(rename = "invoiceId", skip_serializing_if = "Option::is_none")]
+    pub invoice_id: Option<String>,
+    /// Whether this payment has been sent after expiration of the invoice
+    #[serde(rename = "afterExpiration", skip_serializing_if = "Option::is_none")]
+    pub after_expiration: Option<bool>,
+    /// The payment method used, eg. `BTC` or `BTC-LightningNetwork`
+    #[serde(rename = "paymentMethod", skip_serializing_if = "Option::is_none")]
+    pub payment_method: Option<String>,
+    /// Details about the payment
+    #[serde(rename = "payment", skip_serializing_if = "Option::is_none")]
+    pub payment: Option<Box<Payment>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct WebhookInvoicePaymentSettledEvent {
+    /// The delivery id of the webhook
+    #[serde(rename = "deliveryId", skip_serializing_if = "Option::is_none")]
+    pub delivery_id: Option<String>,
+    /// The id of the webhook
+    #[serde(rename = "webhookId", skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<String>,
+    /// If this delivery is a redelivery, the is the delivery id of the original delivery.
+    #[serde(rename = "originalDeliveryId", skip_serializing_if = "Option::is_none")]
+    pub original_delivery_id: Option<String>,
+    /// True if this delivery is a redelivery
+    #[serde(rename = "isRedelivery", skip_serializing_if = "Option::is_none")]
+    pub is_redelivery: Option<bool>,
+    /// The type of this event, which is always "InvoicePaymentSettled" for this struct
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub _type: Option<String>,
+    /// The timestamp when this delivery has been created
+    #[serde(rename = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f32>,
+    /// The store id of the invoice's event
+    #[serde(rename = "storeId", skip_serializing_if = "Option::is_none")]
+    pub store_id: Option<String>,
+    /// The invoice id of the invoice's event
+    #[serde(rename = "invoiceId", skip_serializing_if = "Option::is_none")]
+    pub invoice_id: Option<String>,
+    /// Whether this payment has been sent after expiration of the invoice
+    #[serde(rename = "afterExpiration", skip_serializing_if = "Option::is_none")]
+    pub after_expiration: Option<bool>,
+    /// The payment method used, eg. `BTC` or `BTC-LightningNetwork`
+    #[serde(rename = "paymentMethod", skip_serializing_if = "Option::is_none")]
+    pub payment_method: Option<String>,
+    /// Details about the payment
+    #[serde(rename = "payment", skip_serializing_if = "Option::is_none")]
+    pub payment: Option<Box<Payment>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct WebhookInvoiceProcessingEvent {
+    /// The delivery id of the webhook
+    #[serde(rename = "deliveryId", skip_serializing_if = "Option::is_none")]
+    pub delivery_id: Option<String>,
+    /// The id of the webhook
+    #[serde(rename = "webhookId", skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<String>,
+    /// If this delivery is a redelivery, the is the delivery id of the original delivery.
+    #[serde(rename = "originalDeliveryId", skip_serializing_if = "Option::is_none")]
+    pub original_delivery_id: Option<String>,
+    /// True if this delivery is a redelivery
+    #[serde(rename = "isRedelivery", skip_serializing_if = "Option::is_none")]
+    pub is_redelivery: Option<bool>,
+    /// The type of this event, which is always "InvoiceProcessing" for this struct
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub _type: Option<String>,
+    /// The timestamp when this delivery has been created
+    #[serde(rename = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f32>,
+    /// The store id of the invoice's event
+    #[serde(rename = "storeId", skip_serializing_if = "Option::is_none")]
+    pub store_id: Option<String>,
+    /// The invoice id of the invoice's event
+    #[serde(rename = "invoiceId", skip_serializing_if = "Option::is_none")]
+    pub invoice_id: Option<String>,
+    /// Whether this invoice has received more money than expected
+    #[serde(rename = "overPaid", skip_serializing_if = "Option::is_none")]
+    pub over_paid: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct WebhookInvoiceExpiredEvent {
+    /// The delivery id of the webhook
+    #[serde(rename = "deliveryId", skip_serializing_if = "Option::is_none")]
+    pub delivery_id: Option<String>,
+    /// The id of the webhook
+    #[serde(rename = "webhookId", skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<String>,
+    /// If this delivery is a redelivery, the is the delivery id of the original delivery.
+    #[serde(rename = "originalDeliveryId", skip_serializing_if = "Option::is_none")]
+    pub original_delivery_id: Option<String>,
+    /// True if this delivery is a redelivery
+    #[serde(rename = "isRedelivery", skip_serializing_if = "Option::is_none")]
+    pub is_redelivery: Option<bool>,
+    /// The type of this event, which is always "InvoiceExpired" for this struct
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub _type: Option<String>,
+    /// The timestamp when this delivery has been created
+    #[serde(rename = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f32>,
+    /// The store id of the invoice's event
+    #[serde(rename = "storeId", skip_serializing_if = "Option::is_none")]
+    pub store_id: Option<String>,
+    /// The invoice id of the invoice's event
+    #[serde(rename = "invoiceId", skip_serializing_if = "Option::is_none")]
+    pub invoice_id: Option<String>,
+    /// Whether the invoice received some payments before being expired
+    #[serde(rename = "partiallyPaid", skip_serializing_if = "Option::is_none")]
+    pub partially_paid: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct WebhookInvoiceInvalidEvent {
+    /// The delivery id of the webhook
+    #[serde(rename = "deliveryId", skip_serializing_if = "Option::is_none")]
+    pub delivery_id: Option<String>,
+    /// The id of the webhook
+    #[serde(rename = "webhookId", skip_serializing_if = "Option::is_none")]
+    pub webhook_id: Option<String>,
+    /// If this delivery is a redelivery, the is the delivery id of the original delivery.
+    #[serde(rename = "originalDeliveryId", skip_serializing_if = "Option::is_none")]
+    pub original_delivery_id: Option<String>,
+    /// True if this delivery is a redelivery
+    #[serde(rename = "isRedelivery", skip_serializing_if = "Option::is_none")]
+    pub is_redelivery: Option<bool>,
+    /// The type of this event, which is always "InvoiceInvalid" for this struct
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub _type: Option<String>,
+    /// The timestamp when this delivery has been created
+    #[serde(rename = "timestamp", skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f32>,
+    /// The store id of the invoice's event
+    #[serde(rename = "storeId", skip_serializing_if = "Option::is_none")]
+    pub store_id: Option<String>,
+    /// The invoice id of the invoice's event
+    #[serde(rename = "invoiceId", skip_serializing_if = "Option::is_none")]
+    pub invoice_id: Option<String>,
+    /// Whether the invoice have been manually marked as invalid
+    #[serde(rename = "manuallyMarked", skip_serializing_if = "Option::is_none")]
+    pub manually_marked: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub struct Payment {
+    /// A unique identifier for this payment
+    #[serde(rename = "id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The date the payment got received by BTCPay Server
+    #[serde(rename = "receivedDate", skip_serializing_if = "Option::is_none")]
+    pub received_date: Option<i64>,
+    /// The value of the payment
+    #[serde(rename = "value", skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    /// The fee paid for the payment
+    #[serde(rename = "fee", skip_serializing_if = "Option::is_none")]
+    pub fee: Option<String>,
+    /// The status of the payment, `Invalid`, `Processing` or `Settled`
+    #[serde(rename = "status", skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// The destination the payment was made to
+    #[serde(rename = "destination", skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+}
@@ -1,30 +1,51 @@
-use anyhow::Result;
+#[cfg(feature = "btcpay-api")]
+use anyhow::{anyhow, Result};
 use hex::encode;
+#[cfg(feature = "btcpay-api")]
 use lazy_static::lazy_static;
 use ring::{hmac, hmac::Key, hmac::HMAC_SHA256};
 use serde::{Serialize, Deserialize};
+use serde_json::Value;
 
+use crate::event::{EventType, NormalizedEvent, Provider};
+use crate::verifier;
+
+#[cfg(feature = "btcpay-api")]
+pub mod btcpay_api;
+#[cfg(feature = "axum")]
+pub mod btcpay_extract;
+#[cfg(feature = "tower")]
+pub mod btcpay_layer;
+#[cfg(feature = "actix")]
 pub mod btcpay_middleware;
 pub mod btcpay_models;
 
-// Use our custom types here to support the metadata key and fix physical being a bool, instead of a string
+#[cfg(feature = "btcpay-api")]
+pub use btcpay_api::BTCPayClient;
+#[cfg(feature = "axum")]
+pub use btcpay_extract::BTCPayWebhook;
+#[cfg(feature = "tower")]
+pub use btcpay_layer::BTCPayVerifyLayer;
+
+// Our own types, so parsing does not need the btcpay-client crate. They also support the
+// metadata key and fix physical being a bool, instead of a string.
 pub use btcpay_models::{
   WebhookInvoiceSettledEvent,
-  WebhookInvoiceCreatedEvent
-};
-pub use btcpay_client::models::{
+  WebhookInvoiceCreatedEvent,
   WebhookInvoicePaymentSettledEvent,
   WebhookInvoiceReceivedPaymentEvent,
   WebhookInvoiceExpiredEvent,
   WebhookInvoiceInvalidEvent,
   WebhookInvoiceProcessingEvent,
-  InvoiceData
 };
+#[cfg(feature = "btcpay-api")]
+pub use btcpay_client::models::InvoiceData;
 
 
+#[cfg(feature = "btcpay-api")]
 lazy_static! {
-    static ref BTCPAY_HOST: String = std::env::var("BTCPAY_HOST").expect("BTCPAY_HOST must be set");
-    static ref BTCPAY_API_KEY: String = std::env::var("BTCPAY_API_KEY").expect("BTCPAY_API_KEY must be set");
+    static ref BTCPAY_HOST: Option<String> = std::env::var("BTCPAY_HOST").ok();
+    static ref BTCPAY_API_KEY: Option<String> = std::env::var("BTCPAY_API_KEY").ok();
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -36,36 +57,75 @@ pub enum WebhookPayload {
     InvoiceExpired(WebhookInvoiceExpiredEvent),
     InvoiceInvalid(WebhookInvoiceInvalidEvent),
     InvoiceProcessing(WebhookInvoiceProcessingEvent),
-    InvoiceCreated(WebhookInvoiceCreatedEvent), // Note: Not yet part of btcpay-client crate
+    InvoiceCreated(WebhookInvoiceCreatedEvent),
     Unsupported
 }
 
-pub fn verify_signature(payload_body: &str, secret_token: &str, signature_header: &str) -> bool {
-    let expected_signature = hmac_sha256(payload_body.as_bytes(), secret_token.as_bytes());
-    let actual_signature = signature_header.trim_start_matches("sha256=");
+// Builds a NormalizedEvent from the fields shared by all BTCPay webhook events
+macro_rules! normalized_event {
+    ($event:expr, $event_type:expr, $metadata:expr) => {
+        NormalizedEvent {
+            id: $event.original_delivery_id.clone()
+                .or_else(|| $event.delivery_id.clone())
+                .unwrap_or_else(|| format!("{}:{}", $event.invoice_id.clone().unwrap_or_default(), $event_type)),
+            provider: Provider::BTCPay,
+            event_type: $event_type,
+            store_id: $event.store_id.clone(),
+            invoice_id: $event.invoice_id.clone(),
+            payment_hash: None,
+            amount_msat: None,
+            timestamp: $event.timestamp.map(|timestamp| timestamp as i64),
+            metadata: $metadata,
+        }
+    };
+}
+
+impl WebhookPayload {
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        let event = match self {
+            WebhookPayload::InvoiceSettled(event) => {
+                let metadata = serde_json::to_value(&event.metadata).unwrap_or(Value::Null);
+                normalized_event!(event, EventType::InvoiceSettled, metadata)
+            },
+            WebhookPayload::InvoicePaymentSettled(event) => normalized_event!(event, EventType::PaymentSettled, Value::Null),
+            WebhookPayload::InvoiceReceivedPayment(event) => normalized_event!(event, EventType::PaymentReceived, Value::Null),
+            WebhookPayload::InvoiceExpired(event) => normalized_event!(event, EventType::InvoiceExpired, Value::Null),
+            WebhookPayload::InvoiceInvalid(event) => normalized_event!(event, EventType::InvoiceInvalid, Value::Null),
+            WebhookPayload::InvoiceProcessing(event) => normalized_event!(event, EventType::InvoiceProcessing, Value::Null),
+            WebhookPayload::InvoiceCreated(event) => {
+                let metadata = serde_json::to_value(&event.metadata).unwrap_or(Value::Null);
+                normalized_event!(event, EventType::InvoiceCreated, metadata)
+            },
+            WebhookPayload::Unsupported => return None,
+        };
+        Some(event)
+    }
+}
+
+/// Sign a payload body, returning a header value in the `sha256=<hex>` format checked by `verify_signature`
+pub fn sign_payload(payload_body: &str, secret_token: &str) -> String {
+    format!("sha256={}", hmac_sha256(payload_body.as_bytes(), secret_token.as_bytes()))
+}
 
-    actual_signature == expected_signature
+/// Check a `sha256=<hex>` signature header in constant time, ignoring the hex case
+pub fn verify_signature(payload_body: &str, secret_token: &str, signature_header: &str) -> bool {
+    verifier::check_hmac_sha256_hex(secret_token.as_bytes(), payload_body.as_bytes(), signature_header.as_bytes()).is_ok()
 }
 
-fn hmac_sha256(message: &[u8], secret_key: &[u8]) -> String {
+pub(crate) fn hmac_sha256(message: &[u8], secret_key: &[u8]) -> String {
     let key = Key::new(HMAC_SHA256, secret_key);
     let signature = hmac::sign(&key, message);
     encode(signature.as_ref())
 }
 
+/// Fetch an invoice with the `BTCPAY_HOST` and `BTCPAY_API_KEY` environment variables.
+/// Prefer a `BTCPayClient`, eg. from `config::BTCPayConfig::client`.
+#[cfg(feature = "btcpay-api")]
 pub async fn get_invoice_data(store_id: &str, invoice_id: &str) -> Result<InvoiceData> {
+    let host = BTCPAY_HOST.as_deref().ok_or_else(|| anyhow!("BTCPAY_HOST must be set"))?;
+    let api_key = BTCPAY_API_KEY.as_deref().ok_or_else(|| anyhow!("BTCPAY_API_KEY must be set"))?;
 
-    // Build the URL for the API endpoint
-    let url = format!("{}/api/v1/stores/{store_id}/invoices/{invoice_id}", *BTCPAY_HOST);
-
-    // Send the GET request with the API key in the headers and deserialize the response JSON into an InvoiceData struct
-    let response = reqwest::Client::new()
-        .get(&url)
-        .header(reqwest::header::AUTHORIZATION, format!("token {}", *BTCPAY_API_KEY))
-        .send()
-        .await?
-        .json::<InvoiceData>()
-        .await?;
-
-    Ok(response)
+    BTCPayClient::new(host, api_key)
+        .get_invoice_data(store_id, invoice_id)
+        .await
 }
@@ -0,0 +1,946 @@
+// Application configuration, layered from lowest to highest precedence: defaults, a TOML file,
+// `.env` and the process environment. Every secret can instead be read from a file (`*_FILE`, or
+// `*_file` in TOML) for Docker and Kubernetes secrets.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::admin::{AdminScope, AdminTokens};
+use crate::btcpay::{btcpay_middleware::WebhookSecrets, BTCPayClient};
+use crate::db::DbConfig;
+use crate::event::EventType;
+use crate::firewall::{Cidr, Firewall, RateLimit};
+#[cfg(feature = "lnbits-api")]
+use crate::lnbits::LNbitsClient;
+use crate::nostr::Keys;
+use crate::recorder::{JsonlFiles, Recorder};
+use crate::relay::Subscriber;
+
+/// A value that is never printed, eg. in `Debug` output or logs
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub postgres: PostgresConfig,
+    pub inbox: InboxConfig,
+    pub btcpay: Option<BTCPayConfig>,
+    pub lnbits: Option<LNbitsConfig>,
+    pub nostr: NostrConfig,
+    pub relay: RelayConfig,
+    pub recorder: Option<RecorderConfig>,
+    pub admin: Option<AdminConfig>,
+    pub firewall: FirewallConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostgresConfig {
+    /// libpq style URL, see `db::DbConfig::from_url`. May include a password, so kept secret.
+    pub url: Secret,
+    /// Apply the library's migrations on startup
+    pub migrate: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct InboxConfig {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BTCPayConfig {
+    pub host: String,
+    pub api_key: Secret,
+    /// Used for stores without their own secret
+    pub webhook_secret: Option<Secret>,
+    pub stores: Vec<BTCPayStoreConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BTCPayStoreConfig {
+    pub id: String,
+    pub webhook_secret: Secret,
+}
+
+#[derive(Debug, Clone)]
+pub struct LNbitsConfig {
+    pub host: String,
+    pub wallets: Vec<LNbitsWalletConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LNbitsWalletConfig {
+    pub id: String,
+    pub api_key: Secret,
+}
+
+#[cfg(feature = "lnbits-api")]
+impl LNbitsConfig {
+    /// A client looking payments up in every wallet, eg. for `LNbitsConfirm`
+    pub fn client(&self) -> LNbitsClient {
+        self.wallets.iter().fold(LNbitsClient::new(&self.host), |client, wallet| client.with_wallet(wallet.api_key.expose()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NostrConfig {
+    /// Hex secret key used to sign NIP-57 zap receipts
+    pub secret_key: Option<Secret>,
+    /// Hex pubkeys the Lightning address receives zaps for, see `Zapper::with_recipients`. Empty
+    /// means only the secret key's own pubkey.
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RelayConfig {
+    pub subscribers: Vec<RelaySubscriberConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelaySubscriberConfig {
+    pub id: String,
+    pub url: String,
+    pub secret: Secret,
+    /// Event types to forward. Empty forwards everything.
+    pub event_types: Vec<EventType>,
+}
+
+impl BTCPayConfig {
+    pub fn client(&self) -> BTCPayClient {
+        BTCPayClient::new(&self.host, self.api_key.expose())
+    }
+
+    /// Secrets for `BTCPayHeaderVerify`, registered as `web::Data<WebhookSecrets>`
+    pub fn webhook_secrets(&self) -> WebhookSecrets {
+        self.stores.iter().fold(
+            WebhookSecrets::new(self.webhook_secret.as_ref().map(|secret| secret.expose().to_string())),
+            |secrets, store| secrets.with_store(&store.id, store.webhook_secret.expose()),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    /// Directory for the JSONL files, see `recorder::JsonlFiles`
+    pub dir: PathBuf,
+    pub max_file_bytes: u64,
+    pub max_files: usize,
+    /// Redacted in addition to `recorder::DEFAULT_REDACTED_HEADERS`
+    pub redact_headers: Vec<String>,
+}
+
+/// Source checks for the webhook routes, see `firewall::Firewall`
+#[derive(Debug, Clone, Default)]
+pub struct FirewallConfig {
+    /// Accepts every client when empty
+    pub allow: Vec<Cidr>,
+    pub trusted_proxies: Vec<Cidr>,
+    pub rate_limit: Option<RateLimit>,
+}
+
+impl FirewallConfig {
+    /// A firewall with its own buckets, so call once per route
+    pub fn firewall(&self) -> Firewall {
+        let firewall = self.allow.iter().fold(Firewall::new(), |firewall, cidr| firewall.allow(*cidr));
+        let firewall = self.trusted_proxies.iter().fold(firewall, |firewall, cidr| firewall.trust_proxy(*cidr));
+        match self.rate_limit {
+            Some(limit) => firewall.rate_limit(limit),
+            None => firewall,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    /// Where `admin::scope` is mounted
+    pub path: String,
+    /// Where `dashboard::scope` is mounted, when enabled
+    pub dashboard_path: Option<String>,
+    pub tokens: Vec<AdminTokenConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminTokenConfig {
+    /// Shown in logs instead of the token
+    pub name: String,
+    pub token: Secret,
+    pub scopes: Vec<AdminScope>,
+}
+
+impl AdminConfig {
+    pub fn tokens(&self) -> AdminTokens {
+        self.tokens.iter().fold(AdminTokens::new(), |tokens, token| {
+            tokens.with_token(&token.name, token.token.expose(), &token.scopes)
+        })
+    }
+}
+
+impl RecorderConfig {
+    pub fn recorder(&self) -> Result<Recorder> {
+        let files = JsonlFiles::new(&self.dir, self.max_file_bytes, self.max_files)?;
+        Ok(self.redact_headers.iter().fold(Recorder::new(files), |recorder, name| recorder.redact_header(name)))
+    }
+}
+
+impl RelayConfig {
+    /// Subscribers for `relay::Relay`
+    pub fn subscribers(&self) -> Vec<Subscriber> {
+        self.subscribers.iter().map(|subscriber| Subscriber {
+            id: subscriber.id.clone(),
+            url: subscriber.url.clone(),
+            secret: subscriber.secret.expose().to_string(),
+            event_types: subscriber.event_types.clone(),
+        }).collect()
+    }
+}
+
+impl Config {
+    /// Load from the TOML file in `CONFIG_FILE` (if set), `.env` and the environment
+    pub fn load() -> Result<Config> {
+        dotenv::dotenv().ok();
+
+        match std::env::var_os("CONFIG_FILE") {
+            Some(path) => Config::load_from(Path::new(&path)),
+            None => Config::from_sources(None, |name| std::env::var(name).ok()),
+        }
+    }
+
+    /// Load from a TOML file, `.env` and the environment. Environment variables win over the file.
+    pub fn load_from(path: &Path) -> Result<Config> {
+        dotenv::dotenv().ok();
+
+        let toml = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        Config::from_sources(Some(&toml), |name| std::env::var(name).ok())
+    }
+
+    /// Build and validate a config, reporting every problem at once
+    pub fn from_sources(toml: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Result<Config> {
+        let mut raw: RawConfig = match toml {
+            Some(toml) => toml::from_str(toml).context("invalid config file")?,
+            None => RawConfig::default(),
+        };
+
+        let mut errors = Vec::new();
+        raw.apply_env(&env, &mut errors);
+        let config = raw.resolve(&mut errors);
+
+        if !errors.is_empty() {
+            bail!("invalid configuration:\n  - {}", errors.join("\n  - "));
+        }
+
+        Ok(config)
+    }
+
+    /// The address to bind the server to
+    pub fn bind_address(&self) -> (String, u16) {
+        (self.server.host.clone(), self.server.port)
+    }
+}
+
+// The file layout, before environment overrides and validation
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    server: RawServer,
+    postgres: RawPostgres,
+    inbox: RawInbox,
+    btcpay: Option<RawBTCPay>,
+    lnbits: Option<RawLNbits>,
+    nostr: RawNostr,
+    relay: RawRelay,
+    recorder: Option<RawRecorder>,
+    admin: Option<RawAdmin>,
+    firewall: RawFirewall,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawServer {
+    host: Option<String>,
+    port: Option<u16>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawPostgres {
+    url: RawSecret,
+    url_file: Option<PathBuf>,
+    migrate: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawInbox {
+    enabled: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawBTCPay {
+    host: Option<String>,
+    api_key: RawSecret,
+    api_key_file: Option<PathBuf>,
+    webhook_secret: RawSecret,
+    webhook_secret_file: Option<PathBuf>,
+    stores: Vec<RawBTCPayStore>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawBTCPayStore {
+    id: String,
+    webhook_secret: RawSecret,
+    webhook_secret_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawLNbits {
+    host: Option<String>,
+    wallets: Vec<RawLNbitsWallet>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawLNbitsWallet {
+    id: String,
+    api_key: RawSecret,
+    api_key_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawNostr {
+    secret_key: RawSecret,
+    secret_key_file: Option<PathBuf>,
+    recipients: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawRelay {
+    subscribers: Vec<RawRelaySubscriber>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawRelaySubscriber {
+    id: String,
+    url: Option<String>,
+    secret: RawSecret,
+    secret_file: Option<PathBuf>,
+    event_types: Vec<EventType>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawRecorder {
+    dir: Option<PathBuf>,
+    max_file_bytes: Option<u64>,
+    max_files: Option<usize>,
+    redact_headers: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawFirewall {
+    allow: Vec<String>,
+    trusted_proxies: Vec<String>,
+    rate_limit: Option<RawRateLimit>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawRateLimit {
+    burst: u32,
+    per_second: f64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAdmin {
+    path: Option<String>,
+    dashboard: Option<bool>,
+    dashboard_path: Option<String>,
+    tokens: Vec<RawAdminToken>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawAdminToken {
+    name: String,
+    token: RawSecret,
+    token_file: Option<PathBuf>,
+    scopes: Vec<AdminScope>,
+}
+
+// Deserialized secret, hidden from `Debug`
+#[derive(Default, Deserialize)]
+#[serde(transparent)]
+struct RawSecret(Option<String>);
+
+impl fmt::Debug for RawSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if self.0.is_some() { "Some(***)" } else { "None" })
+    }
+}
+
+// Wallet id for the key given in LNBITS_API_KEY
+const LNBITS_ENV_WALLET: &str = "default";
+
+// Name of the read/write token given in ADMIN_TOKEN
+const ADMIN_ENV_TOKEN: &str = "env";
+
+// Shorter tokens are too easy to guess
+const ADMIN_TOKEN_MIN_LEN: usize = 16;
+
+impl RawConfig {
+    fn apply_env(&mut self, env: &impl Fn(&str) -> Option<String>, errors: &mut Vec<String>) {
+        if let Some(host) = env("HOST") {
+            self.server.host = Some(host);
+        }
+        if let Some(port) = env("PORT") {
+            match port.parse() {
+                Ok(port) => self.server.port = Some(port),
+                Err(_) => errors.push(format!("PORT is not a valid port: {port}")),
+            }
+        }
+
+        env_secret(env, "POSTGRES_ADDRESS", &mut self.postgres.url, &mut self.postgres.url_file);
+        env_bool(env, "DB_MIGRATE", &mut self.postgres.migrate, errors);
+        env_bool(env, "WEBHOOK_INBOX", &mut self.inbox.enabled, errors);
+
+        if ["BTCPAY_HOST", "BTCPAY_API_KEY", "BTCPAY_API_KEY_FILE", "BTCPAY_WEBHOOK_SECRET", "BTCPAY_WEBHOOK_SECRET_FILE"].iter().any(|name| env(name).is_some()) {
+            let btcpay = self.btcpay.get_or_insert_with(RawBTCPay::default);
+            if let Some(host) = env("BTCPAY_HOST") {
+                btcpay.host = Some(host);
+            }
+            env_secret(env, "BTCPAY_API_KEY", &mut btcpay.api_key, &mut btcpay.api_key_file);
+            env_secret(env, "BTCPAY_WEBHOOK_SECRET", &mut btcpay.webhook_secret, &mut btcpay.webhook_secret_file);
+        }
+
+        if ["LNBITS_HOST", "LNBITS_API_KEY", "LNBITS_API_KEY_FILE"].iter().any(|name| env(name).is_some()) {
+            let lnbits = self.lnbits.get_or_insert_with(RawLNbits::default);
+            if let Some(host) = env("LNBITS_HOST") {
+                lnbits.host = Some(host);
+            }
+            if env("LNBITS_API_KEY").is_some() || env("LNBITS_API_KEY_FILE").is_some() {
+                lnbits.wallets.retain(|wallet| wallet.id != LNBITS_ENV_WALLET);
+                let mut wallet = RawLNbitsWallet { id: LNBITS_ENV_WALLET.to_string(), ..Default::default() };
+                env_secret(env, "LNBITS_API_KEY", &mut wallet.api_key, &mut wallet.api_key_file);
+                lnbits.wallets.push(wallet);
+            }
+        }
+
+        env_secret(env, "NOSTR_SECRET_KEY", &mut self.nostr.secret_key, &mut self.nostr.secret_key_file);
+        if let Some(recipients) = env("NOSTR_RECIPIENTS") {
+            self.nostr.recipients = split_list(&recipients);
+        }
+
+        if let Some(dir) = env("RECORDER_DIR") {
+            self.recorder.get_or_insert_with(RawRecorder::default).dir = Some(PathBuf::from(dir));
+        }
+
+        if let Some(allow) = env("FIREWALL_ALLOW") {
+            self.firewall.allow = split_list(&allow);
+        }
+        if let Some(proxies) = env("FIREWALL_TRUSTED_PROXIES") {
+            self.firewall.trusted_proxies = split_list(&proxies);
+        }
+
+        if env("ADMIN_DASHBOARD").is_some() {
+            env_bool(env, "ADMIN_DASHBOARD", &mut self.admin.get_or_insert_with(RawAdmin::default).dashboard, errors);
+        }
+        if env("ADMIN_TOKEN").is_some() || env("ADMIN_TOKEN_FILE").is_some() {
+            let admin = self.admin.get_or_insert_with(RawAdmin::default);
+            admin.tokens.retain(|token| token.name != ADMIN_ENV_TOKEN);
+            let mut token = RawAdminToken { name: ADMIN_ENV_TOKEN.to_string(), scopes: vec![AdminScope::Write], ..Default::default() };
+            env_secret(env, "ADMIN_TOKEN", &mut token.token, &mut token.token_file);
+            admin.tokens.push(token);
+        }
+    }
+
+    fn resolve(self, errors: &mut Vec<String>) -> Config {
+        let server = ServerConfig {
+            host: self.server.host.unwrap_or_else(|| "127.0.0.1".to_string()),
+            port: self.server.port.unwrap_or(4040),
+        };
+        if server.host.is_empty() {
+            errors.push("server.host (HOST) must not be empty".to_string());
+        }
+
+        let url = secret("postgres.url (POSTGRES_ADDRESS)", self.postgres.url, self.postgres.url_file, errors);
+        let url = match url {
+            Some(url) => {
+                if let Err(err) = DbConfig::from_url(url.expose()) {
+                    errors.push(format!("postgres.url (POSTGRES_ADDRESS): {err}"));
+                }
+                url
+            },
+            None => {
+                errors.push("postgres.url (POSTGRES_ADDRESS) must be set".to_string());
+                Secret::new("")
+            },
+        };
+
+        let postgres = PostgresConfig { url, migrate: self.postgres.migrate.unwrap_or(false) };
+        let inbox = InboxConfig { enabled: self.inbox.enabled.unwrap_or(false) };
+        let btcpay = self.btcpay.map(|btcpay| resolve_btcpay(btcpay, errors));
+        let lnbits = self.lnbits.map(|lnbits| resolve_lnbits(lnbits, errors));
+
+        let secret_key = secret("nostr.secret_key (NOSTR_SECRET_KEY)", self.nostr.secret_key, self.nostr.secret_key_file, errors);
+        if let Some(secret_key) = &secret_key {
+            if Keys::from_secret_hex(secret_key.expose()).is_err() {
+                errors.push("nostr.secret_key (NOSTR_SECRET_KEY) is not a valid hex secret key".to_string());
+            }
+        }
+
+        for recipient in &self.nostr.recipients {
+            if recipient.len() != 64 || !recipient.bytes().all(|b| b.is_ascii_hexdigit()) {
+                errors.push(format!("nostr.recipients (NOSTR_RECIPIENTS): {recipient} is not a hex pubkey"));
+            }
+        }
+        let nostr = NostrConfig { secret_key, recipients: self.nostr.recipients };
+
+        let relay = resolve_relay(self.relay, errors);
+
+        let recorder = self.recorder.and_then(|recorder| match recorder.dir {
+            Some(dir) => Some(RecorderConfig {
+                dir,
+                max_file_bytes: recorder.max_file_bytes.unwrap_or(64 * 1024 * 1024),
+                max_files: recorder.max_files.unwrap_or(10),
+                redact_headers: recorder.redact_headers,
+            }),
+            None => {
+                errors.push("recorder.dir (RECORDER_DIR) must be set".to_string());
+                None
+            },
+        });
+
+        let admin = self.admin.map(|admin| resolve_admin(admin, errors));
+        let firewall = resolve_firewall(self.firewall, errors);
+
+        Config { server, postgres, inbox, btcpay, lnbits, nostr, relay, recorder, admin, firewall }
+    }
+}
+
+fn resolve_btcpay(btcpay: RawBTCPay, errors: &mut Vec<String>) -> BTCPayConfig {
+    let host = required_url("btcpay.host (BTCPAY_HOST)", btcpay.host, errors);
+    let api_key = secret("btcpay.api_key (BTCPAY_API_KEY)", btcpay.api_key, btcpay.api_key_file, errors);
+    if api_key.is_none() {
+        errors.push("btcpay.api_key (BTCPAY_API_KEY) must be set".to_string());
+    }
+    let webhook_secret = secret("btcpay.webhook_secret (BTCPAY_WEBHOOK_SECRET)", btcpay.webhook_secret, btcpay.webhook_secret_file, errors);
+
+    let mut ids = HashSet::new();
+    let stores: Vec<_> = btcpay.stores.into_iter().enumerate().filter_map(|(i, store)| {
+        let name = format!("btcpay.stores[{i}]");
+        if store.id.is_empty() {
+            errors.push(format!("{name}.id must be set"));
+        } else if !ids.insert(store.id.clone()) {
+            errors.push(format!("{name}.id {} is listed more than once", store.id));
+        }
+        match secret(&format!("{name}.webhook_secret"), store.webhook_secret, store.webhook_secret_file, errors) {
+            Some(webhook_secret) => Some(BTCPayStoreConfig { id: store.id, webhook_secret }),
+            None => {
+                errors.push(format!("{name}.webhook_secret must be set"));
+                None
+            },
+        }
+    }).collect();
+
+    if webhook_secret.is_none() && stores.is_empty() {
+        errors.push("btcpay.webhook_secret (BTCPAY_WEBHOOK_SECRET) must be set, or a secret given per store".to_string());
+    }
+
+    BTCPayConfig { host, api_key: api_key.unwrap_or_else(|| Secret::new("")), webhook_secret, stores }
+}
+
+fn resolve_lnbits(lnbits: RawLNbits, errors: &mut Vec<String>) -> LNbitsConfig {
+    let host = required_url("lnbits.host (LNBITS_HOST)", lnbits.host, errors);
+
+    if lnbits.wallets.is_empty() {
+        errors.push("lnbits.wallets (LNBITS_API_KEY) must list at least one wallet".to_string());
+    }
+
+    let mut ids = HashSet::new();
+    let wallets = lnbits.wallets.into_iter().enumerate().filter_map(|(i, wallet)| {
+        let name = format!("lnbits.wallets[{i}]");
+        if wallet.id.is_empty() {
+            errors.push(format!("{name}.id must be set"));
+        } else if !ids.insert(wallet.id.clone()) {
+            errors.push(format!("{name}.id {} is listed more than once", wallet.id));
+        }
+        match secret(&format!("{name}.api_key"), wallet.api_key, wallet.api_key_file, errors) {
+            Some(api_key) => Some(LNbitsWalletConfig { id: wallet.id, api_key }),
+            None => {
+                errors.push(format!("{name}.api_key must be set"));
+                None
+            },
+        }
+    }).collect();
+
+    LNbitsConfig { host, wallets }
+}
+
+fn resolve_relay(relay: RawRelay, errors: &mut Vec<String>) -> RelayConfig {
+    let mut ids = HashSet::new();
+    let subscribers = relay.subscribers.into_iter().enumerate().filter_map(|(i, subscriber)| {
+        let name = format!("relay.subscribers[{i}]");
+        if subscriber.id.is_empty() {
+            errors.push(format!("{name}.id must be set"));
+        } else if !ids.insert(subscriber.id.clone()) {
+            errors.push(format!("{name}.id {} is listed more than once", subscriber.id));
+        }
+        let url = required_url(&format!("{name}.url"), subscriber.url, errors);
+        match secret(&format!("{name}.secret"), subscriber.secret, subscriber.secret_file, errors) {
+            Some(secret) => Some(RelaySubscriberConfig { id: subscriber.id, url, secret, event_types: subscriber.event_types }),
+            None => {
+                errors.push(format!("{name}.secret must be set"));
+                None
+            },
+        }
+    }).collect();
+
+    RelayConfig { subscribers }
+}
+
+fn resolve_firewall(firewall: RawFirewall, errors: &mut Vec<String>) -> FirewallConfig {
+    let mut cidrs = |name: &str, values: Vec<String>| -> Vec<Cidr> {
+        values.iter().filter_map(|value| match value.parse() {
+            Ok(cidr) => Some(cidr),
+            Err(err) => {
+                errors.push(format!("{name}: {err:#}"));
+                None
+            },
+        }).collect()
+    };
+
+    let allow = cidrs("firewall.allow (FIREWALL_ALLOW)", firewall.allow);
+    let trusted_proxies = cidrs("firewall.trusted_proxies (FIREWALL_TRUSTED_PROXIES)", firewall.trusted_proxies);
+
+    let rate_limit = firewall.rate_limit.and_then(|limit| match RateLimit::new(limit.burst, limit.per_second) {
+        Ok(limit) => Some(limit),
+        Err(err) => {
+            errors.push(format!("firewall.rate_limit: {err}"));
+            None
+        },
+    });
+
+    FirewallConfig { allow, trusted_proxies, rate_limit }
+}
+
+fn resolve_admin(admin: RawAdmin, errors: &mut Vec<String>) -> AdminConfig {
+    let path = admin.path.unwrap_or_else(|| "/admin".to_string());
+    if !path.starts_with('/') || path == "/" {
+        errors.push(format!("admin.path must start with / and not be the root, not {path}"));
+    }
+
+    let dashboard_path = match admin.dashboard.unwrap_or(false) {
+        true => Some(admin.dashboard_path.unwrap_or_else(|| "/dashboard".to_string())),
+        false => None,
+    };
+    if let Some(dashboard_path) = &dashboard_path {
+        if !dashboard_path.starts_with('/') || dashboard_path == "/" || *dashboard_path == path {
+            errors.push(format!("admin.dashboard_path must start with / and not be the root or admin.path, not {dashboard_path}"));
+        }
+    }
+    if admin.tokens.is_empty() {
+        errors.push("admin.tokens (ADMIN_TOKEN) must list at least one token".to_string());
+    }
+
+    let mut names = HashSet::new();
+    let tokens = admin.tokens.into_iter().enumerate().filter_map(|(i, token)| {
+        let name = format!("admin.tokens[{i}]");
+        if token.name.is_empty() {
+            errors.push(format!("{name}.name must be set"));
+        } else if !names.insert(token.name.clone()) {
+            errors.push(format!("{name}.name {} is listed more than once", token.name));
+        }
+        if token.scopes.is_empty() {
+            errors.push(format!("{name}.scopes must list read and/or write"));
+        }
+        match secret(&format!("{name}.token"), token.token, token.token_file, errors) {
+            Some(secret) if secret.expose().len() < ADMIN_TOKEN_MIN_LEN => {
+                errors.push(format!("{name}.token must be at least {ADMIN_TOKEN_MIN_LEN} characters"));
+                None
+            },
+            Some(secret) => Some(AdminTokenConfig { name: token.name, token: secret, scopes: token.scopes }),
+            None => {
+                errors.push(format!("{name}.token must be set"));
+                None
+            },
+        }
+    }).collect();
+
+    AdminConfig { path, dashboard_path, tokens }
+}
+
+// Environment variables replace the file's value, including a `*_file` set in the file
+fn env_secret(env: &impl Fn(&str) -> Option<String>, name: &str, value: &mut RawSecret, file: &mut Option<PathBuf>) {
+    let env_value = env(name);
+    let env_file = env(&format!("{name}_FILE"));
+
+    if env_value.is_some() || env_file.is_some() {
+        *value = RawSecret(env_value);
+        *file = env_file.map(PathBuf::from);
+    }
+}
+
+// Comma separated, eg. FIREWALL_ALLOW="10.0.0.0/8, 192.0.2.1"
+fn split_list(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(str::to_string).collect()
+}
+
+fn env_bool(env: &impl Fn(&str) -> Option<String>, name: &str, value: &mut Option<bool>, errors: &mut Vec<String>) {
+    if let Some(raw) = env(name) {
+        match raw.to_lowercase().as_str() {
+            "true" | "1" | "yes" => *value = Some(true),
+            "false" | "0" | "no" => *value = Some(false),
+            _ => errors.push(format!("{name} must be true or false, not {raw}")),
+        }
+    }
+}
+
+// A secret given directly or as a file path, but not both. Trailing newlines in files are ignored.
+fn secret(name: &str, value: RawSecret, file: Option<PathBuf>, errors: &mut Vec<String>) -> Option<Secret> {
+    match (value.0, file) {
+        (Some(_), Some(_)) => {
+            errors.push(format!("{name} is set both directly and as a file, use one"));
+            None
+        },
+        (Some(value), None) => Some(Secret(value)),
+        (None, Some(path)) => match std::fs::read_to_string(&path) {
+            Ok(value) => Some(Secret(value.trim_end_matches(['\r', '\n']).to_string())),
+            Err(err) => {
+                errors.push(format!("{name} file {} could not be read: {err}", path.display()));
+                None
+            },
+        },
+        (None, None) => None,
+    }
+}
+
+fn required_url(name: &str, value: Option<String>, errors: &mut Vec<String>) -> String {
+    match value {
+        Some(value) => {
+            match url::Url::parse(&value) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {},
+                _ => errors.push(format!("{name} must be an http(s) URL, not {value}")),
+            }
+            value
+        },
+        None => {
+            errors.push(format!("{name} must be set"));
+            String::new()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(toml: Option<&str>, env: &[(&str, &str)]) -> Result<Config> {
+        let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::from_sources(toml, |name| env.get(name).cloned())
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let config = load(Some(r#"
+            [server]
+            host = "0.0.0.0"
+            port = 8080
+
+            [postgres]
+            url = "postgresql://postgres@localhost/file"
+
+            [btcpay]
+            host = "https://btcpay.example.com"
+            api_key = "file-key"
+
+            [[btcpay.stores]]
+            id = "store-a"
+            webhook_secret = "secret-a"
+        "#), &[
+            ("PORT", "9090"),
+            ("POSTGRES_ADDRESS", "postgresql://postgres@localhost/env"),
+            ("BTCPAY_API_KEY", "env-key"),
+        ]).unwrap();
+
+        assert_eq!(config.bind_address(), ("0.0.0.0".to_string(), 9090));
+        assert_eq!(config.postgres.url.expose(), "postgresql://postgres@localhost/env");
+
+        let btcpay = config.btcpay.unwrap();
+        assert_eq!(btcpay.host, "https://btcpay.example.com");
+        assert_eq!(btcpay.api_key.expose(), "env-key");
+        assert_eq!(btcpay.webhook_secrets().secret_for(Some("store-a")), Some("secret-a"));
+        assert_eq!(btcpay.webhook_secrets().secret_for(Some("store-b")), None);
+    }
+
+    #[test]
+    fn test_secret_files() {
+        let path = std::env::temp_dir().join(format!("lightning-webhook-secret-{}", std::process::id()));
+        std::fs::write(&path, "from-file\n").unwrap();
+
+        let config = load(None, &[
+            ("POSTGRES_ADDRESS", "postgresql://postgres@localhost/postgres"),
+            ("LNBITS_HOST", "https://lnbits.example.com"),
+            ("LNBITS_API_KEY_FILE", path.to_str().unwrap()),
+        ]).unwrap();
+
+        let lnbits = config.lnbits.unwrap();
+        assert_eq!(lnbits.wallets[0].id, "default");
+        assert_eq!(lnbits.wallets[0].api_key.expose(), "from-file");
+        assert!(!format!("{lnbits:?}").contains("from-file"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reports_every_error() {
+        let error = load(Some(r#"
+            [btcpay]
+            host = "btcpay.example.com"
+
+            [[lnbits.wallets]]
+            id = "main"
+            api_key = "key"
+            api_key_file = "/run/secrets/lnbits"
+        "#), &[("PORT", "http"), ("DB_MIGRATE", "maybe"), ("NOSTR_RECIPIENTS", "npub1alice")]).unwrap_err().to_string();
+
+        for expected in [
+            "PORT is not a valid port: http",
+            "DB_MIGRATE must be true or false, not maybe",
+            "postgres.url (POSTGRES_ADDRESS) must be set",
+            "btcpay.host (BTCPAY_HOST) must be an http(s) URL, not btcpay.example.com",
+            "btcpay.api_key (BTCPAY_API_KEY) must be set",
+            "btcpay.webhook_secret (BTCPAY_WEBHOOK_SECRET) must be set, or a secret given per store",
+            "lnbits.host (LNBITS_HOST) must be set",
+            "lnbits.wallets[0].api_key is set both directly and as a file, use one",
+            "nostr.recipients (NOSTR_RECIPIENTS): npub1alice is not a hex pubkey",
+        ] {
+            assert!(error.contains(expected), "missing {expected:?} in:\n{error}");
+        }
+    }
+
+    #[test]
+    fn test_relay_subscribers() {
+        let config = load(Some(r#"
+            [[relay.subscribers]]
+            id = "orders"
+            url = "https://orders.example.com/hooks"
+            secret = "orders-secret"
+            event_types = ["invoice.settled"]
+        "#), &[("POSTGRES_ADDRESS", "postgresql://postgres@localhost/postgres")]).unwrap();
+
+        let subscriber = &config.relay.subscribers()[0];
+        assert_eq!(subscriber.url, "https://orders.example.com/hooks");
+        assert_eq!(subscriber.secret, "orders-secret");
+        assert_eq!(subscriber.event_types, vec![EventType::InvoiceSettled]);
+        assert!(!format!("{:?}", config.relay).contains("orders-secret"));
+
+        let error = load(Some(r#"
+            [[relay.subscribers]]
+            id = "orders"
+            url = "orders.example.com"
+        "#), &[("POSTGRES_ADDRESS", "postgresql://postgres@localhost/postgres")]).unwrap_err().to_string();
+        assert!(error.contains("relay.subscribers[0].url must be an http(s) URL"));
+        assert!(error.contains("relay.subscribers[0].secret must be set"));
+    }
+
+    #[test]
+    fn test_admin_tokens() {
+        let config = load(Some(r#"
+            [[admin.tokens]]
+            name = "dashboard"
+            token = "dashboard-token-0f3a9c"
+            scopes = ["read"]
+        "#), &[
+            ("POSTGRES_ADDRESS", "postgresql://postgres@localhost/postgres"),
+            ("ADMIN_TOKEN", "ops-token-5d1e0b7a42"),
+            ("ADMIN_DASHBOARD", "true"),
+        ]).unwrap();
+
+        let admin = config.admin.unwrap();
+        assert_eq!(admin.path, "/admin");
+        assert_eq!(admin.dashboard_path.as_deref(), Some("/dashboard"));
+        assert_eq!(admin.tokens.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["dashboard", "env"]);
+        assert_eq!(admin.tokens[1].scopes, vec![AdminScope::Write]);
+        assert!(!format!("{:?}", admin.tokens).contains("ops-token"));
+
+        let error = load(Some(r#"
+            [[admin.tokens]]
+            name = "dashboard"
+            token = "short"
+        "#), &[("POSTGRES_ADDRESS", "postgresql://postgres@localhost/postgres")]).unwrap_err().to_string();
+        assert!(error.contains("admin.tokens[0].scopes must list read and/or write"));
+        assert!(error.contains("admin.tokens[0].token must be at least 16 characters"));
+    }
+
+    #[test]
+    fn test_firewall() {
+        let config = load(Some(r#"
+            [firewall]
+            trusted_proxies = ["10.0.0.2"]
+            rate_limit = { burst = 20, per_second = 5.0 }
+        "#), &[
+            ("POSTGRES_ADDRESS", "postgresql://postgres@localhost/postgres"),
+            ("FIREWALL_ALLOW", "203.0.113.0/24, 2001:db8::/32"),
+        ]).unwrap();
+
+        assert_eq!(config.firewall.allow.iter().map(Cidr::to_string).collect::<Vec<_>>(), vec!["203.0.113.0/24", "2001:db8::/32"]);
+        assert_eq!(config.firewall.trusted_proxies[0].to_string(), "10.0.0.2/32");
+        assert_eq!(config.firewall.rate_limit, Some(RateLimit { burst: 20, per_second: 5.0 }));
+
+        let error = load(Some(r#"
+            [firewall]
+            allow = ["10.0.0.0/40"]
+            rate_limit = { burst = 0, per_second = 5.0 }
+        "#), &[("POSTGRES_ADDRESS", "postgresql://postgres@localhost/postgres")]).unwrap_err().to_string();
+        assert!(error.contains("firewall.allow (FIREWALL_ALLOW): invalid prefix length in 10.0.0.0/40"));
+        assert!(error.contains("firewall.rate_limit: rate limits need a burst of at least 1"));
+    }
+
+    #[test]
+    fn test_example_file_parses() {
+        toml::from_str::<RawConfig>(include_str!("../config.example.toml")).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_unknown_keys() {
+        let error = load(Some("[server]\nhots = \"localhost\""), &[]).unwrap_err();
+        assert!(format!("{error:#}").contains("unknown field `hots`"));
+    }
+}
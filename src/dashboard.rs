@@ -0,0 +1,531 @@
+// Server-rendered HTML dashboard for ops staff, built on the store: recent deliveries, a timeline
+// per invoice, dead-letters with a retry button and signature failure counts. Plain HTML forms,
+// so there is no JavaScript or asset build. Staff sign in with an admin token, which is exchanged
+// for an expiring session cookie signed with that token.
+
+use actix_web::{
+    cookie::{time::Duration as CookieDuration, Cookie, SameSite},
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, Scope,
+};
+use serde::Deserialize;
+use std::{
+    fmt::Write,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::admin::{AdminScope, AdminToken, AdminTokens, MAX_LIMIT};
+use crate::inbox::InboxStatus;
+use crate::store::{InboxFilter, InboxRecord, WebhookStore};
+
+const COOKIE_NAME: &str = "lnwebhook_dashboard";
+
+const SESSION_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+// How far back the signature failure counts go
+const FAILURE_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+const RECENT_LIMIT: i64 = 100;
+
+struct Dashboard {
+    path: String,
+    store: Arc<dyn WebhookStore>,
+    tokens: AdminTokens,
+}
+
+impl Dashboard {
+    // The signed in token, if it grants `scope`
+    fn token(&self, req: &HttpRequest, scope: AdminScope) -> Option<&AdminToken> {
+        let cookie = req.cookie(COOKIE_NAME)?;
+        self.session_token(cookie.value(), SystemTime::now()).filter(|token| token.grants(scope))
+    }
+
+    // Sessions are `{name}.{expires}.{signature}`, signed with the named token. They need no server
+    // side state, so work across workers and restarts, and end when the token is removed.
+    fn session_token(&self, session: &str, now: SystemTime) -> Option<&AdminToken> {
+        let mut parts = session.rsplitn(3, '.');
+        let (signature, expires, name) = (parts.next()?, parts.next()?, parts.next()?);
+
+        let expires: u64 = expires.parse().ok()?;
+        if UNIX_EPOCH + Duration::from_secs(expires) <= now {
+            return None;
+        }
+
+        let token = self.tokens.named(name)?;
+        token.verify(session_message(name, expires).as_bytes(), &hex::decode(signature).ok()?).then_some(token)
+    }
+
+    fn redirect(&self, to: &str) -> HttpResponse {
+        HttpResponse::SeeOther()
+            .insert_header((header::LOCATION, format!("{}{to}", self.path)))
+            .finish()
+    }
+
+    fn page(&self, status: StatusCode, title: &str, body: &str) -> HttpResponse {
+        let path = escape(&self.path);
+        let html = format!(r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title} - Lightning webhooks</title>
+<style>{STYLE}</style>
+</head>
+<body>
+<nav><a href="{path}">Deliveries</a> <a href="{path}/dead-letters">Dead-letters</a>
+<form method="post" action="{path}/logout"><button>Sign out</button></form></nav>
+<main>
+<h1>{title}</h1>
+{body}
+</main>
+</body>
+</html>
+"#, title = escape(title));
+
+        HttpResponse::build(status)
+            .content_type("text/html; charset=utf-8")
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .insert_header((header::X_FRAME_OPTIONS, "DENY"))
+            .insert_header((header::CONTENT_SECURITY_POLICY, "default-src 'none'; style-src 'unsafe-inline'; form-action 'self'"))
+            .body(html)
+    }
+}
+
+/// The dashboard, mounted at `path`. Signing in takes an admin token with read scope, and retrying
+/// dead-letters takes write scope.
+///
+/// ```ignore
+/// App::new()
+///     .service(dashboard::scope("/dashboard", store.clone(), tokens))
+/// ```
+pub fn scope(path: &str, store: Arc<dyn WebhookStore>, tokens: AdminTokens) -> Scope {
+    let path = path.trim_end_matches('/').to_string();
+
+    web::scope(&path)
+        .app_data(web::Data::new(Dashboard { path: path.clone(), store, tokens }))
+        .route("", web::get().to(deliveries))
+        .route("/", web::get().to(deliveries))
+        .route("/login", web::get().to(login_form))
+        .route("/login", web::post().to(login))
+        .route("/logout", web::post().to(logout))
+        .route("/invoices/{invoice_id}", web::get().to(invoice_timeline))
+        .route("/dead-letters", web::get().to(dead_letters))
+        .route("/deliveries/{id}/retry", web::post().to(retry))
+}
+
+#[derive(Deserialize)]
+struct DeliveriesQuery {
+    status: Option<String>,
+}
+
+async fn deliveries(req: HttpRequest, query: web::Query<DeliveriesQuery>, dashboard: web::Data<Dashboard>) -> HttpResponse {
+    if dashboard.token(&req, AdminScope::Read).is_none() {
+        return dashboard.redirect("/login");
+    }
+
+    let status = query.status.as_deref().and_then(|status| status.parse::<InboxStatus>().ok());
+    let filter = InboxFilter { status, limit: RECENT_LIMIT, ..Default::default() };
+
+    let (records, failures) = match (
+        dashboard.store.inbox_search(&filter).await,
+        dashboard.store.verification_failures(SystemTime::now() - FAILURE_WINDOW).await,
+    ) {
+        (Ok(records), Ok(failures)) => (records, failures),
+        (Err(err), _) | (_, Err(err)) => return internal_error(&dashboard, err),
+    };
+
+    let mut body = String::new();
+
+    body.push_str("<h2>Signature failures, last 24 hours</h2>\n");
+    if failures.is_empty() {
+        body.push_str("<p>None.</p>\n");
+    } else {
+        body.push_str("<table>\n<tr><th>Provider</th><th>Reason</th><th>Requests</th></tr>\n");
+        for failure in &failures {
+            let _ = writeln!(body, "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(&failure.provider), escape(&failure.reason), failure.count);
+        }
+        body.push_str("</table>\n");
+    }
+
+    body.push_str("<h2>Recent deliveries</h2>\n<p>");
+    for (label, value) in [("All", ""), ("Pending", "pending"), ("Done", "done"), ("Dead", "dead")] {
+        let href = match value {
+            "" => dashboard.path.clone(),
+            value => format!("{}?status={value}", dashboard.path),
+        };
+        let _ = write!(body, r#"<a href="{}">{label}</a> "#, escape(&href));
+    }
+    body.push_str("</p>\n");
+    deliveries_table(&mut body, &dashboard, &records, None);
+
+    dashboard.page(StatusCode::OK, "Deliveries", &body)
+}
+
+async fn invoice_timeline(req: HttpRequest, invoice_id: web::Path<String>, dashboard: web::Data<Dashboard>) -> HttpResponse {
+    if dashboard.token(&req, AdminScope::Read).is_none() {
+        return dashboard.redirect("/login");
+    }
+
+    let filter = InboxFilter { invoice_id: Some(invoice_id.to_string()), limit: MAX_LIMIT, ..Default::default() };
+    let mut records = match dashboard.store.inbox_search(&filter).await {
+        Ok(records) => records,
+        Err(err) => return internal_error(&dashboard, err),
+    };
+    records.reverse();
+
+    let mut body = String::new();
+    if records.is_empty() {
+        body.push_str("<p>No events received for this invoice.</p>\n");
+    } else {
+        body.push_str("<ol class=\"timeline\">\n");
+        for record in &records {
+            let message = &record.message;
+            let _ = writeln!(body, "<li><strong>{}</strong> {} {} <span class=\"muted\">via {}, delivery {}</span></li>",
+                escape(message.event_type.as_deref().unwrap_or("unknown event")),
+                format_time(message.received_at),
+                badge(record.status),
+                escape(&message.provider),
+                message.id);
+        }
+        body.push_str("</ol>\n");
+    }
+
+    dashboard.page(StatusCode::OK, &format!("Invoice {invoice_id}"), &body)
+}
+
+async fn dead_letters(req: HttpRequest, dashboard: web::Data<Dashboard>) -> HttpResponse {
+    let can_retry = match dashboard.token(&req, AdminScope::Read) {
+        Some(token) => token.grants(AdminScope::Write),
+        None => return dashboard.redirect("/login"),
+    };
+
+    let filter = InboxFilter { status: Some(InboxStatus::Dead), limit: MAX_LIMIT, ..Default::default() };
+    let records = match dashboard.store.inbox_search(&filter).await {
+        Ok(records) => records,
+        Err(err) => return internal_error(&dashboard, err),
+    };
+
+    let mut body = String::new();
+    if !can_retry {
+        body.push_str("<p class=\"muted\">Retrying needs a token with write scope.</p>\n");
+    }
+    deliveries_table(&mut body, &dashboard, &records, Some(can_retry));
+
+    dashboard.page(StatusCode::OK, "Dead-letters", &body)
+}
+
+async fn retry(req: HttpRequest, id: web::Path<i64>, dashboard: web::Data<Dashboard>) -> HttpResponse {
+    let token = match dashboard.token(&req, AdminScope::Write) {
+        Some(token) => token.name().to_string(),
+        None => return dashboard.page(StatusCode::FORBIDDEN, "Forbidden", "<p>Retrying needs a token with write scope.</p>"),
+    };
+
+    match dashboard.store.inbox_status(*id).await {
+        Ok(Some(InboxStatus::Dead)) => {},
+        Ok(_) => return dashboard.redirect("/dead-letters"),
+        Err(err) => return internal_error(&dashboard, err),
+    }
+
+    match dashboard.store.inbox_requeue(*id).await {
+        Ok(_) => {
+            info!("Dashboard user {token} queued delivery {id} for retry");
+            dashboard.redirect("/dead-letters")
+        },
+        Err(err) => internal_error(&dashboard, err),
+    }
+}
+
+#[derive(Deserialize)]
+struct LoginForm {
+    token: String,
+}
+
+async fn login_form(dashboard: web::Data<Dashboard>) -> HttpResponse {
+    login_page(&dashboard, StatusCode::OK, None)
+}
+
+async fn login(req: HttpRequest, form: web::Form<LoginForm>, dashboard: web::Data<Dashboard>) -> HttpResponse {
+    let token = form.token.trim();
+    match dashboard.tokens.lookup(token) {
+        Some(found) if found.grants(AdminScope::Read) => {
+            info!("Dashboard user {} signed in", found.name());
+
+            let cookie = Cookie::build(COOKIE_NAME, session(found, SystemTime::now() + SESSION_TTL))
+                .path(dashboard.path.clone())
+                .http_only(true)
+                .secure(req.connection_info().scheme() == "https")
+                .same_site(SameSite::Strict)
+                .max_age(CookieDuration::seconds(SESSION_TTL.as_secs() as i64))
+                .finish();
+
+            let mut response = dashboard.redirect("");
+            let _ = response.add_cookie(&cookie);
+            response
+        },
+        _ => login_page(&dashboard, StatusCode::UNAUTHORIZED, Some("Unknown token.")),
+    }
+}
+
+async fn logout(dashboard: web::Data<Dashboard>) -> HttpResponse {
+    let mut response = dashboard.redirect("/login");
+    let _ = response.add_removal_cookie(&Cookie::build(COOKIE_NAME, "").path(dashboard.path.clone()).finish());
+    response
+}
+
+// A session for `token` that expires at `expires`, see `Dashboard::session_token`
+fn session(token: &AdminToken, expires: SystemTime) -> String {
+    let expires = expires.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let signature = token.sign(session_message(token.name(), expires).as_bytes());
+    format!("{}.{expires}.{}", token.name(), hex::encode(signature.as_ref()))
+}
+
+fn session_message(name: &str, expires: u64) -> String {
+    format!("lnwebhook-dashboard-session\n{name}\n{expires}")
+}
+
+fn login_page(dashboard: &Dashboard, status: StatusCode, error: Option<&str>) -> HttpResponse {
+    let mut body = String::new();
+    if let Some(error) = error {
+        let _ = writeln!(body, "<p class=\"error\">{}</p>", escape(error));
+    }
+    let _ = writeln!(body, r#"<form method="post" action="{}/login">
+<label>Admin token <input type="password" name="token" autocomplete="current-password" required></label>
+<button>Sign in</button>
+</form>"#, escape(&dashboard.path));
+
+    dashboard.page(status, "Sign in", &body)
+}
+
+// `retry` is None to leave out the retry column, otherwise whether the button is enabled
+fn deliveries_table(body: &mut String, dashboard: &Dashboard, records: &[InboxRecord], retry: Option<bool>) {
+    if records.is_empty() {
+        body.push_str("<p>No deliveries.</p>\n");
+        return;
+    }
+
+    body.push_str("<table>\n<tr><th>Id</th><th>Received</th><th>Provider</th><th>Event</th><th>Invoice</th>");
+    body.push_str("<th>Status</th><th>Attempts</th><th>Last error</th>");
+    if retry.is_some() {
+        body.push_str("<th></th>");
+    }
+    body.push_str("</tr>\n");
+
+    for record in records {
+        let message = &record.message;
+        let invoice = match &message.invoice_id {
+            Some(invoice_id) => format!(r#"<a href="{}/invoices/{}">{}</a>"#,
+                escape(&dashboard.path), escape(&url_encode(invoice_id)), escape(invoice_id)),
+            None => escape(message.payment_hash.as_deref().unwrap_or_default()),
+        };
+
+        let _ = write!(body, "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{invoice}</td><td>{}</td><td>{}</td><td>{}</td>",
+            message.id,
+            format_time(message.received_at),
+            escape(&message.provider),
+            escape(message.event_type.as_deref().unwrap_or_default()),
+            badge(record.status),
+            message.attempts,
+            escape(record.last_error.as_deref().unwrap_or_default()));
+
+        if let Some(enabled) = retry {
+            let _ = write!(body, r#"<td><form method="post" action="{}/deliveries/{}/retry"><button{}>Retry</button></form></td>"#,
+                escape(&dashboard.path), message.id, if enabled { "" } else { " disabled" });
+        }
+        body.push_str("</tr>\n");
+    }
+
+    body.push_str("</table>\n");
+}
+
+fn badge(status: InboxStatus) -> String {
+    format!(r#"<span class="badge {0}">{0}</span>"#, status.as_str())
+}
+
+fn internal_error(dashboard: &Dashboard, err: anyhow::Error) -> HttpResponse {
+    error!("Error: {err:?}");
+    dashboard.page(StatusCode::INTERNAL_SERVER_ERROR, "Error", "<p>Something went wrong, see the logs.</p>")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn url_encode(segment: &str) -> String {
+    url::form_urlencoded::byte_serialize(segment.as_bytes()).collect()
+}
+
+// UTC, eg. "2023-05-02 17:49:15 UTC"
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC", secs_of_day / 3600, secs_of_day % 3600 / 60, secs_of_day % 60)
+}
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 0; color: #1f2328; }
+nav { display: flex; gap: 1em; align-items: center; padding: 0.75em 1.5em; background: #24292f; }
+nav a { color: #fff; text-decoration: none; }
+nav form { margin-left: auto; }
+main { padding: 0 1.5em 2em; }
+table { border-collapse: collapse; width: 100%; font-size: 0.9em; }
+th, td { text-align: left; padding: 0.4em 0.6em; border-bottom: 1px solid #d0d7de; vertical-align: top; }
+.badge { padding: 0.1em 0.5em; border-radius: 1em; font-size: 0.85em; color: #fff; }
+.badge.pending { background: #9a6700; }
+.badge.done { background: #1a7f37; }
+.badge.dead { background: #cf222e; }
+.timeline li { margin-bottom: 0.5em; }
+.muted { color: #656d76; }
+.error { color: #cf222e; }
+";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        body::to_bytes,
+        test::{call_service, init_service, TestRequest},
+        App,
+    };
+
+    use crate::store::{InboxOutcome, MemoryStore, NewInboxMessage};
+
+    const READ_TOKEN: &str = "read-token-3b6f0c";
+    const WRITE_TOKEN: &str = "write-token-91d2aa";
+
+    fn event(event_type: &str, invoice_id: &str) -> NewInboxMessage {
+        NewInboxMessage {
+            provider: "btcpay".to_string(),
+            event_type: Some(event_type.to_string()),
+            delivery_id: None,
+            invoice_id: Some(invoice_id.to_string()),
+            payment_hash: None,
+            verified: true,
+            headers: serde_json::json!({}),
+            body: "{}".to_string(),
+        }
+    }
+
+    fn tokens() -> AdminTokens {
+        AdminTokens::new()
+            .with_token("dashboard", READ_TOKEN, &[AdminScope::Read])
+            .with_token("ops", WRITE_TOKEN, &[AdminScope::Write])
+    }
+
+    fn signed_in(req: TestRequest, token: &str) -> actix_http::Request {
+        let session = session(tokens().lookup(token).unwrap(), SystemTime::now() + SESSION_TTL);
+        req.cookie(Cookie::new(COOKIE_NAME, session)).to_request()
+    }
+
+    async fn body_text(response: actix_web::dev::ServiceResponse) -> String {
+        String::from_utf8(to_bytes(response.into_body()).await.unwrap().to_vec()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn test_dashboard_pages() {
+        let store = Arc::new(MemoryStore::new());
+        for event_type in ["InvoiceCreated", "InvoiceProcessing", "InvoiceSettled"] {
+            store.inbox_push(event(event_type, "6wmoR7p5UFVzCYuwyiViKX")).await.unwrap();
+        }
+        let dead = store.inbox_push(event("InvoiceExpired", "<script>")).await.unwrap();
+        store.inbox_finish(dead, InboxOutcome::Dead { error: "missing posData".to_string() }).await.unwrap();
+        store.log_verification_failure("btcpay", "bad_signature", 1).await.unwrap();
+
+        let app = init_service(App::new().service(scope("/dashboard", store.clone(), tokens()))).await;
+
+        let response = call_service(&app, TestRequest::get().uri("/dashboard").to_request()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers().get(header::LOCATION).unwrap(), "/dashboard/login");
+
+        let response = call_service(&app, TestRequest::post().uri("/dashboard/login").set_form([("token", "guess")]).to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = call_service(&app, TestRequest::post().uri("/dashboard/login").set_form([("token", READ_TOKEN)]).to_request()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let cookie = response.response().cookies().next().unwrap().into_owned();
+        assert!(cookie.value().starts_with("dashboard.") && !cookie.value().contains(READ_TOKEN));
+        assert!(cookie.http_only().unwrap());
+        let response = call_service(&app, TestRequest::get().uri("/dashboard").cookie(cookie).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = call_service(&app, signed_in(TestRequest::get().uri("/dashboard"), READ_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let html = body_text(response).await;
+        assert!(html.contains("<td>bad_signature</td><td>1</td>"));
+        assert!(html.contains(r#"<span class="badge dead">dead</span>"#));
+        assert!(html.contains("&lt;script&gt;") && !html.contains("<script>"));
+
+        let response = call_service(&app, signed_in(TestRequest::get().uri("/dashboard/invoices/6wmoR7p5UFVzCYuwyiViKX"), READ_TOKEN)).await;
+        let html = body_text(response).await;
+        let positions: Vec<usize> = ["InvoiceCreated", "InvoiceProcessing", "InvoiceSettled"].iter()
+            .map(|event_type| html.find(event_type).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Read tokens see dead-letters, but cannot retry them
+        let html = body_text(call_service(&app, signed_in(TestRequest::get().uri("/dashboard/dead-letters"), READ_TOKEN)).await).await;
+        assert!(html.contains("missing posData") && html.contains("<button disabled>Retry</button>"));
+
+        let retry = format!("/dashboard/deliveries/{dead}/retry");
+        let response = call_service(&app, signed_in(TestRequest::post().uri(&retry), READ_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(store.inbox_status(dead).await.unwrap(), Some(InboxStatus::Dead));
+
+        let response = call_service(&app, signed_in(TestRequest::post().uri(&retry), WRITE_TOKEN)).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(store.inbox_status(dead).await.unwrap(), Some(InboxStatus::Pending));
+    }
+
+    #[test]
+    fn test_sessions() {
+        let dashboard = Dashboard { path: "/dashboard".to_string(), store: Arc::new(MemoryStore::new()), tokens: tokens() };
+        let ops = tokens().lookup(WRITE_TOKEN).unwrap().clone();
+        let now = SystemTime::now();
+
+        let valid = session(&ops, now + SESSION_TTL);
+        assert_eq!(dashboard.session_token(&valid, now).map(AdminToken::name), Some("ops"));
+        assert!(dashboard.session_token(&valid, now + SESSION_TTL).is_none());
+
+        // The token itself, another token's name or a changed expiry are all refused
+        assert!(dashboard.session_token(WRITE_TOKEN, now).is_none());
+        assert!(dashboard.session_token(&valid.replacen("ops", "dashboard", 1), now).is_none());
+        let (_, signature) = valid.rsplit_once('.').unwrap();
+        let extended = format!("ops.{}.{signature}", (now + SESSION_TTL * 2).duration_since(UNIX_EPOCH).unwrap().as_secs());
+        assert!(dashboard.session_token(&extended, now).is_none());
+
+        // Sessions end with their token
+        let revoked = Dashboard { tokens: AdminTokens::new().with_token("ops", "rotated", &[AdminScope::Write]), ..dashboard };
+        assert!(revoked.session_token(&valid, now).is_none());
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(1683049755)), "2023-05-02 17:49:15 UTC");
+        assert_eq!(format_time(UNIX_EPOCH + Duration::from_secs(951782400)), "2000-02-29 00:00:00 UTC");
+    }
+}
@@ -1,8 +1,13 @@
-use actix_web::{
-    error,
-    http::{header::ContentType, StatusCode}, HttpResponse,
-};
+// Handler errors and problem details, shared by the actix, tower and axum adapters. Statuses are
+// `http` 1.x, and converted for actix, which is still on `http` 0.2.
+
+#[cfg(feature = "actix")]
+use actix_web::{error, http::header::CONTENT_TYPE, HttpResponse};
+use bytes::Bytes;
 use derive_more::{Display, Error};
+use http::{Response, StatusCode};
+use serde::Serialize;
+use std::fmt;
 
 #[derive(Debug, Display, Error)]
 pub enum ServiceError {
@@ -16,14 +21,8 @@ pub enum ServiceError {
     Timeout,
 }
 
-impl error::ResponseError for ServiceError {
-    fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::html())
-            .body(self.to_string())
-    }
-
-    fn status_code(&self) -> StatusCode {
+impl ServiceError {
+    pub fn status(&self) -> StatusCode {
         match *self {
             ServiceError::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
             ServiceError::BadClientData => StatusCode::BAD_REQUEST,
@@ -31,3 +30,238 @@ impl error::ResponseError for ServiceError {
         }
     }
 }
+
+#[cfg(feature = "actix")]
+impl error::ResponseError for ServiceError {
+    fn error_response(&self) -> HttpResponse {
+        problem_response(self.status(), None)
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        actix_status(self.status())
+    }
+}
+
+/// Why a webhook handler failed, which decides whether the provider (or inbox) should try again
+#[derive(Debug)]
+pub enum HandlerError {
+    /// A temporary failure, eg. the database or an upstream API is down. Answered with a 503 so the
+    /// provider redelivers, and retried by the inbox.
+    Retryable(anyhow::Error),
+    /// The event can never be processed, eg. its invoice is missing required metadata. Acknowledged
+    /// with a 2xx so it is not redelivered, and dead-lettered by the inbox.
+    Permanent(anyhow::Error),
+    /// The request itself is unacceptable, answered with the given 4xx status
+    Rejected { status: StatusCode, detail: String },
+}
+
+pub type HandlerResult<T = ()> = std::result::Result<T, HandlerError>;
+
+impl HandlerError {
+    pub fn permanent<E: Into<anyhow::Error>>(err: E) -> Self {
+        HandlerError::Permanent(err.into())
+    }
+
+    /// `status` is an `http` 1.x status code, eg. `http::StatusCode::UNPROCESSABLE_ENTITY`
+    pub fn rejected(status: StatusCode, detail: impl Into<String>) -> Self {
+        HandlerError::Rejected { status, detail: detail.into() }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, HandlerError::Retryable(_))
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            HandlerError::Retryable(_) => "retryable",
+            HandlerError::Permanent(_) => "permanent",
+            HandlerError::Rejected { .. } => "rejected",
+        }
+    }
+
+    /// 503 for retryable errors so the provider redelivers, 200 for permanent ones so it does not
+    pub fn status(&self) -> StatusCode {
+        match self {
+            HandlerError::Retryable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            HandlerError::Permanent(_) => StatusCode::OK,
+            HandlerError::Rejected { status, .. } => *status,
+        }
+    }
+
+    /// The response body, if any. Internal error details are never included.
+    pub fn problem(&self) -> Option<Problem> {
+        match self {
+            HandlerError::Permanent(_) => None,
+            HandlerError::Rejected { status, detail } => Some(Problem::new(*status, Some(detail))),
+            HandlerError::Retryable(_) => Some(Problem::new(self.status(), None)),
+        }
+    }
+}
+
+// Errors are retryable unless marked otherwise. `ServiceError::BadClientData` is the exception, as
+// handlers use it for events with missing or malformed data, which will never succeed.
+impl<E: Into<anyhow::Error>> From<E> for HandlerError {
+    fn from(err: E) -> Self {
+        let err = err.into();
+        match err.downcast_ref::<ServiceError>() {
+            Some(ServiceError::BadClientData) => HandlerError::Permanent(err),
+            _ => HandlerError::Retryable(err),
+        }
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandlerError::Retryable(err) => write!(f, "{err:#}"),
+            HandlerError::Permanent(err) => write!(f, "permanent failure: {err:#}"),
+            HandlerError::Rejected { status, detail } => write!(f, "rejected with {status}: {detail}"),
+        }
+    }
+}
+
+#[cfg(feature = "actix")]
+impl error::ResponseError for HandlerError {
+    fn error_response(&self) -> HttpResponse {
+        match self.problem() {
+            Some(problem) => problem_response(self.status(), problem.detail.as_deref()),
+            None => HttpResponse::build(self.status_code()).finish(),
+        }
+    }
+
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        actix_status(self.status())
+    }
+}
+
+/// Classify the error of a `Result` for a handler, eg. `invoice.metadata.ok_or(..).permanent()?`
+pub trait HandlerResultExt<T> {
+    fn permanent(self) -> HandlerResult<T>;
+    fn retryable(self) -> HandlerResult<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> HandlerResultExt<T> for std::result::Result<T, E> {
+    fn permanent(self) -> HandlerResult<T> {
+        self.map_err(HandlerError::permanent)
+    }
+
+    fn retryable(self) -> HandlerResult<T> {
+        self.map_err(|err| HandlerError::Retryable(err.into()))
+    }
+}
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An RFC 9457 problem details body
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Problem {
+    /// `status` is an `http` or actix status code
+    pub fn new(status: impl Into<u16>, detail: Option<&str>) -> Self {
+        let status = StatusCode::from_u16(status.into()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        Problem {
+            problem_type: "about:blank".to_string(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: detail.map(str::to_string),
+        }
+    }
+}
+
+/// A response with an `application/problem+json` body. Internal error details are never included.
+#[cfg(feature = "actix")]
+pub fn problem_response(status: impl Into<u16>, detail: Option<&str>) -> HttpResponse {
+    let problem = Problem::new(status, detail);
+    HttpResponse::build(actix_status(problem.status))
+        .insert_header((CONTENT_TYPE, PROBLEM_JSON))
+        .json(problem)
+}
+
+/// `problem_response` for any framework built on `http`
+pub fn problem_http_response(status: StatusCode, detail: Option<&str>) -> Response<Bytes> {
+    let body = serde_json::to_vec(&Problem::new(status, detail)).unwrap_or_default();
+    let mut response = Response::new(Bytes::from(body));
+    *response.status_mut() = status;
+    response.headers_mut().insert(http::header::CONTENT_TYPE, http::HeaderValue::from_static(PROBLEM_JSON));
+    response
+}
+
+/// The response for a handler's result: an empty 200, or the error's status and problem details
+pub fn handler_response(result: &HandlerResult) -> Response<Bytes> {
+    match result.as_ref().map_err(|err| (err.status(), err.problem())) {
+        Err((status, Some(problem))) => problem_http_response(status, problem.detail.as_deref()),
+        Err((status, None)) => status_response(status),
+        Ok(()) => status_response(StatusCode::OK),
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Bytes> {
+    let mut response = Response::new(Bytes::new());
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(feature = "actix")]
+pub(crate) fn actix_status(status: impl Into<u16>) -> actix_web::http::StatusCode {
+    actix_web::http::StatusCode::from_u16(status.into()).unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classification() {
+        let err: HandlerError = anyhow::anyhow!("connection refused").into();
+        assert!(err.is_retryable());
+
+        let err: HandlerError = anyhow::Error::from(ServiceError::BadClientData).into();
+        assert_eq!(err.kind(), "permanent");
+
+        let err = None::<()>.ok_or(ServiceError::InternalError).permanent().unwrap_err();
+        assert_eq!(err.kind(), "permanent");
+    }
+
+    #[cfg(feature = "actix")]
+    #[actix_web::test]
+    async fn test_problem_responses() {
+        use actix_web::{body::to_bytes, http::StatusCode, ResponseError};
+
+        let response = HandlerError::Retryable(anyhow::anyhow!("password=secret")).error_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/problem+json");
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, r#"{"type":"about:blank","title":"Service Unavailable","status":503}"#);
+
+        let response = HandlerError::rejected(http::StatusCode::UNPROCESSABLE_ENTITY, "unknown store").error_response();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, r#"{"type":"about:blank","title":"Unprocessable Entity","status":422,"detail":"unknown store"}"#);
+
+        let response = HandlerError::permanent(anyhow::anyhow!("missing posData")).error_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // The same statuses and bodies as the actix responses above
+    #[test]
+    fn test_handler_responses() {
+        let response = handler_response(&Err(HandlerError::Retryable(anyhow::anyhow!("password=secret"))));
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["content-type"], PROBLEM_JSON);
+        assert_eq!(response.body(), r#"{"type":"about:blank","title":"Service Unavailable","status":503}"#);
+
+        let response = handler_response(&Err(HandlerError::rejected(StatusCode::UNPROCESSABLE_ENTITY, "unknown store")));
+        assert_eq!(response.body(), r#"{"type":"about:blank","title":"Unprocessable Entity","status":422,"detail":"unknown store"}"#);
+
+        let response = handler_response(&Err(HandlerError::permanent(anyhow::anyhow!("missing posData"))));
+        assert_eq!((response.status(), response.body().len()), (StatusCode::OK, 0));
+        assert_eq!(handler_response(&Ok(())).status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{anyhow, Result};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "alby")]
+use crate::alby;
+#[cfg(feature = "btcpay")]
+use crate::btcpay;
+#[cfg(feature = "lnbits")]
+use crate::lnbits;
+#[cfg(feature = "opennode")]
+use crate::opennode;
+#[cfg(feature = "phoenixd")]
+use crate::phoenixd;
+#[cfg(feature = "strike")]
+use crate::strike;
+#[cfg(feature = "zbd")]
+use crate::zbd;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    BTCPay,
+    LNbits,
+    OpenNode,
+    Strike,
+    Alby,
+    Phoenixd,
+    Zbd,
+}
+
+impl Provider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Provider::BTCPay => "btcpay",
+            Provider::LNbits => "lnbits",
+            Provider::OpenNode => "opennode",
+            Provider::Strike => "strike",
+            Provider::Alby => "alby",
+            Provider::Phoenixd => "phoenixd",
+            Provider::Zbd => "zbd",
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Provider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "btcpay" => Ok(Provider::BTCPay),
+            "lnbits" => Ok(Provider::LNbits),
+            "opennode" => Ok(Provider::OpenNode),
+            "strike" => Ok(Provider::Strike),
+            "alby" => Ok(Provider::Alby),
+            "phoenixd" => Ok(Provider::Phoenixd),
+            "zbd" => Ok(Provider::Zbd),
+            _ => Err(anyhow!("unknown provider: {s}")),
+        }
+    }
+}
+
+/// Provider independent event types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EventType {
+    #[serde(rename = "invoice.created")]
+    InvoiceCreated,
+    #[serde(rename = "invoice.payment_received")]
+    PaymentReceived,
+    #[serde(rename = "invoice.payment_settled")]
+    PaymentSettled,
+    #[serde(rename = "invoice.processing")]
+    InvoiceProcessing,
+    #[serde(rename = "invoice.settled")]
+    InvoiceSettled,
+    #[serde(rename = "invoice.expired")]
+    InvoiceExpired,
+    #[serde(rename = "invoice.invalid")]
+    InvoiceInvalid,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::InvoiceCreated => "invoice.created",
+            EventType::PaymentReceived => "invoice.payment_received",
+            EventType::PaymentSettled => "invoice.payment_settled",
+            EventType::InvoiceProcessing => "invoice.processing",
+            EventType::InvoiceSettled => "invoice.settled",
+            EventType::InvoiceExpired => "invoice.expired",
+            EventType::InvoiceInvalid => "invoice.invalid",
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        serde_json::from_value(Value::String(s.to_string()))
+            .map_err(|_| anyhow!("unknown event type: {s}"))
+    }
+}
+
+/// A payment event in a provider independent shape, suitable for forwarding to other services. Only
+/// incoming payments are covered, so outgoing payments and withdrawals normalize to `None`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizedEvent {
+    /// Provider unique id for the event. Redeliveries keep the same id.
+    pub id: String,
+    pub provider: Provider,
+    #[serde(rename = "type")]
+    pub event_type: EventType,
+    /// BTCPay store id, LNbits wallet id, or the `receiverId` of a Strike invoice
+    pub store_id: Option<String>,
+    pub invoice_id: Option<String>,
+    pub payment_hash: Option<String>,
+    pub amount_msat: Option<u64>,
+    /// Unix timestamp in seconds
+    pub timestamp: Option<i64>,
+    /// BTCPay invoice metadata, or LNbits `extra`
+    pub metadata: Value,
+}
+
+/// Parse a raw webhook body from a provider into a normalized event.
+/// Returns `None` for event types that have no normalized form, and an error for providers whose
+/// feature is not enabled.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip(body), fields(%provider, event_type, invoice_id, payment_hash)))]
+pub fn normalize(provider: Provider, body: &str) -> Result<Option<NormalizedEvent>> {
+    let event = parse(provider, body)?;
+
+    #[cfg(feature = "tracing")]
+    if let Some(event) = &event {
+        let span = tracing::Span::current();
+        span.record("event_type", event.event_type.as_str());
+        span.record("invoice_id", event.invoice_id.as_deref());
+        span.record("payment_hash", event.payment_hash.as_deref());
+    }
+
+    Ok(event)
+}
+
+fn parse(provider: Provider, body: &str) -> Result<Option<NormalizedEvent>> {
+    match provider {
+        #[cfg(feature = "btcpay")]
+        Provider::BTCPay => Ok(serde_json::from_str::<btcpay::WebhookPayload>(body)?.normalize()),
+        #[cfg(feature = "lnbits")]
+        Provider::LNbits => Ok(serde_json::from_str::<lnbits::lnbits_models::WebhookPayload>(body)?.normalize()),
+        #[cfg(feature = "opennode")]
+        Provider::OpenNode => Ok(opennode::WebhookPayload::parse(body)?.normalize()),
+        // Strike events only name the entity, see `StrikeClient::fetch_event`
+        #[cfg(feature = "strike")]
+        Provider::Strike => serde_json::from_str::<strike::WebhookEvent>(body).map(|_| None).map_err(Into::into),
+        #[cfg(feature = "alby")]
+        Provider::Alby => Ok(serde_json::from_str::<alby::Invoice>(body)?.normalize()),
+        #[cfg(feature = "phoenixd")]
+        Provider::Phoenixd => Ok(serde_json::from_str::<phoenixd::WebhookPayload>(body)?.normalize()),
+        // Callbacks are unsigned, so this is only as trustworthy as the body; see `zbd::ZbdConfirm`
+        #[cfg(feature = "zbd")]
+        Provider::Zbd => Ok(serde_json::from_str::<zbd::Callback>(body)?.normalize()),
+        #[allow(unreachable_patterns)]
+        _ => {
+            let _ = body;
+            Err(anyhow!("the {provider} feature is not enabled"))
+        },
+    }
+}
+
+/// Unix seconds from an RFC 3339 timestamp, eg. `2023-05-02T17:49:15.2686637+00:00`, or `None` if any field is out of range
+pub fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let (date, time) = timestamp.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    // RFC 3339 years have four digits
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+
+    // Split off the offset, `Z` or eg. `+01:00`
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(index) => time.split_at(index),
+        None => (time, "Z"),
+    };
+    let offset = match offset.as_bytes().first()? {
+        b'Z' | b'z' => 0,
+        sign => {
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let (hours, minutes) = (hours.parse::<i64>().ok()?, minutes.parse::<i64>().ok()?);
+            if !(0..24).contains(&hours) || !(0..60).contains(&minutes) {
+                return None;
+            }
+            let offset = hours * 3600 + minutes * 60;
+            if *sign == b'-' { -offset } else { offset }
+        },
+    };
+
+    let mut time = time.split('.').next()?.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    // 60 for a leap second
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..=60).contains(&second) {
+        return None;
+    }
+
+    // Days since the epoch from a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year.checked_sub(1)? } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era.checked_mul(146097)?.checked_add(doe - 719468)?;
+
+    days.checked_mul(86400)?.checked_add(hour * 3600 + minute * 60 + second - offset)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_timestamp("2023-05-02T17:49:15.123Z"), Some(1683049755));
+        assert_eq!(parse_timestamp("2023-05-02T12:49:15-05:00"), Some(1683049755));
+        assert_eq!(parse_timestamp("2024-02-29T00:00:00+00:00"), Some(1709164800));
+        assert_eq!(parse_timestamp("yesterday"), None);
+
+        assert_eq!(parse_timestamp("2023-13-45T99:99:99Z"), None);
+        assert_eq!(parse_timestamp("2023-02-29T00:00:00Z"), None);
+        assert_eq!(parse_timestamp("2023-05-02T24:00:00Z"), None);
+        assert_eq!(parse_timestamp("2023-05-02T17:60:00Z"), None);
+        assert_eq!(parse_timestamp("2023-05-02T17:49:15+99:00"), None);
+        assert_eq!(parse_timestamp("2023-05-00T17:49:15Z"), None);
+        assert_eq!(parse_timestamp("9223372036854775807-12-31T23:59:59Z"), None);
+        assert_eq!(parse_timestamp("-9223372036854775808-01-01T00:00:00Z"), None);
+        assert_eq!(parse_timestamp("10000-01-01T00:00:00Z"), None);
+        assert_eq!(parse_timestamp("9999-12-31T23:59:59Z"), Some(253402300799));
+    }
+}
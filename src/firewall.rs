@@ -0,0 +1,428 @@
+// Middleware that turns away requests by source before anything reads the body: an allowlist of
+// CIDR ranges and token bucket rate limits per client IP. The client IP is taken from
+// `X-Forwarded-For` only when the request came through a trusted proxy.
+
+use actix_web::{
+    body::EitherBody,
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::{header, StatusCode},
+    Error, HttpRequest,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{ready, Ready},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    rc::Rc,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::error::problem_response;
+use crate::metrics;
+
+// At most this many sources are tracked, so floods from many addresses cannot grow the map
+// without bound. Once full, the least recently seen are dropped a batch at a time, which keeps
+// the cost of a spray of new addresses linear.
+const MAX_TRACKED_SOURCES: usize = 10_000;
+const EVICTION_BATCH: usize = MAX_TRACKED_SOURCES / 10;
+
+/// An IP network, eg. `10.0.0.0/8` or `2001:db8::/32`. A bare address is a single host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => masked(u32::from(net).into(), self.prefix, 32) == masked(u32::from(ip).into(), self.prefix, 32),
+            (IpAddr::V6(net), IpAddr::V6(ip)) => masked(u128::from(net), self.prefix, 128) == masked(u128::from(ip), self.prefix, 128),
+            _ => false,
+        }
+    }
+}
+
+fn masked(bits: u128, prefix: u8, width: u32) -> u128 {
+    match prefix {
+        0 => 0,
+        prefix => bits >> (width - u32::from(prefix)),
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+
+        let addr = IpAddr::from_str(addr.trim())
+            .with_context(|| format!("invalid address in {s}"))?
+            .to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|prefix| *prefix <= max)
+                .ok_or_else(|| anyhow!("invalid prefix length in {s}"))?,
+            None => max,
+        };
+
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Token bucket settings: up to `burst` requests at once, refilled at `per_second`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, per_second: f64) -> Result<Self> {
+        if burst == 0 || !(per_second > 0.0 && per_second.is_finite()) {
+            bail!("rate limits need a burst of at least 1 and a positive rate");
+        }
+        Ok(RateLimit { burst, per_second })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(f64::from(limit.burst));
+        self.updated = now;
+    }
+
+    // Takes a token, or says how long until one is available
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / limit.per_second))
+        }
+    }
+}
+
+/// Source checks for a scope, eg.
+///
+/// ```ignore
+/// web::scope("/btcpay")
+///     .wrap(btcpay_middleware::BTCPayHeaderVerify)
+///     .wrap(Firewall::new().allow("10.0.0.0/8".parse()?).trust_proxy("10.0.0.2".parse()?).rate_limit(RateLimit::new(20, 5.0)?))
+/// ```
+///
+/// Wrapped last, it runs before verification, so rejected requests are never buffered or hashed.
+/// Clones share their buckets, so build one per route and clone it into each worker.
+#[derive(Clone, Default)]
+pub struct Firewall {
+    allow: Vec<Cidr>,
+    trusted_proxies: Vec<Cidr>,
+    rate_limit: Option<RateLimit>,
+    buckets: Arc<Mutex<HashMap<IpAddr, Bucket>>>,
+}
+
+impl Firewall {
+    pub fn new() -> Self {
+        Firewall::default()
+    }
+
+    /// Only accept clients in `cidr`. Without any, every client is accepted.
+    pub fn allow(mut self, cidr: Cidr) -> Self {
+        self.allow.push(cidr);
+        self
+    }
+
+    /// Believe `X-Forwarded-For` from proxies in `cidr`
+    pub fn trust_proxy(mut self, cidr: Cidr) -> Self {
+        self.trusted_proxies.push(cidr);
+        self
+    }
+
+    /// Limit each client IP, on its own token bucket
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|cidr| cidr.contains(ip))
+    }
+
+    /// The client's IP. `X-Forwarded-For` is read right to left, skipping trusted proxies, so a
+    /// client cannot pick its address by sending the header itself.
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let mut client = req.peer_addr()?.ip().to_canonical();
+
+        if !self.is_trusted(client) {
+            return Some(client);
+        }
+
+        let forwarded: Vec<&str> = req.headers().get_all("x-forwarded-for")
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+
+        for hop in forwarded.iter().rev() {
+            // Some proxies include the port
+            let ip = match hop.parse::<IpAddr>().or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip())) {
+                Ok(ip) => ip.to_canonical(),
+                Err(_) => break,
+            };
+            client = ip;
+            if !self.is_trusted(ip) {
+                break;
+            }
+        }
+
+        Some(client)
+    }
+
+    fn check(&self, req: &HttpRequest) -> Result<(), Blocked> {
+        let ip = self.client_ip(req);
+
+        if !self.allow.is_empty() && !ip.is_some_and(|ip| self.allow.iter().any(|cidr| cidr.contains(ip))) {
+            debug!("Blocked request from {}, not in the allowlist", ip.map(|ip| ip.to_string()).unwrap_or_default());
+            return Err(Blocked::ForbiddenSource);
+        }
+
+        let limit = match &self.rate_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let ip = ip.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_SOURCES && !buckets.contains_key(&ip) {
+            evict_least_recent(&mut buckets, EVICTION_BATCH);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket { tokens: f64::from(limit.burst), updated: now });
+        bucket.take(limit, now).map_err(|wait| {
+            debug!("Rate limited {ip}");
+            Blocked::RateLimited(wait)
+        })
+    }
+}
+
+// Drop the `count` buckets updated longest ago
+fn evict_least_recent(buckets: &mut HashMap<IpAddr, Bucket>, count: usize) {
+    let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+    if count == 0 || updated.is_empty() {
+        return;
+    }
+    let index = count.min(updated.len()) - 1;
+    let cutoff = *updated.select_nth_unstable(index).1;
+
+    let mut evicted = 0;
+    buckets.retain(|_, bucket| {
+        let evict = evicted < count && bucket.updated <= cutoff;
+        evicted += usize::from(evict);
+        !evict
+    });
+}
+
+enum Blocked {
+    ForbiddenSource,
+    RateLimited(Duration),
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for Firewall
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = FirewallMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(FirewallMiddleware {
+            service: Rc::new(service),
+            firewall: self.clone(),
+        }))
+    }
+}
+
+pub struct FirewallMiddleware<S> {
+    service: Rc<S>,
+    firewall: Firewall,
+}
+
+impl<S, B> Service<ServiceRequest> for FirewallMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let blocked = match self.firewall.check(req.request()) {
+            Ok(()) => {
+                let svc = self.service.clone();
+                return Box::pin(async move { Ok(svc.call(req).await?.map_into_left_body()) });
+            },
+            Err(blocked) => blocked,
+        };
+
+        let response = match blocked {
+            Blocked::ForbiddenSource => {
+                metrics::request_blocked("forbidden_source");
+                problem_response(StatusCode::FORBIDDEN, None)
+            },
+            Blocked::RateLimited(wait) => {
+                metrics::request_blocked("rate_limited");
+                let mut response = problem_response(StatusCode::TOO_MANY_REQUESTS, None);
+                // Whole seconds, rounded up so a client that waits gets a token
+                let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                response.headers_mut().insert(header::RETRY_AFTER, retry_after.max(1).into());
+                response
+            },
+        };
+
+        let (request, _pl) = req.into_parts();
+        Box::pin(ready(Ok(ServiceResponse::new(request, response.map_into_right_body()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    fn from(peer: &str) -> TestRequest {
+        TestRequest::post().uri("/btcpay/webhook").peer_addr(SocketAddr::new(peer.parse().unwrap(), 40000))
+    }
+
+    #[test]
+    fn test_cidr() {
+        let net: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(net.contains("10.1.255.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        // IPv4 clients on a dual stack socket
+        assert!(net.contains("::ffff:10.1.0.9".parse().unwrap()));
+
+        let net: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(net.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!net.contains("10.1.0.1".parse().unwrap()));
+
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains("203.0.113.7".parse().unwrap()));
+        assert_eq!("192.0.2.1".parse::<Cidr>().unwrap().to_string(), "192.0.2.1/32");
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("btcpay.example.com/24".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let firewall = Firewall::new().trust_proxy("10.0.0.0/8".parse().unwrap());
+        let client_ip = |req: TestRequest| firewall.client_ip(&req.to_http_request()).unwrap().to_string();
+
+        // Untrusted peers cannot choose their address
+        assert_eq!(client_ip(from("203.0.113.7").insert_header(("x-forwarded-for", "198.51.100.1"))), "203.0.113.7");
+
+        assert_eq!(client_ip(from("10.0.0.2").insert_header(("x-forwarded-for", "198.51.100.1"))), "198.51.100.1");
+        // A spoofed first hop is skipped, as the nearest untrusted hop was added by our proxy
+        assert_eq!(client_ip(from("10.0.0.2").insert_header(("x-forwarded-for", "192.0.2.9, 198.51.100.1, 10.0.0.3"))), "198.51.100.1");
+        assert_eq!(client_ip(from("10.0.0.2").insert_header(("x-forwarded-for", "198.51.100.1:5123"))), "198.51.100.1");
+        assert_eq!(client_ip(from("10.0.0.2")), "10.0.0.2");
+    }
+
+    #[test]
+    fn test_bucket_refills() {
+        let limit = RateLimit::new(2, 1.0).unwrap();
+        let start = Instant::now();
+        let mut bucket = Bucket { tokens: 2.0, updated: start };
+
+        assert!(bucket.take(&limit, start).is_ok());
+        assert!(bucket.take(&limit, start).is_ok());
+        assert_eq!(bucket.take(&limit, start), Err(Duration::from_secs(1)));
+        assert!(bucket.take(&limit, start + Duration::from_millis(1500)).is_ok());
+        assert!(bucket.take(&limit, start + Duration::from_millis(1500)).is_err());
+
+        assert!(RateLimit::new(0, 1.0).is_err());
+        assert!(RateLimit::new(5, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_tracked_sources_are_capped() {
+        let firewall = Firewall::new().rate_limit(RateLimit::new(2, 1.0).unwrap());
+        let start = Instant::now();
+        let ip = |i: usize| IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i as u32));
+
+        // Every source is mid-burst, so none could be dropped for being idle
+        firewall.buckets.lock().unwrap().extend((0..MAX_TRACKED_SOURCES).map(|i| {
+            (ip(i), Bucket { tokens: 1.0, updated: start + Duration::from_millis(i as u64) })
+        }));
+
+        let newcomer = from("203.0.113.7").to_http_request();
+        assert!(firewall.check(&newcomer).is_ok());
+
+        let buckets = firewall.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_TRACKED_SOURCES - EVICTION_BATCH + 1);
+        assert!(!buckets.contains_key(&ip(0)) && !buckets.contains_key(&ip(EVICTION_BATCH - 1)));
+        assert!(buckets.contains_key(&ip(EVICTION_BATCH)));
+    }
+
+    #[actix_web::test]
+    async fn test_blocks_before_handler() {
+        let firewall = Firewall::new()
+            .allow("203.0.113.0/24".parse().unwrap())
+            .trust_proxy("10.0.0.2".parse().unwrap())
+            .rate_limit(RateLimit::new(2, 0.5).unwrap());
+
+        let app = init_service(App::new().service(
+            web::scope("/btcpay")
+                .wrap(firewall)
+                .route("/webhook", web::post().to(HttpResponse::Ok)),
+        )).await;
+
+        let response = call_service(&app, from("198.51.100.1").to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(response.headers().get("content-type").unwrap(), "application/problem+json");
+
+        for _ in 0..2 {
+            let response = call_service(&app, from("203.0.113.7").to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let response = call_service(&app, from("203.0.113.7").to_request()).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "2");
+
+        // Each client has its own bucket, including those behind the proxy
+        let response = call_service(&app, from("10.0.0.2").insert_header(("x-forwarded-for", "203.0.113.8")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,341 @@
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+#[cfg(feature = "postgres")]
+use deadpool_postgres::Pool as PGPool;
+use futures_util::future::join_all;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "btcpay-api")]
+use crate::btcpay::BTCPayClient;
+use crate::inbox::Inbox;
+
+/// A dependency the service needs before it can take traffic
+#[async_trait]
+pub trait HealthCheck: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    /// Returns details to report (or `Value::Null`), or an error when the dependency is unhealthy
+    async fn check(&self) -> Result<Value>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckReport {
+    pub status: CheckStatus,
+    pub duration_ms: u64,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub details: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The set of checks behind `/health/ready`. Checks run concurrently, each with its own timeout.
+#[derive(Clone)]
+pub struct Readiness {
+    checks: Vec<Arc<dyn HealthCheck>>,
+    timeout: Duration,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Readiness {
+            checks: Vec::new(),
+            timeout: Duration::from_secs(2),
+        }
+    }
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Readiness::default()
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_check(mut self, check: impl HealthCheck) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// Run every check, returning whether all are up and a report per check name
+    pub async fn run(&self) -> (CheckStatus, Map<String, Value>) {
+        let reports = join_all(self.checks.iter().map(|check| async move {
+            let started = Instant::now();
+            let result = tokio::time::timeout(self.timeout, check.check()).await;
+            let duration_ms = started.elapsed().as_millis() as u64;
+
+            let report = match result {
+                Ok(Ok(details)) => CheckReport { status: CheckStatus::Up, duration_ms, details, error: None },
+                Ok(Err(err)) => CheckReport { status: CheckStatus::Down, duration_ms, details: Value::Null, error: Some(format!("{err:#}")) },
+                Err(_) => CheckReport { status: CheckStatus::Down, duration_ms, details: Value::Null, error: Some(format!("timed out after {:?}", self.timeout)) },
+            };
+
+            if report.status == CheckStatus::Down {
+                warn!("Readiness check {} failed: {}", check.name(), report.error.as_deref().unwrap_or_default());
+            }
+
+            (check.name().to_string(), report)
+        })).await;
+
+        let status = match reports.iter().all(|(_, report)| report.status == CheckStatus::Up) {
+            true => CheckStatus::Up,
+            false => CheckStatus::Down,
+        };
+
+        let reports = reports.into_iter()
+            .map(|(name, report)| (name, json!(report)))
+            .collect();
+
+        (status, reports)
+    }
+}
+
+/// Runs `SELECT 1` on a pooled connection
+#[cfg(feature = "postgres")]
+pub struct PostgresCheck {
+    pg_pool: PGPool,
+}
+
+#[cfg(feature = "postgres")]
+impl PostgresCheck {
+    pub fn new(pg_pool: PGPool) -> Self {
+        PostgresCheck { pg_pool }
+    }
+}
+
+#[cfg(feature = "postgres")]
+#[async_trait]
+impl HealthCheck for PostgresCheck {
+    fn name(&self) -> &str {
+        "postgres"
+    }
+
+    async fn check(&self) -> Result<Value> {
+        let pg_conn = self.pg_pool.get().await?;
+        pg_conn.query_one("SELECT 1", &[]).await?;
+
+        let status = self.pg_pool.status();
+        Ok(json!({"pool_size": status.size, "pool_available": status.available}))
+    }
+}
+
+/// Checks the BTCPay server is synchronized and the API key has not been revoked
+#[cfg(feature = "btcpay-api")]
+pub struct BTCPayCheck {
+    client: BTCPayClient,
+}
+
+#[cfg(feature = "btcpay-api")]
+impl BTCPayCheck {
+    pub fn new(client: BTCPayClient) -> Self {
+        BTCPayCheck { client }
+    }
+}
+
+#[cfg(feature = "btcpay-api")]
+#[async_trait]
+impl HealthCheck for BTCPayCheck {
+    fn name(&self) -> &str {
+        "btcpay"
+    }
+
+    async fn check(&self) -> Result<Value> {
+        if !self.client.health().await? {
+            bail!("BTCPay server is not synchronized");
+        }
+        self.client.validate_api_key().await?;
+
+        Ok(Value::Null)
+    }
+}
+
+/// Checks the LNbits wallet can be read with the invoice (or admin) key
+#[cfg(feature = "lnbits-api")]
+pub struct LNbitsCheck {
+    name: String,
+    host: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "lnbits-api")]
+impl LNbitsCheck {
+    pub fn new(host: &str, api_key: &str) -> Self {
+        LNbitsCheck {
+            name: "lnbits".to_string(),
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Report under another name, eg. one per wallet
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+}
+
+#[cfg(feature = "lnbits-api")]
+#[async_trait]
+impl HealthCheck for LNbitsCheck {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn check(&self) -> Result<Value> {
+        let response = self.client
+            .get(format!("{}/api/v1/wallet", self.host))
+            .header("X-Api-Key", &self.api_key)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            bail!("wallet request failed with {}", response.status());
+        }
+
+        Ok(Value::Null)
+    }
+}
+
+/// Fails when more than `max_pending` webhooks are waiting in the inbox
+pub struct InboxBacklogCheck {
+    inbox: Inbox,
+    max_pending: i64,
+}
+
+impl InboxBacklogCheck {
+    pub fn new(inbox: Inbox, max_pending: i64) -> Self {
+        InboxBacklogCheck { inbox, max_pending }
+    }
+}
+
+#[async_trait]
+impl HealthCheck for InboxBacklogCheck {
+    fn name(&self) -> &str {
+        "inbox"
+    }
+
+    async fn check(&self) -> Result<Value> {
+        let pending = self.inbox.depth().await?;
+
+        if pending > self.max_pending {
+            bail!("{pending} webhooks pending, more than {}", self.max_pending);
+        }
+
+        Ok(json!({"pending": pending}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use actix_web::http::header::HeaderMap;
+    #[cfg(feature = "btcpay-api")]
+    use actix_web::{web, App, HttpResponse, HttpServer};
+
+    struct StaticCheck(&'static str, Option<Duration>, bool);
+
+    #[async_trait]
+    impl HealthCheck for StaticCheck {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        async fn check(&self) -> Result<Value> {
+            if let Some(delay) = self.1 {
+                tokio::time::sleep(delay).await;
+            }
+            match self.2 {
+                true => Ok(Value::Null),
+                false => bail!("unreachable"),
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_readiness_reports_each_check() {
+        let readiness = Readiness::new()
+            .with_timeout(Duration::from_millis(50))
+            .with_check(StaticCheck("up", None, true))
+            .with_check(StaticCheck("down", None, false))
+            .with_check(StaticCheck("slow", Some(Duration::from_secs(5)), true));
+
+        let (status, reports) = readiness.run().await;
+
+        assert_eq!(status, CheckStatus::Down);
+        assert_eq!(reports["up"]["status"], "up");
+        assert_eq!(reports["down"]["status"], "down");
+        assert_eq!(reports["down"]["error"], "unreachable");
+        assert_eq!(reports["slow"]["status"], "down");
+        assert!(reports["slow"]["error"].as_str().unwrap().starts_with("timed out"));
+
+        let (status, _) = Readiness::new().with_check(StaticCheck("up", None, true)).run().await;
+        assert_eq!(status, CheckStatus::Up);
+    }
+
+    #[actix_web::test]
+    async fn test_inbox_backlog_check() {
+        let inbox = Inbox::with_store(Arc::new(MemoryStore::new()));
+        let check = InboxBacklogCheck::new(inbox.clone(), 1);
+
+        inbox.enqueue("btcpay", &HeaderMap::new(), "{}", true).await.unwrap();
+        assert_eq!(check.check().await.unwrap(), json!({"pending": 1}));
+
+        inbox.enqueue("btcpay", &HeaderMap::new(), "{}", true).await.unwrap();
+        assert!(check.check().await.is_err());
+    }
+
+    // Local stand-in for the BTCPay Greenfield API, accepting a single API key
+    #[cfg(feature = "btcpay-api")]
+    fn start_btcpay(synchronized: bool) -> String {
+        let server = HttpServer::new(move || {
+            App::new()
+                .route("/api/v1/health", web::get().to(move || async move {
+                    HttpResponse::Ok().json(json!({"synchronized": synchronized}))
+                }))
+                .route("/api/v1/api-keys/current", web::get().to(|req: actix_web::HttpRequest| async move {
+                    match req.headers().get("Authorization").and_then(|v| v.to_str().ok()) {
+                        Some("token valid") => HttpResponse::Ok().json(json!({"apiKey": "valid"})),
+                        _ => HttpResponse::Unauthorized().finish(),
+                    }
+                }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        url
+    }
+
+    #[cfg(feature = "btcpay-api")]
+    #[actix_web::test]
+    async fn test_btcpay_check() {
+        let host = start_btcpay(true);
+        assert!(BTCPayCheck::new(BTCPayClient::new(&host, "valid")).check().await.is_ok());
+
+        let error = BTCPayCheck::new(BTCPayClient::new(&host, "revoked")).check().await.unwrap_err();
+        assert_eq!(error.to_string(), "API key was rejected");
+
+        let host = start_btcpay(false);
+        let error = BTCPayCheck::new(BTCPayClient::new(&host, "valid")).check().await.unwrap_err();
+        assert_eq!(error.to_string(), "BTCPay server is not synchronized");
+    }
+}
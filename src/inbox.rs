@@ -0,0 +1,437 @@
+use actix_web::{http::{header::HeaderMap, StatusCode}, web, HttpMessage, HttpRequest, HttpResponse, ResponseError, Route};
+use anyhow::Result;
+use async_trait::async_trait;
+#[cfg(feature = "postgres")]
+use deadpool_postgres::Pool as PGPool;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+use crate::error::{HandlerError, HandlerResult, problem_response};
+use crate::metrics::{self, VerifyFailure};
+use crate::store::{InboxOutcome, NewInboxMessage, WebhookStore};
+#[cfg(feature = "postgres")]
+use crate::store::PostgresStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InboxStatus {
+    Pending,
+    Done,
+    Dead,
+}
+
+impl InboxStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InboxStatus::Pending => "pending",
+            InboxStatus::Done => "done",
+            InboxStatus::Dead => "dead",
+        }
+    }
+}
+
+impl FromStr for InboxStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(InboxStatus::Pending),
+            "done" => Ok(InboxStatus::Done),
+            "dead" => Ok(InboxStatus::Dead),
+            _ => Err(anyhow::anyhow!("unknown inbox status: {s}")),
+        }
+    }
+}
+
+/// A stored webhook request, as handed to an `InboxHandler`
+#[derive(Debug, Clone)]
+pub struct InboxMessage {
+    pub id: i64,
+    pub provider: String,
+    pub event_type: Option<String>,
+    pub delivery_id: Option<String>,
+    pub invoice_id: Option<String>,
+    pub payment_hash: Option<String>,
+    /// Whether a verifier accepted the request before it was stored, see `Verified`
+    pub verified: bool,
+    pub headers: Value,
+    pub body: String,
+    /// Number of attempts made before this one
+    pub attempts: i32,
+    pub received_at: SystemTime,
+}
+
+impl InboxMessage {
+    /// Deserialize the raw body into a provider payload, eg. `btcpay::WebhookPayload`
+    pub fn payload<T: DeserializeOwned>(&self) -> Result<T> {
+        Ok(serde_json::from_str(&self.body)?)
+    }
+}
+
+/// Marks a request as verified, or confirmed with the provider, for `route`, which stores it as
+/// `InboxMessage::verified`. Inserted into the request extensions by `BTCPayHeaderVerify` and
+/// `VerifyWebhook`.
+#[derive(Debug, Clone, Copy)]
+pub struct Verified;
+
+/// Business logic run by the inbox workers. A retryable error schedules a retry, while permanent
+/// errors and rejections move the message straight to the dead-letter state.
+#[async_trait]
+pub trait InboxHandler: Send + Sync + 'static {
+    async fn handle(&self, message: &InboxMessage) -> HandlerResult;
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerConfig {
+    /// Number of concurrent workers
+    pub workers: usize,
+    /// Attempts before a message is moved to the dead-letter state
+    pub max_attempts: i32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How long an idle worker waits before polling again
+    pub poll_interval: Duration,
+    /// How long a claimed message is hidden from other workers. Messages still unfinished
+    /// after this (eg. the worker crashed) are retried.
+    pub lease: Duration,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        WorkerConfig {
+            workers: 4,
+            max_attempts: 10,
+            base_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(60 * 60),
+            poll_interval: Duration::from_secs(1),
+            lease: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+impl WorkerConfig {
+    /// Delay before the next attempt, doubling after each failed attempt
+    pub fn backoff(&self, attempts: i32) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 31) as u32;
+        self.base_backoff
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_backoff)
+    }
+}
+
+// How often `spawn_workers` writes the verification failures counted in memory to the store
+const FAILURE_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub struct Inbox {
+    store: Arc<dyn WebhookStore>,
+    // Verification failures not yet written, by provider and reason
+    failures: Arc<Mutex<HashMap<(&'static str, &'static str), i64>>>,
+}
+
+impl Inbox {
+    #[cfg(feature = "postgres")]
+    pub fn new(pg_pool: PGPool) -> Self {
+        Inbox::with_store(Arc::new(PostgresStore::new(pg_pool)))
+    }
+
+    pub fn with_store(store: Arc<dyn WebhookStore>) -> Self {
+        Inbox { store, failures: Arc::default() }
+    }
+
+    /// Store a webhook request, returning the inbox id. `verified` says whether its signature was
+    /// checked or it was confirmed with the provider.
+    pub async fn enqueue(&self, provider: &str, headers: &HeaderMap, body: &str, verified: bool) -> Result<i64> {
+        // Pull out a few well known keys so deliveries can be found without parsing bodies
+        let json: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+
+        self.store.inbox_push(NewInboxMessage {
+            provider: provider.to_string(),
+            event_type: json_str(&json, "type"),
+            delivery_id: json_str(&json, "deliveryId"),
+            invoice_id: json_str(&json, "invoiceId"),
+            payment_hash: json_str(&json, "payment_hash"),
+            verified,
+            headers: headers_to_json(headers),
+            body: body.to_string(),
+        }).await
+    }
+
+    /// Count a request rejected before it reached the inbox, eg. for a bad signature. Counts are kept
+    /// in memory, so a flood of bad requests does not become a flood of writes, until
+    /// `flush_verification_failures`.
+    pub fn count_verification_failure(&self, provider: &'static str, reason: VerifyFailure) {
+        *self.failures.lock().unwrap().entry((provider, reason.as_str())).or_default() += 1;
+    }
+
+    /// Write the verification failures counted since the last flush to the store
+    pub async fn flush_verification_failures(&self) -> Result<()> {
+        let failures = std::mem::take(&mut *self.failures.lock().unwrap());
+
+        for (i, ((provider, reason), count)) in failures.iter().enumerate() {
+            if let Err(err) = self.store.log_verification_failure(provider, reason, *count).await {
+                // Kept for the next flush
+                let mut pending = self.failures.lock().unwrap();
+                for (key, count) in failures.into_iter().skip(i) {
+                    *pending.entry(key).or_default() += count;
+                }
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Number of messages waiting to be processed
+    pub async fn depth(&self) -> Result<i64> {
+        self.store.inbox_depth().await
+    }
+
+    /// Claim and process a single due message. Returns false when nothing was due.
+    pub async fn process_next(&self, handler: &dyn InboxHandler, config: &WorkerConfig) -> Result<bool> {
+        let message = match self.store.inbox_claim(config.lease).await? {
+            Some(message) => message,
+            None => return Ok(false),
+        };
+
+        let attempts = message.attempts + 1;
+
+        let span = tracing::info_span!(
+            "inbox.process",
+            inbox_id = message.id,
+            provider = %message.provider,
+            event_type = message.event_type.as_deref(),
+            delivery_id = message.delivery_id.as_deref(),
+            invoice_id = message.invoice_id.as_deref(),
+            attempt = attempts,
+        );
+
+        let handled = metrics::observe_handler(&message.provider, handler.handle(&message)).instrument(span).await;
+
+        let outcome = match handled {
+            Ok(_) => InboxOutcome::Done,
+            Err(err) if !err.is_retryable() => {
+                warn!("Inbox message {} cannot be processed, moving to dead-letter: {err}", message.id);
+                InboxOutcome::Dead { error: err.to_string() }
+            },
+            Err(err) if attempts >= config.max_attempts => {
+                error!("Inbox message {} failed {attempts} times, moving to dead-letter: {err}", message.id);
+                InboxOutcome::Dead { error: err.to_string() }
+            },
+            Err(err) => {
+                let delay = config.backoff(attempts);
+                warn!("Inbox message {} failed, retrying in {delay:?}: {err}", message.id);
+                InboxOutcome::Retry { error: err.to_string(), delay }
+            },
+        };
+
+        self.store.inbox_finish(message.id, outcome).await?;
+
+        Ok(true)
+    }
+}
+
+/// Start the worker pool, and a task writing counted verification failures to the store every
+/// 10 seconds. Workers run until their tasks are aborted.
+pub fn spawn_workers(inbox: Inbox, handler: Arc<dyn InboxHandler>, config: WorkerConfig) -> Vec<JoinHandle<()>> {
+    let flusher = {
+        let inbox = inbox.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(FAILURE_FLUSH_INTERVAL).await;
+                if let Err(err) = inbox.flush_verification_failures().await {
+                    warn!("Could not store verification failure counts: {err:?}");
+                }
+            }
+        })
+    };
+
+    (0..config.workers)
+        .map(|worker| {
+            let inbox = inbox.clone();
+            let handler = handler.clone();
+            let config = config.clone();
+
+            tokio::spawn(async move {
+                debug!("Inbox worker {worker} started");
+
+                loop {
+                    match inbox.process_next(handler.as_ref(), &config).await {
+                        Ok(true) => continue,
+                        Ok(false) => {},
+                        Err(err) => error!("Inbox worker {worker} error: {err:?}"),
+                    }
+
+                    tokio::time::sleep(config.poll_interval).await;
+                }
+            })
+        })
+        .chain([flusher])
+        .collect()
+}
+
+/// Count a failed verification with the app's `web::Data<Inbox>`, if there is one (eg. for the dashboard)
+pub fn count_verification_failure(req: &HttpRequest, provider: &'static str, reason: VerifyFailure) {
+    if let Some(inbox) = req.app_data::<web::Data<Inbox>>() {
+        inbox.count_verification_failure(provider, reason);
+    }
+}
+
+/// Route that stores the request in the inbox and acknowledges it at once. Requests are stored as
+/// verified when a verifier middleware marked them with `Verified`. Requires `web::Data<Inbox>` in
+/// the app data.
+///
+/// ```ignore
+/// web::scope("/btcpay")
+///     .wrap(btcpay_middleware::BTCPayHeaderVerify)
+///     .route("/webhook", inbox::route("btcpay"))
+/// ```
+pub fn route(provider: &'static str) -> Route {
+    web::post().to(move |req: HttpRequest, body: web::Bytes, inbox: web::Data<Inbox>| async move {
+        let body_str = match std::str::from_utf8(&body) {
+            Ok(body_str) => body_str,
+            Err(_) => return problem_response(StatusCode::BAD_REQUEST, Some("body is not valid UTF-8")),
+        };
+
+        let verified = req.extensions().contains::<Verified>();
+
+        match inbox.enqueue(provider, req.headers(), body_str, verified).await {
+            Ok(id) => {
+                debug!("Stored {provider} webhook in inbox: {id}");
+                HttpResponse::Ok().finish()
+            },
+            Err(err) => {
+                error!("Error: {err:?}");
+                HandlerError::Retryable(err).error_response()
+            }
+        }
+    })
+}
+
+fn headers_to_json(headers: &HeaderMap) -> Value {
+    let mut map = Map::new();
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes()).to_string();
+        match map.get_mut(name.as_str()) {
+            Some(Value::String(existing)) => {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            },
+            _ => {
+                map.insert(name.as_str().to_string(), Value::String(value));
+            },
+        }
+    }
+    Value::Object(map)
+}
+
+fn json_str(json: &Value, key: &str) -> Option<String> {
+    json.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let config = WorkerConfig {
+            base_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(60),
+            ..WorkerConfig::default()
+        };
+
+        assert_eq!(config.backoff(1), Duration::from_secs(5));
+        assert_eq!(config.backoff(2), Duration::from_secs(10));
+        assert_eq!(config.backoff(3), Duration::from_secs(20));
+        assert_eq!(config.backoff(5), Duration::from_secs(60));
+        assert_eq!(config.backoff(100), Duration::from_secs(60));
+    }
+
+    struct FailingHandler;
+
+    #[async_trait]
+    impl InboxHandler for FailingHandler {
+        async fn handle(&self, _message: &InboxMessage) -> HandlerResult {
+            Err(anyhow::anyhow!("boom").into())
+        }
+    }
+
+    struct PermanentHandler;
+
+    #[async_trait]
+    impl InboxHandler for PermanentHandler {
+        async fn handle(&self, _message: &InboxMessage) -> HandlerResult {
+            Err(HandlerError::permanent(anyhow::anyhow!("missing posData")))
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_process_next_dead_letters_after_max_attempts() {
+        let store = Arc::new(crate::store::MemoryStore::new());
+        let inbox = Inbox::with_store(store.clone());
+        let config = WorkerConfig {
+            max_attempts: 2,
+            base_backoff: Duration::ZERO,
+            ..WorkerConfig::default()
+        };
+
+        let id = inbox.enqueue("btcpay", &HeaderMap::new(), r#"{"type": "InvoiceSettled"}"#, true).await.unwrap();
+
+        assert!(inbox.process_next(&FailingHandler, &config).await.unwrap());
+        assert_eq!(store.inbox_status(id).await.unwrap(), Some(InboxStatus::Pending));
+
+        assert!(inbox.process_next(&FailingHandler, &config).await.unwrap());
+        assert_eq!(store.inbox_status(id).await.unwrap(), Some(InboxStatus::Dead));
+
+        assert!(!inbox.process_next(&FailingHandler, &config).await.unwrap());
+    }
+
+    #[actix_web::test]
+    async fn test_process_next_dead_letters_permanent_errors_at_once() {
+        let store = Arc::new(crate::store::MemoryStore::new());
+        let inbox = Inbox::with_store(store.clone());
+
+        let id = inbox.enqueue("btcpay", &HeaderMap::new(), r#"{"type": "InvoiceSettled"}"#, true).await.unwrap();
+
+        assert!(inbox.process_next(&PermanentHandler, &WorkerConfig::default()).await.unwrap());
+        assert_eq!(store.inbox_status(id).await.unwrap(), Some(InboxStatus::Dead));
+    }
+
+    #[actix_web::test]
+    async fn test_verification_failures_counted_in_memory() {
+        let store = Arc::new(crate::store::MemoryStore::new());
+        let inbox = Inbox::with_store(store.clone());
+        let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+
+        for _ in 0..3 {
+            inbox.count_verification_failure("btcpay", VerifyFailure::BadSignature);
+        }
+        inbox.clone().count_verification_failure("btcpay", VerifyFailure::MissingHeader);
+        assert!(store.verification_failures(hour_ago).await.unwrap().is_empty());
+
+        inbox.flush_verification_failures().await.unwrap();
+        inbox.flush_verification_failures().await.unwrap();
+        let counts = store.verification_failures(hour_ago).await.unwrap();
+        assert_eq!(counts.iter().map(|c| (c.reason.as_str(), c.count)).collect::<Vec<_>>(), vec![("bad_signature", 3), ("missing_header", 1)]);
+    }
+
+    #[test]
+    fn test_headers_to_json() {
+        let mut headers = HeaderMap::new();
+        headers.insert("btcpay-sig".parse().unwrap(), "sha256=abc".parse().unwrap());
+        headers.append("x-forwarded-for".parse().unwrap(), "10.0.0.1".parse().unwrap());
+        headers.append("x-forwarded-for".parse().unwrap(), "10.0.0.2".parse().unwrap());
+
+        let json = headers_to_json(&headers);
+        assert_eq!(json["btcpay-sig"], "sha256=abc");
+        assert_eq!(json["x-forwarded-for"], "10.0.0.1, 10.0.0.2");
+    }
+}
@@ -1,8 +1,59 @@
+// Parsing on its own never logs, so the macros go unused without the server features
+#[allow(unused_imports)]
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "actix")]
+pub mod admin;
+#[cfg(feature = "alby")]
+pub mod alby;
+pub mod bolt11;
+#[cfg(feature = "config")]
+pub mod config;
+#[cfg(feature = "actix")]
+pub mod dashboard;
+#[cfg(feature = "postgres")]
 pub mod db;
+#[cfg(feature = "http")]
 pub mod error;
+pub mod event;
+#[cfg(feature = "actix")]
+pub mod firewall;
+#[cfg(feature = "actix")]
+pub mod health;
+#[cfg(feature = "actix")]
+pub mod inbox;
+pub mod metrics;
+#[cfg(feature = "postgres")]
+pub mod migrations;
+#[cfg(feature = "nostr")]
+pub mod nostr;
+#[cfg(feature = "opennode")]
+pub mod opennode;
+#[cfg(feature = "paywall")]
+pub mod paywall;
+#[cfg(feature = "phoenixd")]
+pub mod phoenixd;
+#[cfg(feature = "actix")]
+pub mod recorder;
+#[cfg(feature = "relay")]
+pub mod relay;
+#[cfg(feature = "actix")]
+pub mod store;
+#[cfg(feature = "strike")]
+pub mod strike;
+#[cfg(feature = "reqwest")]
+pub mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "btcpay")]
 pub mod btcpay;
+#[cfg(feature = "lnbits")]
 pub mod lnbits;
+#[cfg(feature = "actix")]
 pub mod routes;
+pub mod verifier;
+#[cfg(all(feature = "btcpay", feature = "http"))]
+pub mod webhook;
+#[cfg(feature = "zbd")]
+pub mod zbd;
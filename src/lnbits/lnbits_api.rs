@@ -0,0 +1,104 @@
+use anyhow::{anyhow, bail, Result};
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::lnbits::lnbits_models::WebhookPayload;
+use crate::telemetry;
+
+/// Client for the LNbits wallet API. Add the invoice or admin key of each wallet webhooks may come
+/// from with `with_wallet`.
+#[derive(Debug, Clone)]
+pub struct LNbitsClient {
+    host: String,
+    api_keys: Vec<String>,
+    client: reqwest::Client,
+}
+
+/// A payment as `GET /api/v1/payments/{payment_hash}` reports it
+#[derive(Debug, Deserialize)]
+pub struct PaymentStatus {
+    pub paid: bool,
+    pub details: PaymentDetails,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaymentDetails {
+    pub payment_hash: String,
+    /// Negative for outgoing payments
+    pub amount: i64,
+    pub wallet_id: String,
+}
+
+impl LNbitsClient {
+    pub fn new(host: &str) -> Self {
+        LNbitsClient {
+            host: host.trim_end_matches('/').to_string(),
+            api_keys: Vec::new(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_wallet(mut self, api_key: &str) -> Self {
+        self.api_keys.push(api_key.to_string());
+        self
+    }
+
+    /// Fetch a payment from the first wallet that knows it. `None` when none of them do.
+    #[tracing::instrument(name = "lnbits.get_payment", skip(self))]
+    pub async fn get_payment(&self, payment_hash: &str) -> Result<Option<PaymentStatus>> {
+        check_payment_hash(payment_hash)?;
+
+        for api_key in &self.api_keys {
+            let request = self.client
+                .get(format!("{}/api/v1/payments/{payment_hash}", self.host))
+                .header("X-Api-Key", api_key);
+            let response = telemetry::inject_trace_context(request).send().await?;
+            let status = response.status();
+
+            if status == StatusCode::NOT_FOUND {
+                continue;
+            }
+            if !status.is_success() {
+                bail!("lnbits payment request failed with {status}");
+            }
+
+            let payment = serde_json::from_str::<PaymentStatus>(&response.text().await?)
+                .map_err(|err| anyhow!("lnbits payment response does not parse: {err}"))?;
+            return Ok(Some(payment));
+        }
+
+        Ok(None)
+    }
+
+    /// LNbits webhooks are unsigned, so the payment is fetched from the wallets and has to agree with
+    /// the webhook on its hash, wallet and amount, and be paid unless the webhook says it is pending.
+    /// `None` when it does not, ie. the webhook was forged.
+    #[tracing::instrument(name = "lnbits.confirm", skip_all)]
+    pub async fn confirm(&self, payload: WebhookPayload) -> Result<Option<WebhookPayload>> {
+        let payment = match &payload {
+            WebhookPayload::Payment(payment) => payment,
+            WebhookPayload::Unsupported => return Ok(None),
+        };
+        // Hashes go into the URL path, anything else would change where the API keys are sent
+        if check_payment_hash(&payment.payment_hash).is_err() {
+            return Ok(None);
+        }
+
+        let confirmed = self.get_payment(&payment.payment_hash).await?.is_some_and(|status| {
+            status.details.payment_hash == payment.payment_hash
+                && status.details.wallet_id == payment.wallet_id
+                && status.details.amount == payment.amount as i64
+                && (status.paid || payment.pending)
+        });
+
+        Ok(confirmed.then_some(payload))
+    }
+}
+
+// A payment hash is 32 bytes of hex
+fn check_payment_hash(payment_hash: &str) -> Result<()> {
+    match payment_hash.len() == 64 && payment_hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+        true => Ok(()),
+        false => bail!("not a payment hash: {payment_hash:?}"),
+    }
+}
@@ -0,0 +1,143 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, body::EitherBody,
+};
+use crate::lnbits::{lnbits_models::WebhookPayload, LNbitsClient};
+use crate::metrics::VerifyFailure;
+use crate::verifier::{verifier_middleware::VerifyWebhookMiddleware, ConfirmWebhook, Headers, VerifyWebhook, WebhookVerifier};
+
+/// Confirms LNbits webhooks by fetching the payment they name from the configured wallets, as they
+/// are unsigned. The handler gets the `WebhookPayload` in the request extensions, eg. for
+/// `web::ReqData<WebhookPayload>`. Payments no wallet knows, or that do not match, are answered
+/// with a 401, and a 503 if the API cannot be reached.
+#[derive(Clone)]
+pub struct LNbitsConfirm {
+    verify: VerifyWebhook<LNbitsPayments, LNbitsClient>,
+}
+
+impl LNbitsConfirm {
+    pub fn new(client: LNbitsClient) -> Self {
+        LNbitsConfirm { verify: VerifyWebhook::new(LNbitsPayments).confirm_with(client) }
+    }
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for LNbitsConfirm
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = VerifyWebhookMiddleware<S, LNbitsPayments, LNbitsClient>;
+    type Future = <VerifyWebhook<LNbitsPayments, LNbitsClient> as Transform<S, ServiceRequest>>::Future;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        self.verify.new_transform(service)
+    }
+}
+
+/// Only parses webhooks, which carry no signature. `LNbitsConfirm` pairs it with the `LNbitsClient`
+/// confirmation, and it should not be used without one.
+#[derive(Clone)]
+pub struct LNbitsPayments;
+
+impl WebhookVerifier for LNbitsPayments {
+    type Payload = WebhookPayload;
+
+    fn provider(&self) -> &'static str {
+        "lnbits"
+    }
+
+    fn verify(&self, _headers: &dyn Headers, body: &[u8]) -> Result<WebhookPayload, VerifyFailure> {
+        serde_json::from_slice(body).map_err(|_| VerifyFailure::BadBody)
+    }
+
+    fn event_type(&self, payload: &WebhookPayload) -> String {
+        payload.event_type()
+    }
+}
+
+impl ConfirmWebhook<WebhookPayload> for LNbitsClient {
+    async fn confirm(&self, payload: WebhookPayload) -> anyhow::Result<Option<WebhookPayload>> {
+        LNbitsClient::confirm(self, payload).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifier;
+    use actix_web::{http::StatusCode, test, web, App, HttpRequest, HttpResponse};
+    use serde_json::json;
+    use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+    const PAID: &str = "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2";
+    const UNPAID: &str = "0b5c4a1f24b0c1e7f9d6a3e5c8b2a1d0f3e4c5b6a7980918273645546372819a";
+    const UPSTREAM_ERROR: &str = "502502502502502502502502502502502502502502502502502502502502502a";
+    const WALLET_ID: &str = "b59d05b23e184fa69a13a68c29d62df3";
+
+    fn payment(payment_hash: &str, amount: u64, pending: bool) -> String {
+        json!({
+            "checking_id": payment_hash, "pending": pending, "amount": amount, "fee": 0, "memo": "Article a1",
+            "time": 1682947081, "bolt11": "lnbc100n1...", "preimage": "0".repeat(64), "payment_hash": payment_hash,
+            "expiry": 1682947681, "extra": {}, "wallet_id": WALLET_ID, "webhook": "https://example.com/lnbits/webhook",
+            "webhook_status": null,
+        }).to_string()
+    }
+
+    // Against a local mock of the LNbits API, where only the second wallet has payments
+    #[actix_web::test]
+    async fn test_lnbits_confirm() {
+        let wallet_requested = Arc::new(AtomicBool::new(false));
+        let wallet = wallet_requested.clone();
+        let url = verifier::mock_server(move |app| {
+            let wallet = wallet.clone();
+            app.route("/api/v1/wallet", web::get().to(move || {
+                wallet.store(true, Ordering::SeqCst);
+                async { HttpResponse::Ok().json(json!({"name": "shop", "balance": 21000000})) }
+            }))
+            .route("/api/v1/payments/{payment_hash}", web::get().to(|req: HttpRequest, payment_hash: web::Path<String>| async move {
+                let details = |amount| json!({"payment_hash": payment_hash.as_str(), "amount": amount, "wallet_id": WALLET_ID, "pending": false});
+                match (req.headers().get("x-api-key").and_then(|key| key.to_str().ok()), payment_hash.as_str()) {
+                    (Some("shop-key"), PAID) => HttpResponse::Ok().json(json!({"paid": true, "preimage": "0".repeat(64), "details": details(10000)})),
+                    (Some("shop-key"), UNPAID) => HttpResponse::Ok().json(json!({"paid": false, "details": details(10000)})),
+                    (Some("shop-key"), UPSTREAM_ERROR) => HttpResponse::BadGateway().finish(),
+                    (Some("shop-key" | "tips-key"), _) => HttpResponse::NotFound().json(json!({"detail": "Payment does not exist."})),
+                    _ => HttpResponse::Unauthorized().json(json!({"detail": "Invalid key"})),
+                }
+            }));
+        });
+
+        let client = LNbitsClient::new(&url).with_wallet("tips-key").with_wallet("shop-key");
+        assert_eq!(client.get_payment(PAID).await.unwrap().unwrap().details.amount, 10000);
+        assert!(client.get_payment(UNPAID.replace('0', "1").as_str()).await.unwrap().is_none());
+        assert!(client.get_payment("not-a-hash").await.is_err());
+
+        let app = test::init_service(App::new()
+            .service(web::scope("/lnbits").wrap(LNbitsConfirm::new(client)).route("/webhook", web::post().to(
+                |payload: web::ReqData<WebhookPayload>| async move { HttpResponse::Ok().body(payload.event_type()) },
+            )))).await;
+        let webhook = |body: String| test::TestRequest::post().uri("/lnbits/webhook").set_payload(body).to_request();
+        let unknown_object = r#"{"type":"about:blank","title":"Unauthorized","status":401,"detail":"unknown_object"}"#;
+
+        assert_eq!(test::call_and_read_body(&app, webhook(payment(PAID, 10000, false))).await, "payment.paid");
+        assert_eq!(test::call_and_read_body(&app, webhook(payment(UNPAID, 10000, true))).await, "payment.pending");
+
+        // The wallet has to agree with the webhook's amount and settlement
+        let response = test::call_service(&app, webhook(payment(PAID, 1_000_000, false))).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::read_body(response).await, unknown_object);
+        assert_eq!(test::call_service(&app, webhook(payment(UNPAID, 10000, false))).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::call_service(&app, webhook(payment(&"f".repeat(64), 10000, false))).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(test::call_service(&app, webhook("not json".to_string())).await.status(), StatusCode::UNAUTHORIZED);
+
+        // A hash that walks to another endpoint is never sent with the wallet keys
+        let response = test::call_service(&app, webhook(payment("../wallet", 10000, false))).await;
+        assert_eq!(test::read_body(response).await, unknown_object);
+        assert!(!wallet_requested.load(Ordering::SeqCst));
+
+        assert_eq!(test::call_service(&app, webhook(payment(UPSTREAM_ERROR, 10000, false))).await.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -1,13 +1,17 @@
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::event::{EventType, NormalizedEvent, Provider};
+
+// Payloads are parsed once per request, so boxing the payment buys nothing
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum WebhookPayload {
     Payment(PaymentEvent),
     Unsupported
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaymentEvent {
     pub checking_id: String,
     pub pending: bool,
@@ -24,3 +28,38 @@ pub struct PaymentEvent {
     pub webhook: String,
     pub webhook_status: Option<u64>,
 }
+
+impl WebhookPayload {
+    /// `payment.pending` or `payment.paid`
+    pub fn event_type(&self) -> String {
+        match self {
+            WebhookPayload::Payment(payment) if payment.pending => "payment.pending".to_string(),
+            WebhookPayload::Payment(_) => "payment.paid".to_string(),
+            WebhookPayload::Unsupported => "unsupported".to_string(),
+        }
+    }
+
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        match self {
+            // Only payments LNbits no longer lists as pending are settled
+            WebhookPayload::Payment(payment) => Some(NormalizedEvent {
+                id: match payment.pending {
+                    true => format!("{}:pending", payment.payment_hash),
+                    false => payment.payment_hash.clone(),
+                },
+                provider: Provider::LNbits,
+                event_type: match payment.pending {
+                    true => EventType::InvoiceCreated,
+                    false => EventType::InvoiceSettled,
+                },
+                store_id: Some(payment.wallet_id.clone()),
+                invoice_id: Some(payment.checking_id.clone()),
+                payment_hash: Some(payment.payment_hash.clone()),
+                amount_msat: Some(payment.amount),
+                timestamp: Some(payment.time as i64),
+                metadata: payment.extra.clone(),
+            }),
+            WebhookPayload::Unsupported => None,
+        }
+    }
+}
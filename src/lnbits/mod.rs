@@ -1 +1,10 @@
+#[cfg(feature = "lnbits-api")]
+pub mod lnbits_api;
+#[cfg(all(feature = "actix", feature = "lnbits-api"))]
+pub mod lnbits_middleware;
 pub mod lnbits_models;
+
+#[cfg(feature = "lnbits-api")]
+pub use lnbits_api::LNbitsClient;
+#[cfg(all(feature = "actix", feature = "lnbits-api"))]
+pub use lnbits_middleware::LNbitsConfirm;
@@ -0,0 +1,156 @@
+// Prometheus metrics for webhook traffic and processing. The recording functions are always
+// available so call sites need no feature checks - without the `metrics` feature they do nothing.
+#![cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+
+use anyhow::Result;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "metrics")]
+use lazy_static::lazy_static;
+#[cfg(feature = "metrics")]
+use prometheus::{
+    register_histogram_vec_with_registry, register_int_counter_vec_with_registry, register_int_gauge_with_registry,
+    Encoder, HistogramVec, IntCounterVec, IntGauge, Registry, TextEncoder,
+};
+
+#[cfg(feature = "metrics")]
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::new();
+
+    static ref WEBHOOK_REQUESTS: IntCounterVec = register_int_counter_vec_with_registry!(
+        "lightning_webhook_requests_total", "Verified webhook requests", &["provider", "event_type"], REGISTRY
+    ).unwrap();
+
+    static ref VERIFICATION_FAILURES: IntCounterVec = register_int_counter_vec_with_registry!(
+        "lightning_webhook_verification_failures_total", "Webhook requests rejected before the handler", &["provider", "reason"], REGISTRY
+    ).unwrap();
+
+    static ref BLOCKED_REQUESTS: IntCounterVec = register_int_counter_vec_with_registry!(
+        "lightning_webhook_blocked_requests_total", "Requests turned away by `firewall::Firewall`", &["reason"], REGISTRY
+    ).unwrap();
+
+    static ref HANDLER_OUTCOMES: IntCounterVec = register_int_counter_vec_with_registry!(
+        "lightning_webhook_handler_outcomes_total", "Webhook handler results", &["provider", "outcome"], REGISTRY
+    ).unwrap();
+
+    static ref HANDLER_DURATION: HistogramVec = register_histogram_vec_with_registry!(
+        "lightning_webhook_handler_duration_seconds", "Webhook handler latency", &["provider"], REGISTRY
+    ).unwrap();
+
+    static ref INBOX_DEPTH: IntGauge = register_int_gauge_with_registry!(
+        "lightning_webhook_inbox_depth", "Webhooks waiting in the inbox", REGISTRY
+    ).unwrap();
+
+    static ref BTCPAY_API_DURATION: HistogramVec = register_histogram_vec_with_registry!(
+        "lightning_webhook_btcpay_api_duration_seconds", "BTCPay Greenfield API call latency", &["endpoint", "status"], REGISTRY
+    ).unwrap();
+}
+
+/// Why a webhook request was rejected before reaching the handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyFailure {
+    MissingHeader,
+    /// A signature header that cannot hold a signature, eg. not hex
+    BadHeader,
+    BadSignature,
+    BadBody,
+    /// Signed too long ago (or ahead), eg. a replayed Svix message
+    StaleTimestamp,
+    /// Names a charge or payment the provider does not know, when confirmed with its API
+    UnknownObject,
+}
+
+impl VerifyFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VerifyFailure::MissingHeader => "missing_header",
+            VerifyFailure::BadHeader => "bad_header",
+            VerifyFailure::BadSignature => "bad_signature",
+            VerifyFailure::BadBody => "bad_body",
+            VerifyFailure::StaleTimestamp => "stale_timestamp",
+            VerifyFailure::UnknownObject => "unknown_object",
+        }
+    }
+}
+
+pub fn webhook_received(provider: &str, event_type: &str) {
+    #[cfg(feature = "metrics")]
+    WEBHOOK_REQUESTS.with_label_values(&[provider, event_type]).inc();
+}
+
+pub fn verification_failed(provider: &str, reason: VerifyFailure) {
+    #[cfg(feature = "metrics")]
+    VERIFICATION_FAILURES.with_label_values(&[provider, reason.as_str()]).inc();
+}
+
+/// `reason` is "forbidden_source" or "rate_limited"
+pub fn request_blocked(reason: &str) {
+    #[cfg(feature = "metrics")]
+    BLOCKED_REQUESTS.with_label_values(&[reason]).inc();
+}
+
+/// Run a webhook handler, recording its outcome and latency
+pub async fn observe_handler<T, E, F>(provider: &str, handler: F) -> Result<T, E>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    let started = Instant::now();
+    let result = handler.await;
+
+    #[cfg(feature = "metrics")]
+    {
+        let outcome = if result.is_ok() { "ok" } else { "error" };
+        HANDLER_OUTCOMES.with_label_values(&[provider, outcome]).inc();
+        HANDLER_DURATION.with_label_values(&[provider]).observe(started.elapsed().as_secs_f64());
+    }
+
+    result
+}
+
+pub fn set_inbox_depth(depth: i64) {
+    #[cfg(feature = "metrics")]
+    INBOX_DEPTH.set(depth);
+}
+
+/// Record a BTCPay API call. `status` is the response status code, or `error` when there was no response.
+pub fn btcpay_api_call(endpoint: &str, status: &str, duration: Duration) {
+    #[cfg(feature = "metrics")]
+    BTCPAY_API_DURATION.with_label_values(&[endpoint, status]).observe(duration.as_secs_f64());
+}
+
+/// All metrics in the Prometheus text format
+#[cfg(feature = "metrics")]
+pub fn render() -> Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_render_includes_recorded_metrics() {
+        webhook_received("btcpay", "InvoiceSettled");
+        verification_failed("btcpay", VerifyFailure::BadSignature);
+        observe_handler("btcpay", async { Ok::<_, anyhow::Error>(()) }).await.unwrap();
+        observe_handler("btcpay", async { Err::<(), _>(anyhow::anyhow!("boom")) }).await.unwrap_err();
+        set_inbox_depth(3);
+        request_blocked("rate_limited");
+        btcpay_api_call("invoice", "200", Duration::from_millis(40));
+
+        let text = render().unwrap();
+
+        assert!(text.contains(r#"lightning_webhook_requests_total{event_type="InvoiceSettled",provider="btcpay"}"#));
+        assert!(text.contains(r#"lightning_webhook_verification_failures_total{provider="btcpay",reason="bad_signature"}"#));
+        assert!(text.contains(r#"lightning_webhook_handler_outcomes_total{outcome="error",provider="btcpay"}"#));
+        assert!(text.contains("lightning_webhook_handler_duration_seconds_bucket"));
+        assert!(text.contains("lightning_webhook_inbox_depth 3"));
+        assert!(text.contains(r#"lightning_webhook_blocked_requests_total{reason="rate_limited"} "#));
+        assert!(text.contains(r#"lightning_webhook_btcpay_api_duration_seconds_count{endpoint="invoice",status="200"} "#));
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result};
+use deadpool_postgres::Pool as PGPool;
+use std::collections::HashSet;
+
+/// A versioned schema change, embedded in the crate at build time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// All of the crate's migrations, in the order they are applied. Versions must never be reused.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "webhook_inbox",
+        sql: include_str!("../migrations/0001_webhook_inbox.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "relay",
+        sql: include_str!("../migrations/0002_relay.sql"),
+    },
+    #[cfg(feature = "paywall")]
+    Migration {
+        version: 3,
+        name: "paywall",
+        sql: include_str!("../migrations/0003_paywall.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "delivery_log_index",
+        sql: include_str!("../migrations/0004_delivery_log_index.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "recordings",
+        sql: include_str!("../migrations/0005_recordings.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "inbox_search",
+        sql: include_str!("../migrations/0006_inbox_search.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "verification_failures",
+        sql: include_str!("../migrations/0007_verification_failures.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "inbox_verified",
+        sql: include_str!("../migrations/0008_inbox_verified.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "store",
+        sql: include_str!("../migrations/0009_store.sql"),
+    },
+    #[cfg(feature = "paywall")]
+    Migration {
+        version: 10,
+        name: "paywall_price",
+        sql: include_str!("../migrations/0010_paywall_price.sql"),
+    },
+];
+
+const MIGRATIONS_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS lightning_webhook_migrations (
+    version    BIGINT PRIMARY KEY,
+    name       TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+";
+
+// Serialises concurrent startups, so only one instance applies migrations at a time
+const MIGRATIONS_LOCK_ID: i64 = 0x6c6e_7765_6268_6f6f;
+
+/// Apply all pending migrations in a single transaction, returning those applied
+pub async fn migrate(pg_pool: &PGPool) -> Result<Vec<Migration>> {
+    let mut pg_conn = pg_pool.get().await?;
+    let pg_trans = pg_conn.transaction().await?;
+
+    pg_trans.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATIONS_LOCK_ID]).await?;
+    pg_trans.batch_execute(MIGRATIONS_TABLE).await?;
+
+    let applied: HashSet<i64> = pg_trans
+        .query("SELECT version FROM lightning_webhook_migrations", &[])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    let mut newly_applied = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        info!("Applying migration {} ({})", migration.version, migration.name);
+
+        pg_trans.batch_execute(migration.sql).await
+            .with_context(|| format!("migration {} ({}) failed", migration.version, migration.name))?;
+
+        pg_trans.execute("
+            INSERT INTO lightning_webhook_migrations (version, name) VALUES ($1, $2)
+        ", &[&migration.version, &migration.name]).await?;
+
+        newly_applied.push(*migration);
+    }
+
+    pg_trans.commit().await?;
+
+    Ok(newly_applied)
+}
+
+/// Check mode - report the migrations `migrate` would apply, without changing anything
+pub async fn pending(pg_pool: &PGPool) -> Result<Vec<Migration>> {
+    let pg_conn = pg_pool.get().await?;
+
+    let exists: bool = pg_conn
+        .query_one("SELECT to_regclass('lightning_webhook_migrations') IS NOT NULL", &[])
+        .await?
+        .get(0);
+
+    if !exists {
+        return Ok(MIGRATIONS.to_vec());
+    }
+
+    let applied: HashSet<i64> = pg_conn
+        .query("SELECT version FROM lightning_webhook_migrations", &[])
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    Ok(MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)).copied().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_are_unique_and_ordered() {
+        let versions: Vec<i64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{anyhow, bail, Result};
+use futures_util::{SinkExt, StreamExt};
+use hex::encode;
+use lazy_static::lazy_static;
+use ring::digest::{digest, SHA256};
+use secp256k1::{schnorr, All, Keypair, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::{str::FromStr, time::Duration};
+use tokio_tungstenite::{connect_async, tungstenite::Message as WsMessage};
+
+pub mod zap;
+
+lazy_static! {
+    static ref SECP: Secp256k1<All> = Secp256k1::new();
+}
+
+/// A signing key pair, eg. the server key used for zap receipts
+#[derive(Clone)]
+pub struct Keys {
+    keypair: Keypair,
+}
+
+impl Keys {
+    /// Load from a hex encoded secret key
+    pub fn from_secret_hex(secret_key: &str) -> Result<Self> {
+        let secret_key = SecretKey::from_str(secret_key).map_err(|_| anyhow!("invalid nostr secret key"))?;
+        Ok(Keys { keypair: Keypair::from_secret_key(&SECP, &secret_key) })
+    }
+
+    /// Hex encoded x-only public key
+    pub fn public_key(&self) -> String {
+        self.keypair.x_only_public_key().0.to_string()
+    }
+}
+
+/// A NIP-01 event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u32,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+    pub sig: String,
+}
+
+impl Event {
+    pub fn sign(keys: &Keys, created_at: u64, kind: u32, tags: Vec<Vec<String>>, content: String) -> Event {
+        let pubkey = keys.public_key();
+        let id = event_id(&pubkey, created_at, kind, &tags, &content);
+
+        let message = Message::from_digest(id);
+        let sig = SECP.sign_schnorr_no_aux_rand(&message, &keys.keypair);
+
+        Event {
+            id: encode(id),
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+            sig: sig.to_string(),
+        }
+    }
+
+    /// Check the id matches the event content, and the signature is valid for the pubkey
+    pub fn verify(&self) -> Result<()> {
+        let id = event_id(&self.pubkey, self.created_at, self.kind, &self.tags, &self.content);
+        if encode(id) != self.id {
+            bail!("event id does not match content");
+        }
+
+        let pubkey = XOnlyPublicKey::from_str(&self.pubkey).map_err(|_| anyhow!("invalid event pubkey"))?;
+        let sig = schnorr::Signature::from_str(&self.sig).map_err(|_| anyhow!("invalid event signature"))?;
+
+        SECP.verify_schnorr(&sig, &Message::from_digest(id), &pubkey)
+            .map_err(|_| anyhow!("bad event signature"))
+    }
+
+    /// All tags with the given name, eg. `p`
+    pub fn tags_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a [String]> + 'a {
+        self.tags.iter()
+            .filter(move |tag| tag.first().map(String::as_str) == Some(name))
+            .map(Vec::as_slice)
+    }
+
+    /// The first value of the first tag with the given name
+    pub fn tag_value(&self, name: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|tag| tag.first().map(String::as_str) == Some(name))
+            .and_then(|tag| tag.get(1))
+            .map(String::as_str)
+    }
+}
+
+fn event_id(pubkey: &str, created_at: u64, kind: u32, tags: &[Vec<String>], content: &str) -> [u8; 32] {
+    let serialized = json!([0, pubkey, created_at, kind, tags, content]).to_string();
+    let mut id = [0u8; 32];
+    id.copy_from_slice(digest(&SHA256, serialized.as_bytes()).as_ref());
+    id
+}
+
+/// Send an event to a relay, and wait for it to be accepted
+pub async fn publish(relay_url: &str, event: &Event, timeout: Duration) -> Result<()> {
+    tokio::time::timeout(timeout, async {
+        let (mut ws, _) = connect_async(relay_url).await?;
+
+        ws.send(WsMessage::Text(json!(["EVENT", event]).to_string())).await?;
+
+        while let Some(message) = ws.next().await {
+            let text = match message? {
+                WsMessage::Text(text) => text,
+                WsMessage::Close(_) => break,
+                _ => continue,
+            };
+
+            // Expecting ["OK", <event_id>, <true|false>, <message>]
+            let reply: Vec<Value> = match serde_json::from_str(&text) {
+                Ok(reply) => reply,
+                Err(_) => continue,
+            };
+
+            if reply.first().and_then(Value::as_str) == Some("OK") && reply.get(1).and_then(Value::as_str) == Some(&event.id) {
+                let _ = ws.close(None).await;
+
+                return match reply.get(2).and_then(Value::as_bool) {
+                    Some(true) => Ok(()),
+                    _ => Err(anyhow!("relay rejected event: {}", reply.get(3).and_then(Value::as_str).unwrap_or_default())),
+                };
+            }
+        }
+
+        Err(anyhow!("relay closed the connection before accepting the event"))
+    })
+    .await
+    .map_err(|_| anyhow!("timed out publishing to {relay_url}"))?
+}
@@ -0,0 +1,403 @@
+use anyhow::{anyhow, bail, Result};
+use futures_util::future::join_all;
+use hex::encode;
+use ring::digest::{digest, SHA256};
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::bolt11;
+use crate::lnbits::lnbits_models::PaymentEvent;
+use super::{publish, Event, Keys};
+
+// NIP-57 Lightning Zaps
+// REF: https://github.com/nostr-protocol/nips/blob/master/57.md
+
+pub const ZAP_REQUEST_KIND: u32 = 9734;
+pub const ZAP_RECEIPT_KIND: u32 = 9735;
+
+// Limit how many relays a single zap request can make us connect to
+const MAX_RELAYS: usize = 10;
+
+/// Find the raw zap request JSON for a payment. LNbits stores it in `extra.nostr`, otherwise
+/// the invoice description (memo) is the zap request itself.
+pub fn zap_request_json(payment: &PaymentEvent) -> Option<String> {
+    if let Some(nostr) = payment.extra.get("nostr").and_then(Value::as_str) {
+        return Some(nostr.to_string());
+    }
+
+    let memo: Value = serde_json::from_str(&payment.memo).ok()?;
+    match memo.get("kind").and_then(Value::as_u64) {
+        Some(kind) if kind == ZAP_REQUEST_KIND as u64 => Some(payment.memo.clone()),
+        _ => None,
+    }
+}
+
+/// Validate a zap request against the invoice that paid it (NIP-57 Appendix D)
+pub fn validate_zap_request(zap_request_json: &str, bolt11: &str, amount_msat: u64) -> Result<Event> {
+    let zap_request: Event = serde_json::from_str(zap_request_json)
+        .map_err(|err| anyhow!("invalid zap request: {err}"))?;
+
+    if zap_request.kind != ZAP_REQUEST_KIND {
+        bail!("zap request has kind {}, expected {ZAP_REQUEST_KIND}", zap_request.kind);
+    }
+
+    zap_request.verify()?;
+
+    if zap_request.tags_named("p").count() != 1 {
+        bail!("zap request must have exactly one p tag");
+    }
+
+    if zap_request.tags_named("e").count() > 1 {
+        bail!("zap request must have at most one e tag");
+    }
+
+    if zap_request.tags_named("relays").next().is_none_or(|tag| tag.len() < 2) {
+        bail!("zap request has no relays");
+    }
+
+    if let Some(amount) = zap_request.tag_value("amount") {
+        if amount.parse::<u64>().ok() != Some(amount_msat) {
+            bail!("zap request amount {amount} does not match paid amount {amount_msat}");
+        }
+    }
+
+    // The invoice must commit to this exact zap request
+    let invoice = bolt11::decode(bolt11)?;
+    let description_hash = invoice.description_hash.ok_or(anyhow!("invoice has no description hash"))?;
+
+    if description_hash != encode(digest(&SHA256, zap_request_json.as_bytes())) {
+        bail!("invoice description hash does not match zap request");
+    }
+
+    if invoice.amount_msat.is_some_and(|invoice_amount| invoice_amount != amount_msat) {
+        bail!("invoice amount does not match paid amount");
+    }
+
+    Ok(zap_request)
+}
+
+/// Build a signed zap receipt for a validated zap request
+pub fn build_zap_receipt(keys: &Keys, zap_request: &Event, zap_request_json: &str, bolt11: &str, preimage: Option<&str>, paid_at: u64) -> Event {
+    let mut tags: Vec<Vec<String>> = zap_request.tags.iter()
+        .filter(|tag| matches!(tag.first().map(String::as_str), Some("p" | "e" | "a")))
+        .cloned()
+        .collect();
+
+    tags.push(vec!["P".to_string(), zap_request.pubkey.clone()]);
+    tags.push(vec!["bolt11".to_string(), bolt11.to_string()]);
+    tags.push(vec!["description".to_string(), zap_request_json.to_string()]);
+
+    if let Some(preimage) = preimage {
+        tags.push(vec!["preimage".to_string(), preimage.to_string()]);
+    }
+
+    Event::sign(keys, paid_at, ZAP_RECEIPT_KIND, tags, String::new())
+}
+
+/// Issues zap receipts for paid zap invoices, signed with the server key. Only zaps to the server
+/// key's own pubkey are receipted, unless `with_recipients` names the pubkeys the wallet serves.
+#[derive(Clone)]
+pub struct Zapper {
+    keys: Keys,
+    recipients: Vec<String>,
+    publish_timeout: Duration,
+}
+
+impl Zapper {
+    pub fn new(keys: Keys) -> Self {
+        Zapper {
+            recipients: vec![keys.public_key()],
+            keys,
+            publish_timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Receipt zaps to these hex pubkeys, eg. the users of a Lightning address, instead of the
+    /// server key's own
+    pub fn with_recipients<S: AsRef<str>>(mut self, recipients: impl IntoIterator<Item = S>) -> Self {
+        self.recipients = recipients.into_iter().map(|pubkey| pubkey.as_ref().to_ascii_lowercase()).collect();
+        self
+    }
+
+    pub fn with_publish_timeout(mut self, publish_timeout: Duration) -> Self {
+        self.publish_timeout = publish_timeout;
+        self
+    }
+
+    /// If the payment is a settled zap, validate it and publish a zap receipt to the requested relays.
+    /// Returns `None` when the payment is not a zap or is still pending.
+    #[tracing::instrument(name = "zap.process_payment", skip_all, fields(payment_hash = %payment.payment_hash))]
+    pub async fn process_payment(&self, payment: &PaymentEvent) -> Result<Option<Event>> {
+        if payment.pending {
+            return Ok(None);
+        }
+
+        let zap_request_json = match zap_request_json(payment) {
+            Some(zap_request_json) => zap_request_json,
+            None => return Ok(None),
+        };
+
+        let zap_request = validate_zap_request(&zap_request_json, &payment.bolt11, payment.amount)?;
+
+        // Only zaps to the pubkeys this wallet serves are ours to receipt
+        let recipient = zap_request.tag_value("p").unwrap_or_default();
+        if !self.recipients.iter().any(|pubkey| pubkey == recipient) {
+            bail!("zap request is for {recipient}, which is not a recipient of this wallet");
+        }
+
+        // LNbits sends a zeroed preimage until it is known
+        let preimage = Some(payment.preimage.as_str()).filter(|p| !p.is_empty() && p.chars().any(|c| c != '0'));
+
+        let receipt = build_zap_receipt(&self.keys, &zap_request, &zap_request_json, &payment.bolt11, preimage, payment.time);
+
+        let relays: Vec<&String> = zap_request.tags_named("relays")
+            .flat_map(|tag| tag.iter().skip(1))
+            .take(MAX_RELAYS)
+            .collect();
+
+        let results = join_all(relays.iter().map(|relay| publish(relay, &receipt, self.publish_timeout))).await;
+
+        let mut published = 0;
+        for (relay, result) in relays.iter().zip(results) {
+            match result {
+                Ok(_) => published += 1,
+                Err(err) => warn!("Failed to publish zap receipt {} to {relay}: {err:#}", receipt.id),
+            }
+        }
+
+        if published == 0 {
+            bail!("zap receipt {} was not accepted by any relay", receipt.id);
+        }
+
+        debug!("Published zap receipt {} to {published} relays", receipt.id);
+
+        Ok(Some(receipt))
+    }
+}
+
+// The relay tests run on the actix runtime
+#[cfg(all(test, feature = "actix"))]
+mod tests {
+    use super::*;
+    use crate::bolt11::bech32_encode;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{accept_async, tungstenite::Message as WsMessage};
+    use futures_util::{SinkExt, StreamExt};
+
+    const SERVER_SECRET: &str = "0000000000000000000000000000000000000000000000000000000000000001";
+    const SENDER_SECRET: &str = "0000000000000000000000000000000000000000000000000000000000000002";
+    const USER_SECRET: &str = "0000000000000000000000000000000000000000000000000000000000000003";
+    // The public key of SERVER_SECRET
+    const RECIPIENT: &str = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+    // Local stand-in relay that accepts every valid event it is sent
+    async fn start_relay() -> (String, Arc<Mutex<Vec<Event>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let recorded = received.clone();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let mut ws = accept_async(stream).await.unwrap();
+                    while let Some(Ok(WsMessage::Text(text))) = ws.next().await {
+                        let (_, event): (String, Event) = serde_json::from_str(&text).unwrap();
+                        let accepted = event.verify().is_ok();
+                        let reply = serde_json::json!(["OK", event.id, accepted, ""]).to_string();
+                        recorded.lock().unwrap().push(event);
+                        ws.send(WsMessage::Text(reply)).await.unwrap();
+                    }
+                });
+            }
+        });
+
+        (url, received)
+    }
+
+    // Builds a description hash invoice. The node signature is zeroed as it is never checked.
+    fn invoice(hrp: &str, description: &str) -> String {
+        let to_groups = |bytes: &[u8]| -> Vec<u8> {
+            let mut groups = Vec::new();
+            let (mut acc, mut bits) = (0u32, 0);
+            for byte in bytes {
+                acc = acc << 8 | *byte as u32;
+                bits += 8;
+                while bits >= 5 {
+                    bits -= 5;
+                    groups.push(((acc >> bits) & 31) as u8);
+                }
+            }
+            if bits > 0 {
+                groups.push(((acc << (5 - bits)) & 31) as u8);
+            }
+            groups
+        };
+
+        let mut data = vec![0u8; 7];
+        for (tag, value) in [(1u8, [7u8; 32]), (23u8, {
+            let mut hash = [0u8; 32];
+            hash.copy_from_slice(digest(&SHA256, description.as_bytes()).as_ref());
+            hash
+        })] {
+            data.extend([tag, 1, 20]);
+            data.extend(to_groups(&value));
+        }
+        data.extend([0u8; 104]);
+
+        bech32_encode(hrp, &data)
+    }
+
+    fn zap_request(relay: &str, amount: Option<&str>) -> String {
+        zap_request_to(relay, amount, RECIPIENT)
+    }
+
+    fn zap_request_to(relay: &str, amount: Option<&str>, recipient: &str) -> String {
+        let mut tags = vec![
+            vec!["relays".to_string(), relay.to_string()],
+            vec!["p".to_string(), recipient.to_string()],
+        ];
+        if let Some(amount) = amount {
+            tags.push(vec!["amount".to_string(), amount.to_string()]);
+        }
+        let sender = Keys::from_secret_hex(SENDER_SECRET).unwrap();
+        serde_json::to_string(&Event::sign(&sender, 1682947000, ZAP_REQUEST_KIND, tags, "Great post".to_string())).unwrap()
+    }
+
+    fn payment(zap_request: &str, bolt11: String) -> PaymentEvent {
+        PaymentEvent {
+            checking_id: "checking".to_string(),
+            pending: false,
+            amount: 21_000,
+            fee: 0,
+            memo: String::new(),
+            time: 1682947081,
+            bolt11,
+            preimage: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+            payment_hash: "0707070707070707070707070707070707070707070707070707070707070707".to_string(),
+            expiry: 1682947681,
+            extra: serde_json::json!({ "nostr": zap_request }),
+            wallet_id: "wallet".to_string(),
+            webhook: "https://localhost/lnbits/webhook".to_string(),
+            webhook_status: None,
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_zap_receipt_published_to_relay() {
+        let (relay, received) = start_relay().await;
+        let zap_request = zap_request(&relay, Some("21000"));
+        let payment = payment(&zap_request, invoice("lnbc210n", &zap_request));
+
+        let server = Keys::from_secret_hex(SERVER_SECRET).unwrap();
+        let receipt = Zapper::new(server.clone()).process_payment(&payment).await.unwrap().unwrap();
+
+        receipt.verify().unwrap();
+        assert_eq!(receipt.kind, ZAP_RECEIPT_KIND);
+        assert_eq!(receipt.pubkey, server.public_key());
+        assert_eq!(receipt.created_at, payment.time);
+        assert_eq!(receipt.tag_value("p"), Some(RECIPIENT));
+        assert_eq!(receipt.tag_value("P"), Some(Keys::from_secret_hex(SENDER_SECRET).unwrap().public_key().as_str()));
+        assert_eq!(receipt.tag_value("bolt11"), Some(payment.bolt11.as_str()));
+        assert_eq!(receipt.tag_value("description"), Some(zap_request.as_str()));
+        assert_eq!(receipt.tag_value("preimage"), None);
+
+        assert_eq!(received.lock().unwrap().as_slice(), &[receipt]);
+    }
+
+    #[actix_web::test]
+    async fn test_non_zap_payment_is_ignored() {
+        let mut payment = payment("", invoice("lnbc210n", ""));
+        payment.extra = serde_json::json!({});
+        payment.memo = "test webhook".to_string();
+
+        let server = Keys::from_secret_hex(SERVER_SECRET).unwrap();
+        assert_eq!(Zapper::new(server).process_payment(&payment).await.unwrap(), None);
+    }
+
+    #[actix_web::test]
+    async fn test_pending_payment_is_ignored() {
+        let (relay, received) = start_relay().await;
+        let zap_request = zap_request(&relay, Some("21000"));
+        let mut payment = payment(&zap_request, invoice("lnbc210n", &zap_request));
+        payment.pending = true;
+
+        let server = Keys::from_secret_hex(SERVER_SECRET).unwrap();
+        assert_eq!(Zapper::new(server).process_payment(&payment).await.unwrap(), None);
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_zap_to_other_pubkey_rejected() {
+        let (relay, received) = start_relay().await;
+        let other = Keys::from_secret_hex(SENDER_SECRET).unwrap().public_key();
+        let zap_request = zap_request_to(&relay, Some("21000"), &other);
+        let payment = payment(&zap_request, invoice("lnbc210n", &zap_request));
+
+        let server = Keys::from_secret_hex(SERVER_SECRET).unwrap();
+        let err = Zapper::new(server).process_payment(&payment).await.unwrap_err();
+        assert!(err.to_string().contains("not a recipient"));
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_zap_to_served_recipient_signed_by_server() {
+        let (relay, received) = start_relay().await;
+        let user = Keys::from_secret_hex(USER_SECRET).unwrap().public_key();
+        let zap_request = zap_request_to(&relay, Some("21000"), &user);
+        let payment = payment(&zap_request, invoice("lnbc210n", &zap_request));
+
+        let server = Keys::from_secret_hex(SERVER_SECRET).unwrap();
+        let zapper = Zapper::new(server.clone()).with_recipients([user.to_uppercase()]);
+        let receipt = zapper.process_payment(&payment).await.unwrap().unwrap();
+
+        receipt.verify().unwrap();
+        assert_eq!(receipt.pubkey, server.public_key());
+        assert_eq!(receipt.tag_value("p"), Some(user.as_str()));
+        assert_eq!(received.lock().unwrap().as_slice(), &[receipt]);
+
+        // The server key's own pubkey is no longer served
+        let to_server = zap_request_to(&relay, Some("21000"), RECIPIENT);
+        let mut zap_to_server = payment.clone();
+        zap_to_server.extra = serde_json::json!({ "nostr": to_server });
+        zap_to_server.bolt11 = invoice("lnbc210n", &to_server);
+        assert!(zapper.process_payment(&zap_to_server).await.is_err());
+    }
+
+    #[test]
+    fn test_zap_request_in_memo() {
+        let zap_request = zap_request("ws://localhost", None);
+        let mut payment = payment("", invoice("lnbc210n", &zap_request));
+        payment.extra = serde_json::json!({});
+        payment.memo = zap_request.clone();
+
+        assert_eq!(zap_request_json(&payment), Some(zap_request));
+    }
+
+    #[test]
+    fn test_description_hash_mismatch_rejected() {
+        let zap_request = zap_request("ws://localhost", None);
+        let bolt11 = invoice("lnbc210n", "some other description");
+
+        let err = validate_zap_request(&zap_request, &bolt11, 21_000).unwrap_err();
+        assert!(err.to_string().contains("description hash"));
+    }
+
+    #[test]
+    fn test_amount_mismatch_rejected() {
+        let zap_request = zap_request("ws://localhost", Some("1000"));
+        let bolt11 = invoice("lnbc210n", &zap_request);
+
+        assert!(validate_zap_request(&zap_request, &bolt11, 21_000).is_err());
+    }
+
+    #[test]
+    fn test_tampered_zap_request_rejected() {
+        let zap_request = zap_request("ws://localhost", None).replace("Great post", "Tampered");
+        let bolt11 = invoice("lnbc210n", &zap_request);
+
+        assert!(validate_zap_request(&zap_request, &bolt11, 21_000).is_err());
+    }
+}
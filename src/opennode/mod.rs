@@ -0,0 +1,345 @@
+use anyhow::Result;
+use hex::encode;
+use ring::{hmac, hmac::Key, hmac::HMAC_SHA256};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+
+use crate::bolt11;
+use crate::event::{NormalizedEvent, Provider};
+use crate::metrics::VerifyFailure;
+use crate::verifier::{self, secret_from_env, Headers, WebhookVerifier};
+
+#[cfg(feature = "opennode-api")]
+pub mod opennode_api;
+#[cfg(feature = "actix")]
+pub mod opennode_middleware;
+pub mod opennode_models;
+
+#[cfg(feature = "opennode-api")]
+pub use opennode_api::OpenNodeClient;
+#[cfg(feature = "actix")]
+pub use opennode_middleware::OpenNodeVerify;
+pub use opennode_models::{Charge, ChargeStatus, ChargeWebhook, WithdrawalWebhook};
+
+// Charges are tried first, as only they have a `price`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WebhookPayload {
+    Charge(ChargeWebhook),
+    Withdrawal(WithdrawalWebhook),
+    Unsupported
+}
+
+impl WebhookPayload {
+    /// Parse a webhook body, which OpenNode sends form encoded, or as JSON
+    pub fn parse(body: &str) -> Result<Self> {
+        if body.trim_start().starts_with('{') {
+            Ok(serde_json::from_str(body)?)
+        } else {
+            Ok(serde_urlencoded::from_str(body)?)
+        }
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            WebhookPayload::Charge(charge) => Some(&charge.id),
+            WebhookPayload::Withdrawal(withdrawal) => Some(&withdrawal.id),
+            WebhookPayload::Unsupported => None,
+        }
+    }
+
+    pub fn hashed_order(&self) -> Option<&str> {
+        match self {
+            WebhookPayload::Charge(charge) => Some(&charge.hashed_order),
+            WebhookPayload::Withdrawal(withdrawal) => Some(&withdrawal.hashed_order),
+            WebhookPayload::Unsupported => None,
+        }
+    }
+
+    /// Charge status, or withdrawal status prefixed with `withdrawal.`
+    pub fn event_type(&self) -> String {
+        match self {
+            WebhookPayload::Charge(charge) => charge.status.as_str().to_string(),
+            WebhookPayload::Withdrawal(withdrawal) => format!("withdrawal.{}", withdrawal.status),
+            WebhookPayload::Unsupported => "unknown".to_string(),
+        }
+    }
+
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        match self {
+            WebhookPayload::Charge(charge) => charge.normalize(),
+            // Withdrawals are outgoing payments, which normalized events do not cover
+            WebhookPayload::Withdrawal(_) | WebhookPayload::Unsupported => None,
+        }
+    }
+}
+
+impl ChargeWebhook {
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        let event_type = self.status.event_type()?;
+        Some(NormalizedEvent {
+            // A charge posts once per status
+            id: format!("{}:{}", self.id, self.status.as_str()),
+            provider: Provider::OpenNode,
+            event_type,
+            store_id: None,
+            invoice_id: Some(self.id.clone()),
+            payment_hash: None,
+            amount_msat: self.price.checked_mul(1000),
+            timestamp: None,
+            metadata: order_metadata(self.order_id.as_deref(), self.description.as_deref()),
+        })
+    }
+}
+
+impl Charge {
+    /// The charge as of now, eg. to confirm a webhook or catch up on missed ones
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        let event_type = self.status.event_type()?;
+        let invoice = self.lightning_invoice.as_ref().and_then(|invoice| bolt11::decode(&invoice.payreq).ok());
+        let metadata = match &self.metadata {
+            Value::Object(metadata) if !metadata.is_empty() => self.metadata.clone(),
+            _ => order_metadata(self.order_id.as_deref(), self.description.as_deref()),
+        };
+
+        Some(NormalizedEvent {
+            id: format!("{}:{}", self.id, self.status.as_str()),
+            provider: Provider::OpenNode,
+            event_type,
+            store_id: None,
+            invoice_id: Some(self.id.clone()),
+            payment_hash: invoice.and_then(|invoice| invoice.payment_hash),
+            amount_msat: self.amount.checked_mul(1000),
+            timestamp: self.created_at,
+            metadata,
+        })
+    }
+}
+
+fn order_metadata(order_id: Option<&str>, description: Option<&str>) -> Value {
+    match (order_id, description) {
+        (None, None) => Value::Null,
+        _ => json!({"order_id": order_id, "description": description}),
+    }
+}
+
+/// The `hashed_order` OpenNode sends with a webhook for `id`: hex HMAC-SHA256 of the id, keyed with the API key
+pub fn hashed_order(id: &str, api_key: &str) -> String {
+    let key = Key::new(HMAC_SHA256, api_key.as_bytes());
+    encode(hmac::sign(&key, id.as_bytes()).as_ref())
+}
+
+/// Check a `hashed_order` for `id`, in constant time. It only covers the id, not the charge status or amount
+pub fn verify_hashed_order(id: &str, api_key: &str, hashed_order_value: &str) -> bool {
+    hex::decode(hashed_order_value.trim())
+        .is_ok_and(|signature| verifier::verify_hmac_sha256(api_key.as_bytes(), id.as_bytes(), &signature))
+}
+
+/// Parse a webhook body and check its `hashed_order`. Failures are counted in the metrics.
+pub fn verify_webhook(body: &[u8], api_key: &str) -> Result<WebhookPayload, VerifyFailure> {
+    verifier::verify(&OpenNodeVerifier::new(api_key), &Vec::new(), body)
+}
+
+/// Checks the `hashed_order` field, as OpenNode sends no signature header
+#[derive(Clone)]
+pub struct OpenNodeVerifier {
+    api_key: Option<String>,
+}
+
+impl OpenNodeVerifier {
+    pub fn new(api_key: &str) -> Self {
+        OpenNodeVerifier { api_key: Some(api_key.to_string()) }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(OpenNodeVerifier::new(&secret_from_env("OPENNODE_API_KEY")?))
+    }
+}
+
+impl WebhookVerifier for OpenNodeVerifier {
+    type Payload = WebhookPayload;
+
+    fn provider(&self) -> &'static str {
+        "opennode"
+    }
+
+    fn verify(&self, _headers: &dyn Headers, body: &[u8]) -> Result<WebhookPayload, VerifyFailure> {
+        let payload = std::str::from_utf8(body).ok()
+            .and_then(|body| WebhookPayload::parse(body).ok())
+            .ok_or(VerifyFailure::BadBody)?;
+
+        match (payload.id(), payload.hashed_order(), &self.api_key) {
+            (Some(id), Some(hashed_order), Some(api_key)) if verify_hashed_order(id, api_key, hashed_order) => Ok(payload),
+            (Some(_), Some(_), _) => Err(VerifyFailure::BadSignature),
+            _ => Err(VerifyFailure::BadBody),
+        }
+    }
+
+    fn event_type(&self, payload: &WebhookPayload) -> String {
+        payload.event_type()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    const API_KEY: &str = "test-opennode-key";
+
+    // As posted by OpenNode, with `hashed_order` filled in by `form_charge`
+    const CHARGE_FORM: &str = "id=ch_2a4d1c&callback_url=https%3A%2F%2Fexample.com%2Fopennode%2Fwebhook&success_url=&status=paid&order_id=order-23&description=Article+a1&price=2100&fee=21&auto_settle=false";
+
+    const CHARGE_JSON: &str = r#"{
+  "id": "ch_2a4d1c",
+  "callback_url": "https://example.com/opennode/webhook",
+  "status": "underpaid",
+  "order_id": "order-23",
+  "description": "Article a1",
+  "price": 2100,
+  "fee": 21,
+  "missing_amt": 100,
+  "auto_settle": false,
+  "address": "bc1qexampleaddress",
+  "hashed_order": "HASHED_ORDER"
+}"#;
+
+    const WITHDRAWAL_FORM: &str = "id=wd_77e1&type=ln&amount=5000&reference=&processed_at=1683049755&address=&fee=3&status=confirmed&error=";
+
+    const CHARGE_RESPONSE: &str = r#"{
+  "id": "ch_2a4d1c",
+  "description": "Article a1",
+  "created_at": 1683049755,
+  "status": "paid",
+  "amount": 2100,
+  "callback_url": "https://example.com/opennode/webhook",
+  "order_id": "order-23",
+  "currency": "BTC",
+  "metadata": {},
+  "lightning_invoice": {
+    "expires_at": 1683053355,
+    "payreq": "lnbc100n1pjyl0qfsp533xd0y5zfakhm0ytyauhlgd0jvh0c03ucw7c7eeynaljgzh6yz9spp5t4lw8tmkuft80gpwesfjurjj064cllyjkzhj4ew0z64wwurwew3qdq5w3jhxapqwajky6r0da4sxqzjccqpjrzjqwz34f2ec60uwx0cfhmvfq9lw4j52ct98jr4p5nqwqluynewq7qkszl3wgqq9jqqqqqqqqqqqqqqqqcqjq9qyysgq70qtyljrcp64m7q8lfzezxp2zfasun9flx7mg6aej262gqxsrw94uj0w2y5664ymkapuwrv0gmdzctrjfx0j3xu9qjyeeze5yw0jkhcpqwasks"
+  }
+}"#;
+
+    fn form_charge() -> String {
+        format!("{CHARGE_FORM}&hashed_order={}", hashed_order("ch_2a4d1c", API_KEY))
+    }
+
+    #[test]
+    fn test_parse_charge() {
+        let payload = WebhookPayload::parse(&form_charge()).unwrap();
+        let WebhookPayload::Charge(charge) = &payload else { panic!("not a charge: {payload:?}") };
+        assert_eq!(charge.status, ChargeStatus::Paid);
+        assert_eq!(charge.price, 2100);
+        assert_eq!(charge.fee, Some(21));
+        assert_eq!(charge.success_url.as_deref(), Some(""));
+
+        let event = payload.normalize().unwrap();
+        assert_eq!(event.id, "ch_2a4d1c:paid");
+        assert_eq!(event.provider, Provider::OpenNode);
+        assert_eq!(event.event_type, EventType::InvoiceSettled);
+        assert_eq!(event.amount_msat, Some(2_100_000));
+        assert_eq!(event.metadata, json!({"order_id": "order-23", "description": "Article a1"}));
+
+        let payload = WebhookPayload::parse(CHARGE_JSON).unwrap();
+        let WebhookPayload::Charge(charge) = &payload else { panic!("not a charge: {payload:?}") };
+        assert_eq!(charge.missing_amt, Some(100));
+        assert_eq!(payload.normalize().unwrap().event_type, EventType::PaymentReceived);
+
+        // No amount rather than a wrapped one
+        let overflow = WebhookPayload::parse(&form_charge().replace("price=2100", &format!("price={}", u64::MAX))).unwrap();
+        assert_eq!(overflow.normalize().unwrap().amount_msat, None);
+
+        // Parsing is what `event::normalize` does for OpenNode bodies
+        assert_eq!(crate::event::normalize(Provider::OpenNode, &form_charge()).unwrap().unwrap().invoice_id.as_deref(), Some("ch_2a4d1c"));
+    }
+
+    #[test]
+    fn test_parse_withdrawal() {
+        let body = format!("{WITHDRAWAL_FORM}&hashed_order={}", hashed_order("wd_77e1", API_KEY));
+        let payload = WebhookPayload::parse(&body).unwrap();
+        let WebhookPayload::Withdrawal(withdrawal) = &payload else { panic!("not a withdrawal: {payload:?}") };
+        assert_eq!(withdrawal.withdrawal_type, "ln");
+        assert_eq!(withdrawal.amount, Some(5000));
+        assert_eq!(withdrawal.processed_at, Some(1683049755));
+        assert_eq!(payload.event_type(), "withdrawal.confirmed");
+        assert!(payload.normalize().is_none());
+    }
+
+    #[test]
+    fn test_verify_webhook() {
+        assert!(matches!(verify_webhook(form_charge().as_bytes(), API_KEY), Ok(WebhookPayload::Charge(_))));
+
+        // Hex case does not matter
+        let upper = format!("{CHARGE_FORM}&hashed_order={}", hashed_order("ch_2a4d1c", API_KEY).to_uppercase());
+        assert!(verify_webhook(upper.as_bytes(), API_KEY).is_ok());
+
+        assert_eq!(verify_webhook(form_charge().as_bytes(), "other-key").unwrap_err(), VerifyFailure::BadSignature);
+        let not_hex = format!("{CHARGE_FORM}&hashed_order=zz");
+        assert_eq!(verify_webhook(not_hex.as_bytes(), API_KEY).unwrap_err(), VerifyFailure::BadSignature);
+        assert_eq!(verify_webhook(CHARGE_JSON.as_bytes(), API_KEY).unwrap_err(), VerifyFailure::BadSignature);
+        assert_eq!(verify_webhook(CHARGE_FORM.as_bytes(), API_KEY).unwrap_err(), VerifyFailure::BadBody);
+        assert_eq!(verify_webhook(b"\xff", API_KEY).unwrap_err(), VerifyFailure::BadBody);
+    }
+
+    #[test]
+    fn test_normalize_charge() {
+        let charge: Charge = serde_json::from_str(CHARGE_RESPONSE).unwrap();
+        let event = charge.normalize().unwrap();
+        assert_eq!(event.event_type, EventType::InvoiceSettled);
+        assert_eq!(event.timestamp, Some(1683049755));
+        assert_eq!(event.payment_hash.as_deref(), Some("5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2"));
+        assert_eq!(event.metadata["order_id"], "order-23");
+    }
+
+    #[cfg(feature = "actix")]
+    #[actix_web::test]
+    async fn test_opennode_verify() {
+        use actix_web::{http::header::ContentType, test, web, App, HttpResponse};
+
+        let app = test::init_service(App::new()
+            .service(web::scope("/opennode").wrap(OpenNodeVerify::new(API_KEY)).route("/webhook", web::post().to(
+                |payload: web::ReqData<WebhookPayload>| async move {
+                    HttpResponse::Ok().body(payload.event_type())
+                },
+            )))).await;
+
+        let req = test::TestRequest::post().uri("/opennode/webhook")
+            .insert_header(ContentType::form_url_encoded())
+            .set_payload(form_charge())
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "paid");
+
+        let req = test::TestRequest::post().uri("/opennode/webhook").set_payload(CHARGE_JSON).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/problem+json");
+    }
+
+    #[cfg(all(feature = "actix", feature = "opennode-api"))]
+    #[actix_web::test]
+    async fn test_get_charge() {
+        use actix_web::{web, HttpRequest, HttpResponse};
+
+        let url = verifier::mock_server(|app| {
+            app.route("/v1/charge/{id}", web::get().to(|req: HttpRequest, id: web::Path<String>| async move {
+                let authorized = req.headers().get("authorization").is_some_and(|key| key == API_KEY);
+                match (authorized, id.as_str()) {
+                    (false, _) => HttpResponse::Unauthorized().json(json!({"success": false, "message": "Invalid API key"})),
+                    (true, "ch_2a4d1c") => HttpResponse::Ok().body(format!(r#"{{"data": {CHARGE_RESPONSE}}}"#)),
+                    (true, _) => HttpResponse::NotFound().json(json!({"success": false, "message": "Charge not found"})),
+                }
+            }));
+        });
+
+        let client = OpenNodeClient::new(&url, API_KEY);
+        let charge = client.get_charge("ch_2a4d1c").await.unwrap();
+        assert_eq!(charge.status, ChargeStatus::Paid);
+        assert_eq!(charge.normalize().unwrap().amount_msat, Some(2_100_000));
+
+        let err = client.get_charge("ch_missing").await.unwrap_err();
+        assert!(err.to_string().contains("Charge not found"), "{err}");
+        assert!(OpenNodeClient::new(&url, "other-key").get_charge("ch_2a4d1c").await.is_err());
+    }
+}
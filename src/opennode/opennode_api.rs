@@ -0,0 +1,65 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Deserialize;
+
+use crate::opennode::Charge;
+use crate::telemetry;
+
+pub const OPENNODE_HOST: &str = "https://api.opennode.com";
+
+/// Client for the OpenNode API
+#[derive(Debug, Clone)]
+pub struct OpenNodeClient {
+    host: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+// Successful responses wrap the result in `data`, errors carry a `message`
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    data: Option<T>,
+    message: Option<String>,
+}
+
+impl OpenNodeClient {
+    pub fn new(host: &str, api_key: &str) -> Self {
+        OpenNodeClient {
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Build a client from the `OPENNODE_API_KEY` environment variable, and `OPENNODE_HOST` (eg. the
+    /// dev environment) if set
+    pub fn from_env() -> Result<Self> {
+        let api_key = std::env::var("OPENNODE_API_KEY").map_err(|_| anyhow!("OPENNODE_API_KEY must be set"))?;
+        let host = std::env::var("OPENNODE_HOST").unwrap_or_else(|_| OPENNODE_HOST.to_string());
+        Ok(OpenNodeClient::new(&host, &api_key))
+    }
+
+    /// The key webhooks are verified with
+    pub fn api_key(&self) -> &str {
+        &self.api_key
+    }
+
+    #[tracing::instrument(name = "opennode.get_charge", skip(self))]
+    pub async fn get_charge(&self, id: &str) -> Result<Charge> {
+        let request = self.client
+            .get(format!("{}/v1/charge/{id}", self.host))
+            .header(reqwest::header::AUTHORIZATION, &self.api_key);
+
+        let response = telemetry::inject_trace_context(request).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        // Error bodies would otherwise surface as a missing `data`
+        let response = serde_json::from_str::<ApiResponse<Charge>>(&body)
+            .map_err(|err| anyhow!("charge response ({status}) does not parse: {err}"))?;
+
+        match response.data {
+            Some(charge) if status.is_success() => Ok(charge),
+            _ => bail!("charge request failed with {status}: {}", response.message.unwrap_or_default()),
+        }
+    }
+}
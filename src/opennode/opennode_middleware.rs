@@ -0,0 +1,48 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, body::EitherBody,
+};
+use crate::opennode::OpenNodeVerifier;
+use crate::verifier::{verifier_middleware::VerifyWebhookMiddleware, VerifyWebhook};
+
+/// Checks the `hashed_order` of OpenNode charge and withdrawal webhooks against the API key, or the
+/// `OPENNODE_API_KEY` environment variable by default. Verified requests get the parsed
+/// `opennode::WebhookPayload` in their extensions, eg. for `web::ReqData<WebhookPayload>`.
+#[derive(Clone)]
+pub struct OpenNodeVerify {
+    verify: VerifyWebhook<OpenNodeVerifier>,
+}
+
+impl OpenNodeVerify {
+    pub fn new(api_key: &str) -> Self {
+        OpenNodeVerify { verify: VerifyWebhook::new(OpenNodeVerifier::new(api_key)) }
+    }
+}
+
+// Without the variable every webhook is rejected, as with `BTCPayHeaderVerify`
+impl Default for OpenNodeVerify {
+    fn default() -> Self {
+        let verifier = OpenNodeVerifier::from_env().unwrap_or_else(|err| {
+            error!("No OpenNode API key configured to verify webhooks with: {err}");
+            OpenNodeVerifier { api_key: None }
+        });
+        OpenNodeVerify { verify: VerifyWebhook::new(verifier) }
+    }
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for OpenNodeVerify
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = VerifyWebhookMiddleware<S, OpenNodeVerifier>;
+    type Future = <VerifyWebhook<OpenNodeVerifier> as Transform<S, ServiceRequest>>::Future;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        self.verify.new_transform(service)
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::event::EventType;
+
+// OpenNode sends webhooks as form posts (or JSON), so every field may arrive as a string
+// REF: https://developers.opennode.com/docs/webhooks
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChargeStatus {
+    Unpaid,
+    /// Paid on-chain, waiting for confirmations
+    Processing,
+    Underpaid,
+    Paid,
+    Expired,
+    Refunded,
+    #[serde(other)]
+    Unknown,
+}
+
+impl ChargeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChargeStatus::Unpaid => "unpaid",
+            ChargeStatus::Processing => "processing",
+            ChargeStatus::Underpaid => "underpaid",
+            ChargeStatus::Paid => "paid",
+            ChargeStatus::Expired => "expired",
+            ChargeStatus::Refunded => "refunded",
+            ChargeStatus::Unknown => "unknown",
+        }
+    }
+
+    pub fn event_type(&self) -> Option<EventType> {
+        match self {
+            ChargeStatus::Unpaid => Some(EventType::InvoiceCreated),
+            ChargeStatus::Processing => Some(EventType::InvoiceProcessing),
+            ChargeStatus::Underpaid => Some(EventType::PaymentReceived),
+            ChargeStatus::Paid => Some(EventType::InvoiceSettled),
+            ChargeStatus::Expired => Some(EventType::InvoiceExpired),
+            ChargeStatus::Refunded => Some(EventType::InvoiceInvalid),
+            ChargeStatus::Unknown => None,
+        }
+    }
+}
+
+/// Posted to a charge's `callback_url` when its status changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChargeWebhook {
+    pub id: String,
+    pub status: ChargeStatus,
+    /// Amount in satoshis
+    #[serde(deserialize_with = "amount")]
+    pub price: u64,
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub fee: Option<u64>,
+    /// Left to pay on an underpaid charge, in satoshis
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub missing_amt: Option<u64>,
+    pub order_id: Option<String>,
+    pub description: Option<String>,
+    pub address: Option<String>,
+    pub callback_url: Option<String>,
+    pub success_url: Option<String>,
+    /// HMAC-SHA256 of `id`, keyed with the API key
+    pub hashed_order: String,
+}
+
+/// Posted to a withdrawal's `callback_url` once it has been processed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalWebhook {
+    pub id: String,
+    /// `ln` or `chain`
+    #[serde(rename = "type")]
+    pub withdrawal_type: String,
+    /// Amount in satoshis
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub amount: Option<u64>,
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub fee: Option<u64>,
+    /// eg. `confirmed`, `error` or `failed`
+    pub status: String,
+    pub error: Option<String>,
+    pub reference: Option<String>,
+    pub address: Option<String>,
+    #[serde(default, deserialize_with = "optional_amount")]
+    pub processed_at: Option<u64>,
+    /// HMAC-SHA256 of `id`, keyed with the API key
+    pub hashed_order: String,
+}
+
+/// A charge from `GET /v1/charge/{id}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Charge {
+    pub id: String,
+    pub status: ChargeStatus,
+    /// Amount in satoshis
+    pub amount: u64,
+    pub description: Option<String>,
+    /// Unix timestamp in seconds
+    pub created_at: Option<i64>,
+    pub order_id: Option<String>,
+    pub currency: Option<String>,
+    pub callback_url: Option<String>,
+    pub success_url: Option<String>,
+    pub lightning_invoice: Option<LightningInvoice>,
+    pub chain_invoice: Option<ChainInvoice>,
+    #[serde(default)]
+    pub metadata: Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LightningInvoice {
+    pub payreq: String,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainInvoice {
+    pub address: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Amount {
+    Number(u64),
+    Text(String),
+}
+
+impl Amount {
+    fn value<E: serde::de::Error>(self) -> Result<u64, E> {
+        match self {
+            Amount::Number(amount) => Ok(amount),
+            Amount::Text(amount) => amount.trim().parse().map_err(|_| E::custom(format!("invalid amount: {amount}"))),
+        }
+    }
+}
+
+fn amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    Amount::deserialize(deserializer)?.value()
+}
+
+fn optional_amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    match Option::<Amount>::deserialize(deserializer)? {
+        Some(Amount::Text(amount)) if amount.is_empty() => Ok(None),
+        Some(amount) => amount.value().map(Some),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,294 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::event::{EventType, NormalizedEvent, Provider};
+use crate::metrics::VerifyFailure;
+use crate::verifier::{check_hmac_sha256_hex, hmac_sha256_hex, secret_from_env, Headers, WebhookVerifier};
+
+#[cfg(feature = "phoenixd-api")]
+pub mod phoenixd_api;
+pub mod phoenixd_models;
+
+#[cfg(feature = "phoenixd-api")]
+pub use phoenixd_api::PhoenixdClient;
+pub use phoenixd_models::{CreateInvoice, IncomingPayment, Invoice, PaymentReceived, WebhookPayload};
+
+pub const PHOENIX_SIG_HEADER: &str = "X-Phoenix-Signature";
+
+/// Sign a payload body, returning the hex HMAC-SHA256 phoenixd sends in `X-Phoenix-Signature`
+pub fn sign_payload(payload_body: &[u8], secret: &str) -> String {
+    hmac_sha256_hex(secret.as_bytes(), payload_body)
+}
+
+pub fn verify_signature(payload_body: &[u8], secret: &str, signature_header: &[u8]) -> bool {
+    check_hmac_sha256_hex(secret.as_bytes(), payload_body, signature_header).is_ok()
+}
+
+/// Checks `X-Phoenix-Signature` with the `webhook-secret` from phoenix.conf
+#[derive(Clone)]
+pub struct PhoenixdVerifier {
+    secret: String,
+}
+
+impl PhoenixdVerifier {
+    pub fn new(secret: &str) -> Self {
+        PhoenixdVerifier { secret: secret.to_string() }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(PhoenixdVerifier::new(&secret_from_env("PHOENIXD_WEBHOOK_SECRET")?))
+    }
+}
+
+impl WebhookVerifier for PhoenixdVerifier {
+    type Payload = WebhookPayload;
+
+    fn provider(&self) -> &'static str {
+        "phoenixd"
+    }
+
+    fn verify(&self, headers: &dyn Headers, body: &[u8]) -> Result<WebhookPayload, VerifyFailure> {
+        let signature = headers.header(PHOENIX_SIG_HEADER).ok_or(VerifyFailure::MissingHeader)?;
+        check_hmac_sha256_hex(self.secret.as_bytes(), body, signature)?;
+        serde_json::from_slice(body).map_err(|_| VerifyFailure::BadBody)
+    }
+
+    fn event_type(&self, payload: &WebhookPayload) -> String {
+        payload.event_type().to_string()
+    }
+}
+
+impl WebhookPayload {
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            WebhookPayload::PaymentReceived(_) => phoenixd_models::PAYMENT_RECEIVED,
+            WebhookPayload::Unsupported => "unknown",
+        }
+    }
+
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        match self {
+            WebhookPayload::PaymentReceived(payment) => Some(payment.normalize()),
+            WebhookPayload::Unsupported => None,
+        }
+    }
+}
+
+impl PaymentReceived {
+    /// phoenixd only posts once a payment has fully arrived, so this is a settlement
+    pub fn normalize(&self) -> NormalizedEvent {
+        let metadata = match (&self.payer_note, &self.payer_key) {
+            (None, None) => Value::Null,
+            (payer_note, payer_key) => json!({"payer_note": payer_note, "payer_key": payer_key}),
+        };
+
+        NormalizedEvent {
+            id: format!("{}:settled", self.payment_hash),
+            provider: Provider::Phoenixd,
+            event_type: EventType::InvoiceSettled,
+            store_id: None,
+            invoice_id: self.external_id.clone(),
+            payment_hash: Some(self.payment_hash.clone()),
+            amount_msat: self.amount_sat.checked_mul(1000),
+            timestamp: Some(self.timestamp / 1000),
+            metadata,
+        }
+    }
+}
+
+impl IncomingPayment {
+    /// The payment as of now, eg. to catch up on missed webhooks
+    pub fn normalize(&self) -> NormalizedEvent {
+        let (event_type, state) = match self.is_paid {
+            true => (EventType::InvoiceSettled, "settled"),
+            false => (EventType::InvoiceCreated, "created"),
+        };
+        let metadata = match &self.description {
+            Some(description) => json!({"description": description}),
+            None => Value::Null,
+        };
+
+        NormalizedEvent {
+            id: format!("{}:{state}", self.payment_hash),
+            provider: Provider::Phoenixd,
+            event_type,
+            store_id: None,
+            invoice_id: self.external_id.clone(),
+            payment_hash: Some(self.payment_hash.clone()),
+            amount_msat: self.received_sat.checked_mul(1000).filter(|_| self.is_paid),
+            timestamp: self.completed_at.or(self.created_at).map(|millis| millis / 1000),
+            metadata,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifier::{self, test_headers};
+
+    const SECRET: &str = "phoenixd-webhook-secret";
+
+    // As posted by phoenixd 0.4
+    const PAYMENT_RECEIVED: &str = r#"{"type":"payment_received","timestamp":1683049755123,"amountSat":2100,"paymentHash":"5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2","externalId":"order-23","payerNote":null,"payerKey":null}"#;
+
+    fn headers(signature: &str) -> Vec<(String, String)> {
+        test_headers(&[(PHOENIX_SIG_HEADER, signature)])
+    }
+
+    #[test]
+    fn test_phoenixd_verifier() {
+        let verifier = PhoenixdVerifier::new(SECRET);
+        let signature = sign_payload(PAYMENT_RECEIVED.as_bytes(), SECRET);
+
+        let payload = verifier::verify(&verifier, &headers(&signature), PAYMENT_RECEIVED.as_bytes()).unwrap();
+        let WebhookPayload::PaymentReceived(payment) = &payload else { panic!("not a payment: {payload:?}") };
+        assert_eq!(payment.amount_sat, 2100);
+        assert_eq!(payment.external_id.as_deref(), Some("order-23"));
+
+        let other = sign_payload(PAYMENT_RECEIVED.as_bytes(), "other");
+        assert_eq!(verifier::verify(&verifier, &headers(&other), PAYMENT_RECEIVED.as_bytes()).unwrap_err(), VerifyFailure::BadSignature);
+        assert_eq!(verifier::verify(&verifier, &Vec::new(), PAYMENT_RECEIVED.as_bytes()).unwrap_err(), VerifyFailure::MissingHeader);
+        assert_eq!(verifier::verify(&verifier, &headers("not hex"), PAYMENT_RECEIVED.as_bytes()).unwrap_err(), VerifyFailure::BadHeader);
+        assert_eq!(verifier::verify(&verifier, &headers(&sign_payload(b"[]", SECRET)), b"[]").unwrap_err(), VerifyFailure::BadBody);
+
+        // Types added by later phoenixd versions still verify
+        let future = br#"{"type":"payment_sent","timestamp":1683049755123}"#;
+        let payload = verifier::verify(&verifier, &headers(&sign_payload(future, SECRET)), future).unwrap();
+        assert_eq!(payload.event_type(), "unknown");
+        assert!(payload.normalize().is_none());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let event = crate::event::normalize(Provider::Phoenixd, PAYMENT_RECEIVED).unwrap().unwrap();
+        assert_eq!(event.id, "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2:settled");
+        assert_eq!(event.event_type, EventType::InvoiceSettled);
+        assert_eq!(event.invoice_id.as_deref(), Some("order-23"));
+        assert_eq!(event.amount_msat, Some(2_100_000));
+        assert_eq!(event.timestamp, Some(1683049755));
+        assert_eq!(event.metadata, Value::Null);
+
+        let with_note = PAYMENT_RECEIVED.replace(r#""payerNote":null"#, r#""payerNote":"thanks""#);
+        assert_eq!(crate::event::normalize(Provider::Phoenixd, &with_note).unwrap().unwrap().metadata["payer_note"], "thanks");
+
+        let overflow = PAYMENT_RECEIVED.replace(r#""amountSat":2100"#, &format!(r#""amountSat":{}"#, u64::MAX));
+        assert_eq!(crate::event::normalize(Provider::Phoenixd, &overflow).unwrap().unwrap().amount_msat, None);
+
+        let payment: IncomingPayment = serde_json::from_value(json!({
+            "paymentHash": "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2",
+            "externalId": "order-23",
+            "description": "Article a1",
+            "invoice": "lnbc21u1...",
+            "isPaid": false,
+            "receivedSat": 0,
+            "fees": 0,
+            "createdAt": 1683049681000_i64,
+        })).unwrap();
+        let event = payment.normalize();
+        assert_eq!(event.event_type, EventType::InvoiceCreated);
+        assert_eq!(event.amount_msat, None);
+        assert_eq!(event.timestamp, Some(1683049681));
+    }
+
+    #[cfg(feature = "actix")]
+    #[actix_web::test]
+    async fn test_verify_webhook_middleware() {
+        use crate::verifier::VerifyWebhook;
+        use actix_web::{test, web, App, HttpResponse};
+
+        let app = test::init_service(App::new()
+            .service(web::scope("/phoenixd").wrap(VerifyWebhook::new(PhoenixdVerifier::new(SECRET))).route("/webhook", web::post().to(
+                |payload: web::ReqData<WebhookPayload>| async move { HttpResponse::Ok().body(payload.event_type()) },
+            )))).await;
+
+        let req = test::TestRequest::post().uri("/phoenixd/webhook")
+            .insert_header((PHOENIX_SIG_HEADER, sign_payload(PAYMENT_RECEIVED.as_bytes(), SECRET)))
+            .set_payload(PAYMENT_RECEIVED)
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "payment_received");
+
+        let req = test::TestRequest::post().uri("/phoenixd/webhook").set_payload(PAYMENT_RECEIVED).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+
+    // Against a local mock of the phoenixd API
+    #[cfg(all(feature = "actix", feature = "phoenixd-api"))]
+    #[actix_web::test]
+    async fn test_phoenixd_client() {
+        use actix_web::{web, HttpRequest, HttpResponse};
+        use std::sync::Mutex;
+
+        const PASSWORD: &str = "phoenixd-http-password";
+
+        // Basic auth with an empty user name, ie. `:phoenixd-http-password`
+        fn authorized(req: &HttpRequest) -> bool {
+            req.headers().get("authorization").is_some_and(|value| value == "Basic OnBob2VuaXhkLWh0dHAtcGFzc3dvcmQ=")
+        }
+
+        let payments = web::Data::new(Mutex::new(Vec::<IncomingPayment>::new()));
+        let url = verifier::mock_server(move |app| { app
+            .app_data(payments.clone())
+            .route("/createinvoice", web::post().to(
+                |req: HttpRequest, payments: web::Data<Mutex<Vec<IncomingPayment>>>, form: web::Form<CreateInvoice>| async move {
+                    if !authorized(&req) {
+                        return HttpResponse::Unauthorized().body("Invalid authentication (use basic auth with the http password set in phoenix.conf)");
+                    }
+                    let mut payments = payments.lock().unwrap();
+                    let payment_hash = format!("{:064x}", payments.len() + 1);
+                    payments.push(IncomingPayment {
+                        payment_hash: payment_hash.clone(),
+                        preimage: None,
+                        external_id: form.external_id.clone(),
+                        description: Some(form.description.clone()),
+                        invoice: Some("lnbc21u1...".to_string()),
+                        is_paid: false,
+                        received_sat: 0,
+                        fees: None,
+                        completed_at: None,
+                        created_at: Some(1683049681000),
+                    });
+                    HttpResponse::Ok().json(json!({"amountSat": form.amount_sat, "paymentHash": payment_hash, "serialized": "lnbc21u1..."}))
+                },
+            ))
+            .route("/payments/incoming/{hash}", web::get().to(
+                |payments: web::Data<Mutex<Vec<IncomingPayment>>>, hash: web::Path<String>| async move {
+                    match payments.lock().unwrap().iter().find(|payment| payment.payment_hash == *hash) {
+                        Some(payment) => HttpResponse::Ok().json(payment),
+                        None => HttpResponse::NotFound().body("payment not found"),
+                    }
+                },
+            ))
+            .route("/payments/incoming", web::get().to(
+                |payments: web::Data<Mutex<Vec<IncomingPayment>>>, query: web::Query<std::collections::HashMap<String, String>>| async move {
+                    let payments = payments.lock().unwrap();
+                    let matching: Vec<_> = payments.iter()
+                        .filter(|payment| query.get("externalId").is_none_or(|id| payment.external_id.as_ref() == Some(id)))
+                        .collect();
+                    HttpResponse::Ok().json(matching)
+                },
+            ));
+        });
+
+        let client = PhoenixdClient::new(&url, PASSWORD);
+        let invoice = client.create_invoice(&CreateInvoice {
+            description: "Article a1".to_string(),
+            amount_sat: Some(2100),
+            external_id: Some("order-23".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(invoice.amount_sat, Some(2100));
+
+        let payment = client.get_incoming_payment(&invoice.payment_hash).await.unwrap();
+        assert_eq!(payment.external_id.as_deref(), Some("order-23"));
+        assert_eq!(payment.normalize().event_type, EventType::InvoiceCreated);
+
+        let payments = client.list_incoming_payments("order-23").await.unwrap();
+        assert_eq!(payments.len(), 1);
+        assert!(client.list_incoming_payments("order-24").await.unwrap().is_empty());
+
+        let err = client.get_incoming_payment(&"0".repeat(64)).await.unwrap_err();
+        assert!(err.to_string().contains("payment not found"), "{err}");
+        assert!(PhoenixdClient::new(&url, "other-password").create_invoice(&CreateInvoice::default()).await.is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use anyhow::{anyhow, bail, Result};
+use serde::de::DeserializeOwned;
+
+use crate::phoenixd::{CreateInvoice, IncomingPayment, Invoice};
+use crate::telemetry;
+
+pub const PHOENIXD_HOST: &str = "http://127.0.0.1:9740";
+
+/// Client for the phoenixd HTTP API
+#[derive(Debug, Clone)]
+pub struct PhoenixdClient {
+    host: String,
+    password: String,
+    client: reqwest::Client,
+}
+
+impl PhoenixdClient {
+    /// `password` is the `http-password` from phoenix.conf
+    pub fn new(host: &str, password: &str) -> Self {
+        PhoenixdClient {
+            host: host.trim_end_matches('/').to_string(),
+            password: password.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Build a client from the `PHOENIXD_PASSWORD` environment variable, and `PHOENIXD_HOST` if set
+    pub fn from_env() -> Result<Self> {
+        let password = std::env::var("PHOENIXD_PASSWORD").map_err(|_| anyhow!("PHOENIXD_PASSWORD must be set"))?;
+        let host = std::env::var("PHOENIXD_HOST").unwrap_or_else(|_| PHOENIXD_HOST.to_string());
+        Ok(PhoenixdClient::new(&host, &password))
+    }
+
+    /// Create a BOLT11 invoice. Its `payment_received` webhook carries the `external_id`.
+    #[tracing::instrument(name = "phoenixd.create_invoice", skip_all, fields(external_id = invoice.external_id))]
+    pub async fn create_invoice(&self, invoice: &CreateInvoice) -> Result<Invoice> {
+        self.send(self.client.post(format!("{}/createinvoice", self.host)).form(invoice)).await
+    }
+
+    #[tracing::instrument(name = "phoenixd.get_incoming_payment", skip(self))]
+    pub async fn get_incoming_payment(&self, payment_hash: &str) -> Result<IncomingPayment> {
+        self.send(self.client.get(format!("{}/payments/incoming/{payment_hash}", self.host))).await
+    }
+
+    /// Incoming payments for the invoices created with `external_id`
+    #[tracing::instrument(name = "phoenixd.list_incoming_payments", skip(self))]
+    pub async fn list_incoming_payments(&self, external_id: &str) -> Result<Vec<IncomingPayment>> {
+        let request = self.client
+            .get(format!("{}/payments/incoming", self.host))
+            .query(&[("externalId", external_id)]);
+        self.send(request).await
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let request = request.basic_auth("", Some(&self.password));
+        let response = telemetry::inject_trace_context(request).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        // Errors are plain text
+        if !status.is_success() {
+            bail!("phoenixd request failed with {status}: {body}");
+        }
+        serde_json::from_str(&body).map_err(|err| anyhow!("phoenixd response ({status}) does not parse: {err}"))
+    }
+}
@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+// REF: https://phoenix.acinq.co/server/api
+
+pub const PAYMENT_RECEIVED: &str = "payment_received";
+
+/// A webhook phoenixd posts to its `webhook` url, and to an invoice's `webhookUrl`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WebhookPayload {
+    PaymentReceived(PaymentReceived),
+    #[serde(other)]
+    Unsupported,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaymentReceived {
+    /// Unix milliseconds
+    pub timestamp: i64,
+    pub amount_sat: u64,
+    pub payment_hash: String,
+    /// Set when the invoice was created, see `PhoenixdClient::create_invoice`
+    pub external_id: Option<String>,
+    /// From a BOLT12 payer
+    pub payer_note: Option<String>,
+    pub payer_key: Option<String>,
+}
+
+/// Form for `POST /createinvoice`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInvoice {
+    pub description: String,
+    /// Any amount when `None`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_sat: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiry_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    /// Where `payment_received` is posted for this invoice, besides the configured webhook
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invoice {
+    pub amount_sat: Option<u64>,
+    pub payment_hash: String,
+    /// The BOLT11 invoice
+    pub serialized: String,
+}
+
+/// From `GET /payments/incoming/{paymentHash}` and `GET /payments/incoming?externalId=`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomingPayment {
+    pub payment_hash: String,
+    pub preimage: Option<String>,
+    pub external_id: Option<String>,
+    pub description: Option<String>,
+    pub invoice: Option<String>,
+    pub is_paid: bool,
+    pub received_sat: u64,
+    pub fees: Option<u64>,
+    /// Unix milliseconds
+    pub completed_at: Option<i64>,
+    pub created_at: Option<i64>,
+}
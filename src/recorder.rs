@@ -0,0 +1,468 @@
+// Records raw webhook requests (method, path, headers and the exact body bytes) so production
+// payloads can be reproduced later, and replays them into an `App` or a live URL.
+
+use actix_http::h1;
+use actix_web::{
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode,
+    test::TestRequest,
+    web, Error,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures_util::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    future::{ready, Ready},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "btcpay")]
+use crate::btcpay::{btcpay_middleware::BTCPAY_SIG_HEADER, hmac_sha256};
+use crate::store::WebhookStore;
+
+/// Replaces the value of redacted headers
+pub const REDACTED: &str = "[redacted]";
+
+/// Headers redacted by default. Signature headers are kept, so recordings can be replayed as they arrived.
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &["authorization", "cookie", "proxy-authorization", "x-api-key"];
+
+/// Headers that describe a connection rather than a request, so are not replayed
+pub const HOP_HEADERS: &[&str] = &["host", "content-length", "connection", "transfer-encoding"];
+
+/// A request as received, before verification
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "RecordedJson", try_from = "RecordedJson")]
+pub struct RecordedRequest {
+    /// Unix milliseconds
+    pub recorded_at: i64,
+    pub method: String,
+    /// Path and query string
+    pub path: String,
+    /// In the order received. Non UTF-8 values are converted lossily.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+// The JSONL layout. UTF-8 bodies are kept readable, anything else is hex encoded.
+#[derive(Serialize, Deserialize)]
+struct RecordedJson {
+    recorded_at: i64,
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_hex: Option<String>,
+}
+
+impl From<RecordedRequest> for RecordedJson {
+    fn from(request: RecordedRequest) -> Self {
+        let (body, body_hex) = match String::from_utf8(request.body) {
+            Ok(body) => (Some(body), None),
+            Err(err) => (None, Some(hex::encode(err.into_bytes()))),
+        };
+
+        RecordedJson { recorded_at: request.recorded_at, method: request.method, path: request.path, headers: request.headers, body, body_hex }
+    }
+}
+
+impl TryFrom<RecordedJson> for RecordedRequest {
+    type Error = hex::FromHexError;
+
+    fn try_from(json: RecordedJson) -> Result<Self, Self::Error> {
+        let body = match (json.body, json.body_hex) {
+            (_, Some(body_hex)) => hex::decode(body_hex)?,
+            (Some(body), None) => body.into_bytes(),
+            (None, None) => Vec::new(),
+        };
+
+        Ok(RecordedRequest { recorded_at: json.recorded_at, method: json.method, path: json.path, headers: json.headers, body })
+    }
+}
+
+/// How a replayed request is signed
+#[derive(Debug, Clone)]
+pub enum ReplaySignature {
+    /// Send the recorded `BTCPay-Sig` header as is
+    Original,
+    /// Replace `BTCPay-Sig` with one made with this secret, eg. a test environment's
+    #[cfg(feature = "btcpay")]
+    Resign(String),
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+
+    // The headers to send on replay, signed as requested
+    #[cfg_attr(not(feature = "btcpay"), allow(unused_mut, unused_variables))]
+    fn replay_headers(&self, signature: &ReplaySignature) -> Vec<(String, String)> {
+        let mut headers: Vec<_> = self.headers.iter()
+            .filter(|(name, _)| !HOP_HEADERS.iter().any(|hop| name.eq_ignore_ascii_case(hop)))
+            .cloned()
+            .collect();
+
+        #[cfg(feature = "btcpay")]
+        if let ReplaySignature::Resign(secret) = signature {
+            headers.retain(|(name, _)| !name.eq_ignore_ascii_case(BTCPAY_SIG_HEADER));
+            // Over the exact bytes sent, like `sign_payload`
+            let signature = format!("sha256={}", hmac_sha256(&self.body, secret.as_bytes()));
+            headers.push((BTCPAY_SIG_HEADER.to_string(), signature));
+        }
+
+        headers
+    }
+
+    /// A `TestRequest` for `actix_web::test::call_service`, with the recorded method, path, headers and body
+    pub fn to_test_request(&self, signature: &ReplaySignature) -> Result<TestRequest> {
+        let mut request = TestRequest::default()
+            .method(self.method.parse().context("invalid recorded method")?)
+            .uri(&self.path);
+
+        for (name, value) in self.replay_headers(signature) {
+            request = request.insert_header((name, value));
+        }
+
+        Ok(request.set_payload(self.body.clone()))
+    }
+
+    /// Send to a live server, eg. `http://localhost:4040`, returning the response status
+    #[cfg(feature = "reqwest")]
+    pub async fn replay_to(&self, client: &reqwest::Client, base_url: &str, signature: &ReplaySignature) -> Result<StatusCode> {
+        let url = format!("{}{}", base_url.trim_end_matches('/'), self.path);
+        let mut request = client
+            .request(self.method.parse().context("invalid recorded method")?, url)
+            .body(self.body.clone());
+
+        for (name, value) in self.replay_headers(signature) {
+            request = request.header(name, value);
+        }
+
+        Ok(request.send().await?.status())
+    }
+}
+
+/// Replay a recording into an app from `actix_web::test::init_service`, returning the response status
+pub async fn replay_to_app<S, B>(app: &S, recorded: &RecordedRequest, signature: &ReplaySignature) -> Result<StatusCode>
+where
+    S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = Error>,
+{
+    let request = recorded.to_test_request(signature)?.to_request();
+    let response = app.call(request).await.map_err(|err| anyhow::anyhow!("{err}"))?;
+
+    Ok(response.status())
+}
+
+/// Put a buffered body back on a request, for the services after a middleware that read it
+pub fn bytes_to_payload(buf: web::Bytes) -> dev::Payload {
+    let (_, mut pl) = h1::Payload::create(true);
+    pl.unread_data(buf);
+    dev::Payload::from(pl)
+}
+
+/// Where recordings are written
+#[async_trait]
+pub trait RecordSink: Send + Sync + 'static {
+    async fn record(&self, request: &RecordedRequest) -> Result<()>;
+}
+
+#[async_trait]
+impl RecordSink for Arc<dyn WebhookStore> {
+    async fn record(&self, request: &RecordedRequest) -> Result<()> {
+        self.recording_push(request).await?;
+        Ok(())
+    }
+}
+
+/// JSONL files in a directory, one request per line. A new file is started once the current one
+/// reaches `max_file_bytes`, and the oldest files beyond `max_files` are deleted.
+#[derive(Clone)]
+pub struct JsonlFiles {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    current: Arc<Mutex<Option<(File, u64)>>>,
+}
+
+const JSONL_PREFIX: &str = "webhooks-";
+
+impl JsonlFiles {
+    pub fn new(dir: impl Into<PathBuf>, max_file_bytes: u64, max_files: usize) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+
+        Ok(JsonlFiles { dir, max_file_bytes, max_files: max_files.max(1), current: Arc::new(Mutex::new(None)) })
+    }
+
+    /// The recording files, oldest first
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                name.starts_with(JSONL_PREFIX) && name.ends_with(".jsonl")
+            })
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    fn write_line(&self, line: &[u8]) -> Result<()> {
+        let mut current = self.current.lock().unwrap();
+
+        let rotate = match current.as_ref() {
+            Some((_, size)) => *size >= self.max_file_bytes,
+            None => true,
+        };
+
+        if rotate {
+            // Zero padded, so names sort by age
+            let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+            let path = self.dir.join(format!("{JSONL_PREFIX}{millis:015}.jsonl"));
+            let file = OpenOptions::new().create(true).append(true).open(&path)
+                .with_context(|| format!("failed to open {}", path.display()))?;
+            let size = file.metadata()?.len();
+            *current = Some((file, size));
+
+            for old in self.files()?.iter().rev().skip(self.max_files) {
+                std::fs::remove_file(old)?;
+            }
+        }
+
+        let (file, size) = current.as_mut().unwrap();
+        file.write_all(line)?;
+        *size += line.len() as u64;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RecordSink for JsonlFiles {
+    async fn record(&self, request: &RecordedRequest) -> Result<()> {
+        let mut line = serde_json::to_vec(request)?;
+        line.push(b'\n');
+
+        // Off the worker thread, as writes (and rotation) block
+        let files = self.clone();
+        tokio::task::spawn_blocking(move || files.write_line(&line)).await?
+    }
+}
+
+/// Read the recordings in a JSONL file, in the order they were recorded
+pub fn read_jsonl(path: &Path) -> Result<Vec<RecordedRequest>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(i, line)| serde_json::from_str(&line?).with_context(|| format!("{}:{}", path.display(), i + 1)))
+        .collect()
+}
+
+/// Middleware that records every request before passing it on. Wrap it outside the verify middleware
+/// (ie. the later `.wrap`), so requests that fail verification are recorded too.
+///
+/// ```ignore
+/// web::scope("/btcpay")
+///     .wrap(btcpay_middleware::BTCPayHeaderVerify)
+///     .wrap(Recorder::new(JsonlFiles::new("recordings", 64 << 20, 10)?))
+/// ```
+#[derive(Clone)]
+pub struct Recorder {
+    sink: Arc<dyn RecordSink>,
+    redacted: Arc<HashSet<String>>,
+}
+
+impl Recorder {
+    pub fn new(sink: impl RecordSink) -> Self {
+        Recorder {
+            sink: Arc::new(sink),
+            redacted: Arc::new(DEFAULT_REDACTED_HEADERS.iter().map(|name| name.to_string()).collect()),
+        }
+    }
+
+    /// Also redact this header's value
+    pub fn redact_header(mut self, name: &str) -> Self {
+        Arc::make_mut(&mut self.redacted).insert(name.to_lowercase());
+        self
+    }
+
+    /// Record with these redaction rules, instead of `DEFAULT_REDACTED_HEADERS`
+    pub fn with_redacted_headers(mut self, names: &[&str]) -> Self {
+        self.redacted = Arc::new(names.iter().map(|name| name.to_lowercase()).collect());
+        self
+    }
+
+    fn capture(&self, req: &ServiceRequest, body: &[u8]) -> RecordedRequest {
+        let headers = req.headers().iter().map(|(name, value)| {
+            let value = match self.redacted.contains(name.as_str()) {
+                true => REDACTED.to_string(),
+                false => String::from_utf8_lossy(value.as_bytes()).to_string(),
+            };
+            (name.as_str().to_string(), value)
+        }).collect();
+
+        RecordedRequest {
+            recorded_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or_default(),
+            method: req.method().to_string(),
+            path: req.uri().path_and_query().map(|path| path.as_str()).unwrap_or("/").to_string(),
+            headers,
+            body: body.to_vec(),
+        }
+    }
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for Recorder
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RecorderMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RecorderMiddleware {
+            service: Rc::new(service),
+            recorder: self.clone(),
+        }))
+    }
+}
+
+pub struct RecorderMiddleware<S> {
+    service: Rc<S>,
+    recorder: Recorder,
+}
+
+impl<S, B> Service<ServiceRequest> for RecorderMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let svc = self.service.clone();
+        let recorder = self.recorder.clone();
+
+        Box::pin(async move {
+            let body_bytes = req.extract::<web::Bytes>().await?;
+            req.set_payload(bytes_to_payload(body_bytes.clone()));
+
+            // A failed recording never fails the webhook
+            let recorded = recorder.capture(&req, &body_bytes);
+            if let Err(err) = recorder.sink.record(&recorded).await {
+                error!("Error recording {} {}: {err:?}", recorded.method, recorded.path);
+            }
+
+            svc.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "btcpay")]
+    use crate::btcpay::{btcpay_middleware::{BTCPayHeaderVerify, WebhookSecrets}, sign_payload};
+    #[cfg(feature = "btcpay")]
+    use crate::store::MemoryStore;
+    #[cfg(feature = "btcpay")]
+    use actix_web::{test::init_service, App, HttpResponse};
+
+    #[cfg(feature = "btcpay")]
+    fn app_secrets(secret: &str) -> web::Data<WebhookSecrets> {
+        web::Data::new(WebhookSecrets::new(Some(secret.to_string())))
+    }
+
+    #[test]
+    fn test_jsonl_round_trip_and_rotation() {
+        let dir = std::env::temp_dir().join(format!("lightning-webhook-recordings-{}", std::process::id()));
+        let files = JsonlFiles::new(&dir, 1, 2).unwrap();
+
+        let mut recorded = RecordedRequest {
+            recorded_at: 1683049755000,
+            method: "POST".to_string(),
+            path: "/btcpay/webhook?x=1".to_string(),
+            headers: vec![("btcpay-sig".to_string(), "sha256=abc".to_string())],
+            body: vec![0xff, b'{', b'}'],
+        };
+
+        // Every line goes in a new file, as the limit is 1 byte, and only two are kept
+        for i in 0..3 {
+            recorded.recorded_at += i;
+            actix_web::rt::System::new().block_on(files.record(&recorded)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let paths = files.files().unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(read_jsonl(paths.last().unwrap()).unwrap(), vec![recorded]);
+        assert!(std::fs::read_to_string(&paths[0]).unwrap().contains(r#""body_hex":"ff7b7d""#));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "btcpay")]
+    #[actix_web::test]
+    async fn test_record_and_replay() {
+        let store: Arc<dyn WebhookStore> = Arc::new(MemoryStore::new());
+        let body = r#"{"type":"InvoiceSettled","storeId":"store-a"}"#;
+
+        // Recorded in production, where the secret is "live-secret"
+        let production = init_service(App::new()
+            .app_data(app_secrets("live-secret"))
+            .service(web::scope("/btcpay")
+                .wrap(BTCPayHeaderVerify)
+                .wrap(Recorder::new(store.clone()))
+                .route("/webhook", web::post().to(HttpResponse::Ok)))).await;
+
+        let request = TestRequest::post()
+            .uri("/btcpay/webhook")
+            .insert_header(("BTCPay-Sig", sign_payload(body, "live-secret")))
+            .insert_header(("Authorization", "token hunter2"))
+            .set_payload(body)
+            .to_request();
+        assert!(actix_web::test::call_service(&production, request).await.status().is_success());
+
+        let (_, recorded) = store.recording_list(0, 10).await.unwrap().pop().unwrap();
+        assert_eq!(recorded.body, body.as_bytes());
+        assert_eq!(recorded.header("authorization"), Some(REDACTED));
+
+        // Replayed as is against the same secret, or re-signed for a test environment
+        assert_eq!(replay_to_app(&production, &recorded, &ReplaySignature::Original).await.unwrap(), StatusCode::OK);
+
+        let test_env = init_service(App::new()
+            .app_data(app_secrets("test-secret"))
+            .service(web::scope("/btcpay").wrap(BTCPayHeaderVerify).route("/webhook", web::post().to(HttpResponse::Ok)))).await;
+
+        assert_eq!(replay_to_app(&test_env, &recorded, &ReplaySignature::Original).await.unwrap(), StatusCode::UNAUTHORIZED);
+        assert_eq!(replay_to_app(&test_env, &recorded, &ReplaySignature::Resign("test-secret".to_string())).await.unwrap(), StatusCode::OK);
+
+        // Re-signed over the exact bytes, not a lossy UTF-8 copy
+        let binary = RecordedRequest { body: vec![b'{', 0xff, b'}'], ..recorded };
+        let headers = binary.replay_headers(&ReplaySignature::Resign("test-secret".to_string()));
+        let signature = headers.iter().find(|(name, _)| name == BTCPAY_SIG_HEADER).map(|(_, value)| value.as_str());
+        assert_eq!(signature, Some(format!("sha256={}", hmac_sha256(&binary.body, b"test-secret")).as_str()));
+        assert_ne!(signature, Some(sign_payload(&String::from_utf8_lossy(&binary.body), "test-secret").as_str()));
+    }
+}
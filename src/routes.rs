@@ -1,6 +1,10 @@
-use actix_web::{get, HttpResponse, Responder};
+use actix_web::{get, web, HttpResponse, Responder};
 use serde_json::json;
 
+use crate::health::{CheckStatus, Readiness};
+#[cfg(feature = "metrics")]
+use crate::{inbox::Inbox, metrics};
+
 #[get("/health")]
 pub async fn health_handler() -> impl Responder {
     let response_json = json!({
@@ -9,3 +13,47 @@ pub async fn health_handler() -> impl Responder {
     });
     HttpResponse::Ok().json(response_json)
 }
+
+/// Liveness probe - the process is up and serving requests. Dependencies are not checked,
+/// so an outage elsewhere does not get the pod restarted.
+#[get("/health/live")]
+pub async fn health_live_handler() -> impl Responder {
+    HttpResponse::Ok().json(json!({ "status": CheckStatus::Up }))
+}
+
+/// Readiness probe - 200 when every check passes, otherwise 503 so traffic is held back.
+/// Requires `web::Data<Readiness>` in the app data.
+#[get("/health/ready")]
+pub async fn health_ready_handler(readiness: web::Data<Readiness>) -> impl Responder {
+    let (status, checks) = readiness.run().await;
+    let response_json = json!({
+        "status": status,
+        "checks": checks,
+    });
+
+    match status {
+        CheckStatus::Up => HttpResponse::Ok().json(response_json),
+        CheckStatus::Down => HttpResponse::ServiceUnavailable().json(response_json),
+    }
+}
+
+/// Prometheus scrape endpoint. When `web::Data<Inbox>` is in the app data, the inbox depth is
+/// refreshed on each scrape.
+#[cfg(feature = "metrics")]
+#[get("/metrics")]
+pub async fn metrics_handler(inbox: Option<web::Data<Inbox>>) -> impl Responder {
+    if let Some(inbox) = inbox {
+        match inbox.depth().await {
+            Ok(depth) => metrics::set_inbox_depth(depth),
+            Err(err) => warn!("Could not read inbox depth: {err:?}"),
+        }
+    }
+
+    match metrics::render() {
+        Ok(body) => HttpResponse::Ok().content_type("text/plain; version=0.0.4").body(body),
+        Err(err) => {
+            error!("Error: {err:?}");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
@@ -0,0 +1,268 @@
+// Behaviour every `WebhookStore` must share. Each backend runs `run` against an empty store.
+
+use serde_json::json;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::inbox::InboxStatus;
+use crate::recorder::RecordedRequest;
+use super::{DeliveryAttempt, InboxFilter, InboxOutcome, NewInboxMessage, WebhookStore};
+
+pub(crate) async fn run(store: &dyn WebhookStore) {
+    inbox_claims_in_order(store).await;
+    inbox_retry_and_dead_letter(store).await;
+    inbox_lease_expires(store).await;
+    inbox_list_and_requeue(store).await;
+    inbox_search(store).await;
+    idempotency_keys(store).await;
+    delivery_log(store).await;
+    verification_failures(store).await;
+    checkpoints(store).await;
+    recordings(store).await;
+}
+
+fn new_message(delivery_id: &str) -> NewInboxMessage {
+    NewInboxMessage {
+        provider: "btcpay".to_string(),
+        event_type: Some("InvoiceSettled".to_string()),
+        delivery_id: Some(delivery_id.to_string()),
+        invoice_id: Some("6wmoR7p5UFVzCYuwyiViKX".to_string()),
+        payment_hash: None,
+        verified: true,
+        headers: json!({"btcpay-sig": "sha256=abc"}),
+        body: format!(r#"{{"deliveryId": "{delivery_id}"}}"#),
+    }
+}
+
+const LEASE: Duration = Duration::from_secs(60);
+
+async fn inbox_claims_in_order(store: &dyn WebhookStore) {
+    assert_eq!(store.inbox_depth().await.unwrap(), 0);
+
+    let first = store.inbox_push(new_message("first")).await.unwrap();
+    let second = store.inbox_push(new_message("second")).await.unwrap();
+    assert_ne!(first, second);
+    assert_eq!(store.inbox_depth().await.unwrap(), 2);
+    assert_eq!(store.inbox_status(first).await.unwrap(), Some(InboxStatus::Pending));
+
+    let message = store.inbox_claim(LEASE).await.unwrap().unwrap();
+    assert_eq!(message.id, first);
+    assert_eq!(message.provider, "btcpay");
+    assert_eq!(message.delivery_id.as_deref(), Some("first"));
+    assert_eq!(message.headers["btcpay-sig"], "sha256=abc");
+    assert_eq!(message.body, r#"{"deliveryId": "first"}"#);
+    assert!(message.verified);
+    assert_eq!(message.attempts, 0);
+
+    // The first is leased, so the next claim gets the second
+    assert_eq!(store.inbox_claim(LEASE).await.unwrap().unwrap().id, second);
+    assert!(store.inbox_claim(LEASE).await.unwrap().is_none());
+
+    // Leased messages are still pending
+    assert_eq!(store.inbox_depth().await.unwrap(), 2);
+
+    store.inbox_finish(first, InboxOutcome::Done).await.unwrap();
+    store.inbox_finish(second, InboxOutcome::Done).await.unwrap();
+    assert_eq!(store.inbox_status(first).await.unwrap(), Some(InboxStatus::Done));
+    assert_eq!(store.inbox_depth().await.unwrap(), 0);
+    assert_eq!(store.inbox_status(-1).await.unwrap(), None);
+}
+
+async fn inbox_retry_and_dead_letter(store: &dyn WebhookStore) {
+    let id = store.inbox_push(new_message("retry")).await.unwrap();
+
+    let message = store.inbox_claim(LEASE).await.unwrap().unwrap();
+    store.inbox_finish(message.id, InboxOutcome::Retry { error: "boom".to_string(), delay: Duration::ZERO }).await.unwrap();
+
+    let message = store.inbox_claim(LEASE).await.unwrap().unwrap();
+    assert_eq!(message.id, id);
+    assert_eq!(message.attempts, 1);
+
+    // Not due until the delay passes
+    store.inbox_finish(id, InboxOutcome::Retry { error: "boom".to_string(), delay: Duration::from_secs(3600) }).await.unwrap();
+    assert!(store.inbox_claim(LEASE).await.unwrap().is_none());
+    assert_eq!(store.inbox_status(id).await.unwrap(), Some(InboxStatus::Pending));
+
+    store.inbox_finish(id, InboxOutcome::Dead { error: "boom".to_string() }).await.unwrap();
+    assert_eq!(store.inbox_status(id).await.unwrap(), Some(InboxStatus::Dead));
+}
+
+async fn inbox_lease_expires(store: &dyn WebhookStore) {
+    let id = store.inbox_push(new_message("lease")).await.unwrap();
+
+    // A worker that never finishes its message, eg. after a crash
+    assert_eq!(store.inbox_claim(Duration::ZERO).await.unwrap().unwrap().id, id);
+
+    let message = store.inbox_claim(LEASE).await.unwrap().unwrap();
+    assert_eq!(message.id, id);
+    assert_eq!(message.attempts, 1);
+
+    store.inbox_finish(id, InboxOutcome::Done).await.unwrap();
+    assert!(store.inbox_claim(Duration::ZERO).await.unwrap().is_none());
+}
+
+// Runs after the tests above, which leave "retry" dead and the rest done
+async fn inbox_list_and_requeue(store: &dyn WebhookStore) {
+    let dead = store.inbox_list(InboxStatus::Dead, 10).await.unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].delivery_id.as_deref(), Some("retry"));
+    assert_eq!(dead[0].attempts, 2);
+
+    let done = store.inbox_list(InboxStatus::Done, 2).await.unwrap();
+    assert_eq!(done.iter().map(|m| m.delivery_id.as_deref().unwrap()).collect::<Vec<_>>(), vec!["first", "second"]);
+
+    let id = dead[0].id;
+    assert_eq!(store.inbox_get(id).await.unwrap().unwrap().body, r#"{"deliveryId": "retry"}"#);
+    assert!(store.inbox_get(-1).await.unwrap().is_none());
+
+    assert!(store.inbox_requeue(id).await.unwrap());
+    assert!(!store.inbox_requeue(-1).await.unwrap());
+    assert_eq!(store.inbox_status(id).await.unwrap(), Some(InboxStatus::Pending));
+
+    let message = store.inbox_claim(LEASE).await.unwrap().unwrap();
+    assert_eq!(message.id, id);
+    assert_eq!(message.attempts, 0);
+    store.inbox_finish(id, InboxOutcome::Done).await.unwrap();
+}
+
+// Runs after the tests above, which leave four btcpay messages done
+async fn inbox_search(store: &dyn WebhookStore) {
+    let before = SystemTime::now() - Duration::from_secs(60);
+    let lnbits = store.inbox_push(NewInboxMessage {
+        provider: "lnbits".to_string(),
+        event_type: None,
+        delivery_id: None,
+        invoice_id: None,
+        payment_hash: Some("0b5fc8ee9ab1fb42c1a3b5c2b0b1e27a1a7bd2e07e3e7a51b1fd0b1d0e3e7a55".to_string()),
+        verified: false,
+        headers: json!({}),
+        body: "{}".to_string(),
+    }).await.unwrap();
+
+    let all = store.inbox_search(&InboxFilter { limit: 10, ..Default::default() }).await.unwrap();
+    assert_eq!(all.len(), 5);
+    assert_eq!(all[0].message.id, lnbits);
+    assert_eq!(all[0].status, InboxStatus::Pending);
+    assert!(!all[0].message.verified);
+    assert!(all.windows(2).all(|pair| pair[0].message.id > pair[1].message.id));
+
+    let found = store.inbox_search(&InboxFilter {
+        payment_hash: all[0].message.payment_hash.clone(),
+        limit: 10,
+        ..Default::default()
+    }).await.unwrap();
+    assert_eq!(found.iter().map(|r| r.message.id).collect::<Vec<_>>(), vec![lnbits]);
+
+    let btcpay = InboxFilter {
+        provider: Some("btcpay".to_string()),
+        event_type: Some("InvoiceSettled".to_string()),
+        invoice_id: Some("6wmoR7p5UFVzCYuwyiViKX".to_string()),
+        status: Some(InboxStatus::Done),
+        since: Some(before),
+        until: Some(SystemTime::now() + Duration::from_secs(60)),
+        limit: 2,
+        ..Default::default()
+    };
+    let page = store.inbox_search(&btcpay).await.unwrap();
+    assert_eq!(page.len(), 2);
+    let next = store.inbox_search(&InboxFilter { before_id: Some(page[1].message.id), ..btcpay.clone() }).await.unwrap();
+    assert_eq!(next.len(), 2);
+    assert!(next[0].message.id < page[1].message.id);
+
+    assert!(store.inbox_search(&InboxFilter { until: Some(before), ..btcpay.clone() }).await.unwrap().is_empty());
+    assert!(store.inbox_search(&InboxFilter { provider: Some("opennode".to_string()), ..btcpay }).await.unwrap().is_empty());
+
+    // "retry" was retried twice, dead-lettered, then requeued and processed
+    let retry = all.iter().find(|r| r.message.delivery_id.as_deref() == Some("retry")).unwrap();
+    let record = store.inbox_record(retry.message.id).await.unwrap().unwrap();
+    assert_eq!(record.status, InboxStatus::Done);
+    assert_eq!(record.last_error, None);
+    assert!(record.processed_at.is_some());
+    assert!(store.inbox_record(-1).await.unwrap().is_none());
+
+    let attempts = store.inbox_attempts(retry.message.id).await.unwrap();
+    assert_eq!(attempts.iter().map(|a| a.outcome.as_str()).collect::<Vec<_>>(), vec!["retry", "retry", "dead", "done"]);
+    assert_eq!(attempts[2].error.as_deref(), Some("boom"));
+    assert_eq!(attempts[3].error, None);
+    assert!(store.inbox_attempts(lnbits).await.unwrap().is_empty());
+
+    store.inbox_claim(LEASE).await.unwrap().unwrap();
+    store.inbox_finish(lnbits, InboxOutcome::Dead { error: "unknown wallet".to_string() }).await.unwrap();
+    let record = store.inbox_record(lnbits).await.unwrap().unwrap();
+    assert_eq!(record.status, InboxStatus::Dead);
+    assert_eq!(record.last_error.as_deref(), Some("unknown wallet"));
+    assert_eq!(record.message.attempts, 1);
+}
+
+async fn idempotency_keys(store: &dyn WebhookStore) {
+    assert!(store.idempotency_insert("btcpay", "WZbyGsmWGZvYjRsYCH7Vmt").await.unwrap());
+    assert!(!store.idempotency_insert("btcpay", "WZbyGsmWGZvYjRsYCH7Vmt").await.unwrap());
+    assert!(store.idempotency_insert("lnbits", "WZbyGsmWGZvYjRsYCH7Vmt").await.unwrap());
+}
+
+async fn delivery_log(store: &dyn WebhookStore) {
+    let failed = DeliveryAttempt {
+        delivery_id: 7,
+        attempted_at: UNIX_EPOCH + Duration::from_secs(1683049755),
+        status_code: Some(503),
+        error: Some("subscriber responded with 503".to_string()),
+        duration_ms: 120,
+    };
+    let delivered = DeliveryAttempt {
+        attempted_at: UNIX_EPOCH + Duration::from_secs(1683049815),
+        status_code: Some(200),
+        error: None,
+        ..failed.clone()
+    };
+
+    store.log_delivery(&delivered).await.unwrap();
+    store.log_delivery(&failed).await.unwrap();
+    store.log_delivery(&DeliveryAttempt { delivery_id: 8, ..failed.clone() }).await.unwrap();
+
+    assert_eq!(store.delivery_log(7).await.unwrap(), vec![failed, delivered]);
+    assert!(store.delivery_log(9).await.unwrap().is_empty());
+}
+
+async fn verification_failures(store: &dyn WebhookStore) {
+    let hour_ago = SystemTime::now() - Duration::from_secs(3600);
+    assert!(store.verification_failures(hour_ago).await.unwrap().is_empty());
+
+    store.log_verification_failure("btcpay", "bad_signature", 1).await.unwrap();
+    store.log_verification_failure("btcpay", "bad_signature", 1).await.unwrap();
+    store.log_verification_failure("btcpay", "missing_header", 1).await.unwrap();
+
+    let counts = store.verification_failures(hour_ago).await.unwrap();
+    assert_eq!(counts.iter().map(|c| (c.reason.as_str(), c.count)).collect::<Vec<_>>(), vec![("bad_signature", 2), ("missing_header", 1)]);
+    assert_eq!(counts[0].provider, "btcpay");
+
+    assert!(store.verification_failures(SystemTime::now() + Duration::from_secs(7200)).await.unwrap().is_empty());
+}
+
+async fn checkpoints(store: &dyn WebhookStore) {
+    assert_eq!(store.checkpoint("lnbits").await.unwrap(), None);
+
+    store.set_checkpoint("lnbits", "1682947081").await.unwrap();
+    store.set_checkpoint("lnbits", "1682947090").await.unwrap();
+    assert_eq!(store.checkpoint("lnbits").await.unwrap().as_deref(), Some("1682947090"));
+}
+
+async fn recordings(store: &dyn WebhookStore) {
+    let recorded = RecordedRequest {
+        recorded_at: 1683049755000,
+        method: "POST".to_string(),
+        path: "/btcpay/webhook".to_string(),
+        headers: vec![("btcpay-sig".to_string(), "sha256=abc".to_string()), ("x-forwarded-for".to_string(), "10.0.0.1".to_string())],
+        // Kept byte for byte, even when not UTF-8
+        body: vec![b'{', 0xff, b'}'],
+    };
+
+    let first = store.recording_push(&recorded).await.unwrap();
+    let second = store.recording_push(&RecordedRequest { path: "/lnbits/webhook".to_string(), ..recorded.clone() }).await.unwrap();
+    assert!(second > first);
+
+    let all = store.recording_list(0, 10).await.unwrap();
+    assert_eq!(all, vec![(first, recorded), (second, all[1].1.clone())]);
+    assert_eq!(all[1].1.path, "/lnbits/webhook");
+
+    let after = store.recording_list(first, 10).await.unwrap();
+    assert_eq!(after.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![second]);
+}
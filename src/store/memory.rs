@@ -0,0 +1,284 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use crate::inbox::{InboxMessage, InboxStatus};
+use crate::recorder::RecordedRequest;
+use super::{hour_of, DeliveryAttempt, InboxAttempt, InboxFilter, InboxOutcome, InboxRecord, NewInboxMessage, VerificationFailureCount, WebhookStore};
+
+/// Store that keeps everything in process memory. Intended for tests, as nothing survives a restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    inbox: Vec<MemoryInboxEntry>,
+    inbox_attempts: Vec<(i64, InboxAttempt)>,
+    idempotency_keys: HashSet<(String, String)>,
+    delivery_log: Vec<DeliveryAttempt>,
+    checkpoints: HashMap<String, String>,
+    verification_failures: HashMap<(String, String, i64), i64>,
+    recordings: Vec<RecordedRequest>,
+}
+
+struct MemoryInboxEntry {
+    message: InboxMessage,
+    status: InboxStatus,
+    last_error: Option<String>,
+    processed_at: Option<SystemTime>,
+    next_attempt_at: SystemTime,
+}
+
+impl MemoryInboxEntry {
+    fn record(&self) -> InboxRecord {
+        InboxRecord {
+            message: self.message.clone(),
+            status: self.status,
+            last_error: self.last_error.clone(),
+            processed_at: self.processed_at,
+        }
+    }
+
+    fn matches(&self, filter: &InboxFilter) -> bool {
+        fn eq(wanted: &Option<String>, value: &Option<String>) -> bool {
+            wanted.is_none() || wanted == value
+        }
+
+        let message = &self.message;
+        filter.provider.as_ref().is_none_or(|provider| *provider == message.provider)
+            && eq(&filter.event_type, &message.event_type)
+            && eq(&filter.invoice_id, &message.invoice_id)
+            && eq(&filter.payment_hash, &message.payment_hash)
+            && filter.status.is_none_or(|status| status == self.status)
+            && filter.since.is_none_or(|since| message.received_at >= since)
+            && filter.until.is_none_or(|until| message.received_at < until)
+            && filter.before_id.is_none_or(|before_id| message.id < before_id)
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+}
+
+#[async_trait]
+impl WebhookStore for MemoryStore {
+    async fn inbox_push(&self, message: NewInboxMessage) -> Result<i64> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.inbox.len() as i64 + 1;
+        let now = SystemTime::now();
+
+        state.inbox.push(MemoryInboxEntry {
+            message: InboxMessage {
+                id,
+                provider: message.provider,
+                event_type: message.event_type,
+                delivery_id: message.delivery_id,
+                invoice_id: message.invoice_id,
+                payment_hash: message.payment_hash,
+                verified: message.verified,
+                headers: message.headers,
+                body: message.body,
+                attempts: 0,
+                received_at: now,
+            },
+            status: InboxStatus::Pending,
+            last_error: None,
+            processed_at: None,
+            next_attempt_at: now,
+        });
+
+        Ok(id)
+    }
+
+    async fn inbox_claim(&self, lease: Duration) -> Result<Option<InboxMessage>> {
+        let mut state = self.state.lock().unwrap();
+        let now = SystemTime::now();
+
+        let entry = state.inbox.iter_mut()
+            .filter(|entry| entry.status == InboxStatus::Pending && entry.next_attempt_at <= now)
+            .min_by_key(|entry| (entry.next_attempt_at, entry.message.id));
+
+        Ok(entry.map(|entry| {
+            let message = entry.message.clone();
+            entry.message.attempts += 1;
+            entry.next_attempt_at = now + lease;
+            message
+        }))
+    }
+
+    async fn inbox_finish(&self, id: i64, outcome: InboxOutcome) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let now = SystemTime::now();
+
+        if let Some(entry) = state.inbox.iter_mut().find(|entry| entry.message.id == id) {
+            entry.last_error = outcome.error().map(str::to_string);
+            match outcome {
+                InboxOutcome::Done => entry.status = InboxStatus::Done,
+                InboxOutcome::Retry { delay, .. } => entry.next_attempt_at = now + delay,
+                InboxOutcome::Dead { .. } => entry.status = InboxStatus::Dead,
+            }
+            if entry.status != InboxStatus::Pending {
+                entry.processed_at = Some(now);
+            }
+
+            state.inbox_attempts.push((id, InboxAttempt {
+                attempted_at: now,
+                outcome: outcome.as_str().to_string(),
+                error: outcome.error().map(str::to_string),
+            }));
+        }
+
+        Ok(())
+    }
+
+    async fn inbox_status(&self, id: i64) -> Result<Option<InboxStatus>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.inbox.iter().find(|entry| entry.message.id == id).map(|entry| entry.status))
+    }
+
+    async fn inbox_depth(&self) -> Result<i64> {
+        let state = self.state.lock().unwrap();
+        Ok(state.inbox.iter().filter(|entry| entry.status == InboxStatus::Pending).count() as i64)
+    }
+
+    async fn inbox_get(&self, id: i64) -> Result<Option<InboxMessage>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.inbox.iter().find(|entry| entry.message.id == id).map(|entry| entry.message.clone()))
+    }
+
+    async fn inbox_list(&self, status: InboxStatus, limit: i64) -> Result<Vec<InboxMessage>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.inbox.iter()
+            .filter(|entry| entry.status == status)
+            .take(limit.max(0) as usize)
+            .map(|entry| entry.message.clone())
+            .collect())
+    }
+
+    async fn inbox_requeue(&self, id: i64) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.inbox.iter_mut().find(|entry| entry.message.id == id) {
+            Some(entry) => {
+                entry.status = InboxStatus::Pending;
+                entry.message.attempts = 0;
+                entry.last_error = None;
+                entry.processed_at = None;
+                entry.next_attempt_at = SystemTime::now();
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    async fn inbox_search(&self, filter: &InboxFilter) -> Result<Vec<InboxRecord>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.inbox.iter()
+            .rev()
+            .filter(|entry| entry.matches(filter))
+            .take(filter.limit.max(0) as usize)
+            .map(MemoryInboxEntry::record)
+            .collect())
+    }
+
+    async fn inbox_record(&self, id: i64) -> Result<Option<InboxRecord>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.inbox.iter().find(|entry| entry.message.id == id).map(MemoryInboxEntry::record))
+    }
+
+    async fn inbox_attempts(&self, id: i64) -> Result<Vec<InboxAttempt>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.inbox_attempts.iter()
+            .filter(|(inbox_id, _)| *inbox_id == id)
+            .map(|(_, attempt)| attempt.clone())
+            .collect())
+    }
+
+    async fn idempotency_insert(&self, scope: &str, key: &str) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        Ok(state.idempotency_keys.insert((scope.to_string(), key.to_string())))
+    }
+
+    async fn log_delivery(&self, attempt: &DeliveryAttempt) -> Result<()> {
+        self.state.lock().unwrap().delivery_log.push(attempt.clone());
+        Ok(())
+    }
+
+    async fn delivery_log(&self, delivery_id: i64) -> Result<Vec<DeliveryAttempt>> {
+        let state = self.state.lock().unwrap();
+        let mut attempts: Vec<DeliveryAttempt> = state.delivery_log.iter()
+            .filter(|attempt| attempt.delivery_id == delivery_id)
+            .cloned()
+            .collect();
+
+        // Stable, so attempts made at the same time stay in insertion order
+        attempts.sort_by_key(|attempt| attempt.attempted_at);
+        Ok(attempts)
+    }
+
+    async fn log_verification_failure(&self, provider: &str, reason: &str, count: i64) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let key = (provider.to_string(), reason.to_string(), hour_of(SystemTime::now()));
+        *state.verification_failures.entry(key).or_default() += count;
+        Ok(())
+    }
+
+    async fn verification_failures(&self, since: SystemTime) -> Result<Vec<VerificationFailureCount>> {
+        let state = self.state.lock().unwrap();
+        let since = hour_of(since);
+
+        let mut counts: BTreeMap<(String, String), i64> = BTreeMap::new();
+        for ((provider, reason, hour), count) in &state.verification_failures {
+            if *hour >= since {
+                *counts.entry((provider.clone(), reason.clone())).or_default() += count;
+            }
+        }
+
+        Ok(counts.into_iter()
+            .map(|((provider, reason), count)| VerificationFailureCount { provider, reason, count })
+            .collect())
+    }
+
+    async fn checkpoint(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.state.lock().unwrap().checkpoints.get(name).cloned())
+    }
+
+    async fn set_checkpoint(&self, name: &str, value: &str) -> Result<()> {
+        self.state.lock().unwrap().checkpoints.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    async fn recording_push(&self, request: &RecordedRequest) -> Result<i64> {
+        let mut state = self.state.lock().unwrap();
+        state.recordings.push(request.clone());
+        Ok(state.recordings.len() as i64)
+    }
+
+    async fn recording_list(&self, after_id: i64, limit: i64) -> Result<Vec<(i64, RecordedRequest)>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.recordings.iter()
+            .enumerate()
+            .map(|(i, request)| (i as i64 + 1, request.clone()))
+            .filter(|(id, _)| *id > after_id)
+            .take(limit.max(0) as usize)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_conformance() {
+        crate::store::conformance::run(&MemoryStore::new()).await;
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::Value;
+use std::time::{Duration, SystemTime};
+
+use crate::inbox::{InboxMessage, InboxStatus};
+use crate::recorder::RecordedRequest;
+
+pub mod memory;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(test)]
+mod conformance;
+
+pub use memory::MemoryStore;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+/// A verified webhook request, ready to be stored in the inbox
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewInboxMessage {
+    pub provider: String,
+    pub event_type: Option<String>,
+    pub delivery_id: Option<String>,
+    pub invoice_id: Option<String>,
+    pub payment_hash: Option<String>,
+    /// Whether a verifier accepted the request, see `inbox::Verified`
+    pub verified: bool,
+    pub headers: Value,
+    pub body: String,
+}
+
+/// Criteria for `WebhookStore::inbox_search`. Unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InboxFilter {
+    pub provider: Option<String>,
+    pub event_type: Option<String>,
+    pub invoice_id: Option<String>,
+    pub payment_hash: Option<String>,
+    pub status: Option<InboxStatus>,
+    /// Received at or after
+    pub since: Option<SystemTime>,
+    /// Received before
+    pub until: Option<SystemTime>,
+    /// Only messages with a lower id, for paging through results
+    pub before_id: Option<i64>,
+    pub limit: i64,
+}
+
+/// An inbox message with its processing state
+#[derive(Debug, Clone)]
+pub struct InboxRecord {
+    pub message: InboxMessage,
+    pub status: InboxStatus,
+    pub last_error: Option<String>,
+    pub processed_at: Option<SystemTime>,
+}
+
+/// A single attempt to process an inbox message, as recorded by `WebhookStore::inbox_finish`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboxAttempt {
+    pub attempted_at: SystemTime,
+    /// "done", "retry" or "dead"
+    pub outcome: String,
+    pub error: Option<String>,
+}
+
+/// Result of processing a claimed inbox message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InboxOutcome {
+    Done,
+    Retry { error: String, delay: Duration },
+    Dead { error: String },
+}
+
+impl InboxOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InboxOutcome::Done => "done",
+            InboxOutcome::Retry { .. } => "retry",
+            InboxOutcome::Dead { .. } => "dead",
+        }
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            InboxOutcome::Done => None,
+            InboxOutcome::Retry { error, .. } | InboxOutcome::Dead { error } => Some(error),
+        }
+    }
+}
+
+/// A single attempt to deliver an event downstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryAttempt {
+    pub delivery_id: i64,
+    pub attempted_at: SystemTime,
+    pub status_code: Option<i32>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+/// Requests from a provider rejected for a reason (eg. "bad_signature"), see `metrics::VerifyFailure`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationFailureCount {
+    pub provider: String,
+    pub reason: String,
+    pub count: i64,
+}
+
+/// Persistence used by the crate itself - the webhook inbox, idempotency keys, a delivery log,
+/// checkpoints, verification failure counts and recordings. The relay keeps its own queue and
+/// delivery log in Postgres, see `relay::Relay`.
+#[async_trait]
+pub trait WebhookStore: Send + Sync + 'static {
+    /// Store a webhook request for processing, returning its id
+    async fn inbox_push(&self, message: NewInboxMessage) -> Result<i64>;
+
+    /// Claim the oldest due message, counting it as an attempt. The message is hidden from other
+    /// workers for `lease`, so one that is never finished (eg. after a crash) is retried.
+    async fn inbox_claim(&self, lease: Duration) -> Result<Option<InboxMessage>>;
+
+    /// Record the outcome of processing a claimed message, logging it as an attempt
+    async fn inbox_finish(&self, id: i64, outcome: InboxOutcome) -> Result<()>;
+
+    async fn inbox_status(&self, id: i64) -> Result<Option<InboxStatus>>;
+
+    /// Number of pending messages, including those not due yet
+    async fn inbox_depth(&self) -> Result<i64>;
+
+    async fn inbox_get(&self, id: i64) -> Result<Option<InboxMessage>>;
+
+    /// Up to `limit` messages with the status, oldest first. `attempts` counts every attempt made.
+    async fn inbox_list(&self, status: InboxStatus, limit: i64) -> Result<Vec<InboxMessage>>;
+
+    /// Make a message (eg. a dead-letter) pending and due now, with its attempts reset.
+    /// Returns false when there is no such message.
+    async fn inbox_requeue(&self, id: i64) -> Result<bool>;
+
+    /// Messages matching the filter, newest first. `message.attempts` counts every attempt made.
+    async fn inbox_search(&self, filter: &InboxFilter) -> Result<Vec<InboxRecord>>;
+
+    async fn inbox_record(&self, id: i64) -> Result<Option<InboxRecord>>;
+
+    /// Attempts logged for a message, oldest first
+    async fn inbox_attempts(&self, id: i64) -> Result<Vec<InboxAttempt>>;
+
+    /// Record a key within a scope (eg. a provider's delivery ids). Returns false if it was already recorded.
+    async fn idempotency_insert(&self, scope: &str, key: &str) -> Result<bool>;
+
+    async fn log_delivery(&self, attempt: &DeliveryAttempt) -> Result<()>;
+
+    /// Attempts for a delivery, oldest first
+    async fn delivery_log(&self, delivery_id: i64) -> Result<Vec<DeliveryAttempt>>;
+
+    /// Count `count` webhook requests rejected before processing. Counts are kept per hour.
+    async fn log_verification_failure(&self, provider: &str, reason: &str, count: i64) -> Result<()>;
+
+    /// Rejected requests from the hour containing `since` onwards, ordered by provider and reason
+    async fn verification_failures(&self, since: SystemTime) -> Result<Vec<VerificationFailureCount>>;
+
+    async fn checkpoint(&self, name: &str) -> Result<Option<String>>;
+
+    async fn set_checkpoint(&self, name: &str, value: &str) -> Result<()>;
+
+    /// Keep a raw request, see `recorder::Recorder`. Returns its id.
+    async fn recording_push(&self, request: &RecordedRequest) -> Result<i64>;
+
+    /// Up to `limit` recordings with an id above `after_id`, oldest first, with their ids
+    async fn recording_list(&self, after_id: i64, limit: i64) -> Result<Vec<(i64, RecordedRequest)>>;
+}
+
+/// Start of the hour containing `time`, in unix seconds
+pub(crate) fn hour_of(time: SystemTime) -> i64 {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    secs - secs % 3600
+}
@@ -0,0 +1,448 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::inbox::{InboxMessage, InboxStatus};
+use crate::recorder::RecordedRequest;
+use super::{hour_of, DeliveryAttempt, InboxAttempt, InboxFilter, InboxOutcome, InboxRecord, NewInboxMessage, VerificationFailureCount, WebhookStore};
+
+// Applied in order, tracked with `PRAGMA user_version`
+const SQLITE_MIGRATIONS: &[&str] = &[
+    include_str!("../../migrations/sqlite/0001_store.sql"),
+    include_str!("../../migrations/sqlite/0002_recordings.sql"),
+    include_str!("../../migrations/sqlite/0003_inbox_search.sql"),
+    include_str!("../../migrations/sqlite/0004_verification_failures.sql"),
+    include_str!("../../migrations/sqlite/0005_inbox_verified.sql"),
+    include_str!("../../migrations/sqlite/0006_store.sql"),
+];
+
+/// Store backed by a single SQLite database, for small single node deployments.
+/// Queries run on the blocking thread pool, one at a time.
+#[derive(Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path`, bringing its schema up to date
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        SqliteStore::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self> {
+        SqliteStore::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        conn.busy_timeout(Duration::from_secs(5))?;
+
+        let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+        let trans = conn.transaction()?;
+        for sql in SQLITE_MIGRATIONS.iter().skip(version) {
+            trans.execute_batch(sql)?;
+        }
+        trans.pragma_update(None, "user_version", SQLITE_MIGRATIONS.len())?;
+        trans.commit()?;
+
+        Ok(SqliteStore { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    async fn call<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+}
+
+#[async_trait]
+impl WebhookStore for SqliteStore {
+    async fn inbox_push(&self, message: NewInboxMessage) -> Result<i64> {
+        self.call(move |conn| {
+            let now = to_millis(SystemTime::now());
+            conn.execute("
+                INSERT INTO webhook_inbox (provider, event_type, delivery_id, invoice_id, payment_hash, verified, headers, body, next_attempt_at, received_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)
+            ", params![message.provider, message.event_type, message.delivery_id, message.invoice_id, message.payment_hash, message.verified, message.headers.to_string(), message.body, now])?;
+
+            Ok(conn.last_insert_rowid())
+        }).await
+    }
+
+    async fn inbox_claim(&self, lease: Duration) -> Result<Option<InboxMessage>> {
+        self.call(move |conn| {
+            let now = SystemTime::now();
+            let trans = conn.transaction()?;
+
+            let message = trans.query_row("
+                SELECT id, provider, event_type, delivery_id, invoice_id, payment_hash, verified, headers, body, attempts, received_at
+                FROM webhook_inbox
+                WHERE status = 'pending' AND next_attempt_at <= ?1
+                ORDER BY next_attempt_at, id
+                LIMIT 1
+            ", params![to_millis(now)], inbox_message).optional()?;
+
+            if let Some(message) = &message {
+                trans.execute("
+                    UPDATE webhook_inbox SET attempts = attempts + 1, next_attempt_at = ?2 WHERE id = ?1
+                ", params![message.id, to_millis(now + lease)])?;
+            }
+
+            trans.commit()?;
+
+            Ok(message)
+        }).await
+    }
+
+    async fn inbox_finish(&self, id: i64, outcome: InboxOutcome) -> Result<()> {
+        self.call(move |conn| {
+            let now = SystemTime::now();
+            let trans = conn.transaction()?;
+
+            trans.execute("
+                INSERT INTO webhook_inbox_attempts (inbox_id, attempted_at, outcome, error)
+                SELECT id, ?2, ?3, ?4 FROM webhook_inbox WHERE id = ?1
+            ", params![id, to_millis(now), outcome.as_str(), outcome.error()])?;
+
+            match outcome {
+                InboxOutcome::Done => {
+                    trans.execute("
+                        UPDATE webhook_inbox SET status = ?2, last_error = NULL, processed_at = ?3 WHERE id = ?1
+                    ", params![id, InboxStatus::Done.as_str(), to_millis(now)])?;
+                },
+                InboxOutcome::Retry { error, delay } => {
+                    trans.execute("
+                        UPDATE webhook_inbox SET last_error = ?2, next_attempt_at = ?3 WHERE id = ?1
+                    ", params![id, error, to_millis(now + delay)])?;
+                },
+                InboxOutcome::Dead { error } => {
+                    trans.execute("
+                        UPDATE webhook_inbox SET status = ?2, last_error = ?3, processed_at = ?4 WHERE id = ?1
+                    ", params![id, InboxStatus::Dead.as_str(), error, to_millis(now)])?;
+                },
+            }
+
+            trans.commit()?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn inbox_status(&self, id: i64) -> Result<Option<InboxStatus>> {
+        self.call(move |conn| {
+            let status: Option<String> = conn
+                .query_row("SELECT status FROM webhook_inbox WHERE id = ?1", params![id], |row| row.get(0))
+                .optional()?;
+
+            status.map(|status| status.parse()).transpose()
+        }).await
+    }
+
+    async fn inbox_depth(&self) -> Result<i64> {
+        self.call(|conn| {
+            Ok(conn.query_row("SELECT count(*) FROM webhook_inbox WHERE status = 'pending'", [], |row| row.get(0))?)
+        }).await
+    }
+
+    async fn inbox_get(&self, id: i64) -> Result<Option<InboxMessage>> {
+        self.call(move |conn| {
+            Ok(conn.query_row("
+                SELECT id, provider, event_type, delivery_id, invoice_id, payment_hash, verified, headers, body, attempts, received_at
+                FROM webhook_inbox
+                WHERE id = ?1
+            ", params![id], inbox_message).optional()?)
+        }).await
+    }
+
+    async fn inbox_list(&self, status: InboxStatus, limit: i64) -> Result<Vec<InboxMessage>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("
+                SELECT id, provider, event_type, delivery_id, invoice_id, payment_hash, verified, headers, body, attempts, received_at
+                FROM webhook_inbox
+                WHERE status = ?1
+                ORDER BY id
+                LIMIT ?2
+            ")?;
+
+            let messages = stmt.query_map(params![status.as_str(), limit], inbox_message)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(messages)
+        }).await
+    }
+
+    async fn inbox_requeue(&self, id: i64) -> Result<bool> {
+        self.call(move |conn| {
+            let updated = conn.execute("
+                UPDATE webhook_inbox
+                SET status = 'pending', attempts = 0, last_error = NULL, processed_at = NULL, next_attempt_at = ?2
+                WHERE id = ?1
+            ", params![id, to_millis(SystemTime::now())])?;
+
+            Ok(updated == 1)
+        }).await
+    }
+
+    async fn inbox_search(&self, filter: &InboxFilter) -> Result<Vec<InboxRecord>> {
+        let filter = filter.clone();
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("
+                SELECT id, provider, event_type, delivery_id, invoice_id, payment_hash, verified, headers, body, attempts, received_at,
+                    status, last_error, processed_at
+                FROM webhook_inbox
+                WHERE (?1 IS NULL OR provider = ?1)
+                    AND (?2 IS NULL OR event_type = ?2)
+                    AND (?3 IS NULL OR invoice_id = ?3)
+                    AND (?4 IS NULL OR payment_hash = ?4)
+                    AND (?5 IS NULL OR status = ?5)
+                    AND (?6 IS NULL OR received_at >= ?6)
+                    AND (?7 IS NULL OR received_at < ?7)
+                    AND (?8 IS NULL OR id < ?8)
+                ORDER BY id DESC
+                LIMIT ?9
+            ")?;
+
+            let records = stmt.query_map(params![
+                filter.provider, filter.event_type, filter.invoice_id, filter.payment_hash,
+                filter.status.map(|status| status.as_str()), filter.since.map(to_millis), filter.until.map(to_millis),
+                filter.before_id, filter.limit,
+            ], inbox_record)?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(records)
+        }).await
+    }
+
+    async fn inbox_record(&self, id: i64) -> Result<Option<InboxRecord>> {
+        self.call(move |conn| {
+            Ok(conn.query_row("
+                SELECT id, provider, event_type, delivery_id, invoice_id, payment_hash, verified, headers, body, attempts, received_at,
+                    status, last_error, processed_at
+                FROM webhook_inbox
+                WHERE id = ?1
+            ", params![id], inbox_record).optional()?)
+        }).await
+    }
+
+    async fn inbox_attempts(&self, id: i64) -> Result<Vec<InboxAttempt>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("
+                SELECT attempted_at, outcome, error
+                FROM webhook_inbox_attempts
+                WHERE inbox_id = ?1
+                ORDER BY attempted_at, id
+            ")?;
+
+            let attempts = stmt.query_map(params![id], |row| {
+                Ok(InboxAttempt {
+                    attempted_at: from_millis(row.get("attempted_at")?),
+                    outcome: row.get("outcome")?,
+                    error: row.get("error")?,
+                })
+            })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(attempts)
+        }).await
+    }
+
+    async fn idempotency_insert(&self, scope: &str, key: &str) -> Result<bool> {
+        let (scope, key) = (scope.to_string(), key.to_string());
+        self.call(move |conn| {
+            let inserted = conn.execute("
+                INSERT INTO webhook_idempotency_keys (scope, key, created_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT (scope, key) DO NOTHING
+            ", params![scope, key, to_millis(SystemTime::now())])?;
+
+            Ok(inserted == 1)
+        }).await
+    }
+
+    async fn log_delivery(&self, attempt: &DeliveryAttempt) -> Result<()> {
+        let attempt = attempt.clone();
+        self.call(move |conn| {
+            conn.execute("
+                INSERT INTO webhook_delivery_log (delivery_id, attempted_at, status_code, error, duration_ms)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ", params![attempt.delivery_id, to_millis(attempt.attempted_at), attempt.status_code, attempt.error, attempt.duration_ms])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn delivery_log(&self, delivery_id: i64) -> Result<Vec<DeliveryAttempt>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("
+                SELECT delivery_id, attempted_at, status_code, error, duration_ms
+                FROM webhook_delivery_log
+                WHERE delivery_id = ?1
+                ORDER BY attempted_at, id
+            ")?;
+
+            let attempts = stmt.query_map(params![delivery_id], |row| {
+                Ok(DeliveryAttempt {
+                    delivery_id: row.get("delivery_id")?,
+                    attempted_at: from_millis(row.get("attempted_at")?),
+                    status_code: row.get("status_code")?,
+                    error: row.get("error")?,
+                    duration_ms: row.get("duration_ms")?,
+                })
+            })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(attempts)
+        }).await
+    }
+
+    async fn log_verification_failure(&self, provider: &str, reason: &str, count: i64) -> Result<()> {
+        let (provider, reason) = (provider.to_string(), reason.to_string());
+        self.call(move |conn| {
+            conn.execute("
+                INSERT INTO webhook_verification_failures (provider, reason, hour, count) VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (provider, reason, hour) DO UPDATE SET count = count + excluded.count
+            ", params![provider, reason, hour_of(SystemTime::now()), count])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn verification_failures(&self, since: SystemTime) -> Result<Vec<VerificationFailureCount>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("
+                SELECT provider, reason, sum(count) AS count
+                FROM webhook_verification_failures
+                WHERE hour >= ?1
+                GROUP BY provider, reason
+                ORDER BY provider, reason
+            ")?;
+
+            let counts = stmt.query_map(params![hour_of(since)], |row| {
+                Ok(VerificationFailureCount {
+                    provider: row.get("provider")?,
+                    reason: row.get("reason")?,
+                    count: row.get("count")?,
+                })
+            })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(counts)
+        }).await
+    }
+
+    async fn checkpoint(&self, name: &str) -> Result<Option<String>> {
+        let name = name.to_string();
+        self.call(move |conn| {
+            Ok(conn
+                .query_row("SELECT value FROM webhook_checkpoints WHERE name = ?1", params![name], |row| row.get(0))
+                .optional()?)
+        }).await
+    }
+
+    async fn set_checkpoint(&self, name: &str, value: &str) -> Result<()> {
+        let (name, value) = (name.to_string(), value.to_string());
+        self.call(move |conn| {
+            conn.execute("
+                INSERT INTO webhook_checkpoints (name, value, updated_at) VALUES (?1, ?2, ?3)
+                ON CONFLICT (name) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+            ", params![name, value, to_millis(SystemTime::now())])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn recording_push(&self, request: &RecordedRequest) -> Result<i64> {
+        let request = request.clone();
+        self.call(move |conn| {
+            conn.execute("
+                INSERT INTO webhook_recordings (recorded_at, method, path, headers, body)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ", params![request.recorded_at, request.method, request.path, serde_json::to_string(&request.headers)?, request.body])?;
+
+            Ok(conn.last_insert_rowid())
+        }).await
+    }
+
+    async fn recording_list(&self, after_id: i64, limit: i64) -> Result<Vec<(i64, RecordedRequest)>> {
+        self.call(move |conn| {
+            let mut stmt = conn.prepare("
+                SELECT id, recorded_at, method, path, headers, body
+                FROM webhook_recordings
+                WHERE id > ?1
+                ORDER BY id
+                LIMIT ?2
+            ")?;
+
+            let rows = stmt.query_map(params![after_id, limit], |row| {
+                Ok((row.get::<_, i64>("id")?, row.get::<_, i64>("recorded_at")?, row.get::<_, String>("method")?,
+                    row.get::<_, String>("path")?, row.get::<_, String>("headers")?, row.get::<_, Vec<u8>>("body")?))
+            })?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+            rows.into_iter().map(|(id, recorded_at, method, path, headers, body)| {
+                Ok((id, RecordedRequest { recorded_at, method, path, headers: serde_json::from_str(&headers)?, body }))
+            }).collect()
+        }).await
+    }
+}
+
+fn inbox_message(row: &Row) -> rusqlite::Result<InboxMessage> {
+    Ok(InboxMessage {
+        id: row.get("id")?,
+        provider: row.get("provider")?,
+        event_type: row.get("event_type")?,
+        delivery_id: row.get("delivery_id")?,
+        invoice_id: row.get("invoice_id")?,
+        payment_hash: row.get("payment_hash")?,
+        verified: row.get("verified")?,
+        headers: serde_json::from_str(&row.get::<_, String>("headers")?).unwrap_or_default(),
+        body: row.get("body")?,
+        attempts: row.get("attempts")?,
+        received_at: from_millis(row.get("received_at")?),
+    })
+}
+
+fn inbox_record(row: &Row) -> rusqlite::Result<InboxRecord> {
+    let status: String = row.get("status")?;
+    Ok(InboxRecord {
+        message: inbox_message(row)?,
+        status: status.parse().map_err(|err: anyhow::Error| {
+            rusqlite::Error::FromSqlConversionFailure(10, rusqlite::types::Type::Text, err.into())
+        })?,
+        last_error: row.get("last_error")?,
+        processed_at: row.get::<_, Option<i64>>("processed_at")?.map(from_millis),
+    })
+}
+
+fn to_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_conformance() {
+        crate::store::conformance::run(&SqliteStore::open_in_memory().unwrap()).await;
+    }
+
+    #[test]
+    fn test_reopen_keeps_schema_version() {
+        let path = std::env::temp_dir().join(format!("lightning-webhook-store-{}.sqlite", std::process::id()));
+
+        SqliteStore::open(&path).unwrap();
+        let store = SqliteStore::open(&path).unwrap();
+
+        let version: usize = store.conn.lock().unwrap().query_row("PRAGMA user_version", [], |row| row.get(0)).unwrap();
+        assert_eq!(version, SQLITE_MIGRATIONS.len());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
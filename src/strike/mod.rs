@@ -0,0 +1,373 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::event::{parse_timestamp, EventType, NormalizedEvent, Provider};
+use crate::metrics::VerifyFailure;
+use crate::verifier::{check_hmac_sha256_hex, hmac_sha256_hex, secret_from_env, Headers, WebhookVerifier};
+
+#[cfg(feature = "strike-api")]
+pub mod strike_api;
+pub mod strike_models;
+
+#[cfg(feature = "strike-api")]
+pub use strike_api::StrikeClient;
+pub use strike_models::{
+    Amount,
+    EventData,
+    Invoice,
+    InvoiceState,
+    NewSubscription,
+    Receive,
+    ReceiveState,
+    Subscription,
+    WebhookEvent,
+};
+
+pub const STRIKE_SIG_HEADER: &str = "X-Webhook-Signature";
+
+/// Sign a payload body, returning the hex HMAC-SHA256 Strike sends in `X-Webhook-Signature`
+pub fn sign_payload(payload_body: &[u8], secret: &str) -> String {
+    hmac_sha256_hex(secret.as_bytes(), payload_body)
+}
+
+pub fn verify_signature(payload_body: &[u8], secret: &str, signature_header: &[u8]) -> bool {
+    check_hmac_sha256_hex(secret.as_bytes(), payload_body, signature_header).is_ok()
+}
+
+/// Checks `X-Webhook-Signature` with the subscription secret
+#[derive(Clone)]
+pub struct StrikeVerifier {
+    secret: String,
+}
+
+impl StrikeVerifier {
+    pub fn new(secret: &str) -> Self {
+        StrikeVerifier { secret: secret.to_string() }
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Ok(StrikeVerifier::new(&secret_from_env("STRIKE_WEBHOOK_SECRET")?))
+    }
+}
+
+impl WebhookVerifier for StrikeVerifier {
+    type Payload = WebhookEvent;
+
+    fn provider(&self) -> &'static str {
+        "strike"
+    }
+
+    fn verify(&self, headers: &dyn Headers, body: &[u8]) -> Result<WebhookEvent, VerifyFailure> {
+        let signature = headers.header(STRIKE_SIG_HEADER).ok_or(VerifyFailure::MissingHeader)?;
+        check_hmac_sha256_hex(self.secret.as_bytes(), body, signature)?;
+        serde_json::from_slice(body).map_err(|_| VerifyFailure::BadBody)
+    }
+
+    fn event_type(&self, event: &WebhookEvent) -> String {
+        event.event_type.clone()
+    }
+}
+
+impl Invoice {
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        let event_type = match self.state {
+            InvoiceState::Unpaid => EventType::InvoiceCreated,
+            InvoiceState::Pending => EventType::InvoiceProcessing,
+            InvoiceState::Paid => EventType::InvoiceSettled,
+            InvoiceState::Cancelled => EventType::InvoiceInvalid,
+            InvoiceState::Unknown => return None,
+        };
+
+        let metadata = match (&self.correlation_id, &self.description) {
+            (None, None) => Value::Null,
+            (correlation_id, description) => json!({"correlation_id": correlation_id, "description": description}),
+        };
+
+        Some(NormalizedEvent {
+            id: format!("{}:{}", self.invoice_id, event_type),
+            provider: Provider::Strike,
+            event_type,
+            store_id: self.receiver_id.clone(),
+            invoice_id: Some(self.invoice_id.clone()),
+            payment_hash: None,
+            amount_msat: self.amount.msat(),
+            timestamp: self.created.as_deref().and_then(parse_timestamp),
+            metadata,
+        })
+    }
+}
+
+impl Receive {
+    /// The invoice id is the receive request's, so receives can be matched to what was requested
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        let event_type = match self.state {
+            ReceiveState::Pending => EventType::PaymentReceived,
+            ReceiveState::Completed => EventType::InvoiceSettled,
+            ReceiveState::Unknown => return None,
+        };
+        let lightning = self.lightning.as_ref();
+
+        Some(NormalizedEvent {
+            id: format!("{}:{}", self.receive_id, event_type),
+            provider: Provider::Strike,
+            event_type,
+            store_id: None,
+            invoice_id: Some(self.receive_request_id.clone()),
+            payment_hash: lightning.and_then(|lightning| lightning.payment_hash.clone()),
+            amount_msat: self.amount_received.msat(),
+            timestamp: self.completed.as_deref().or(self.created.as_deref()).and_then(parse_timestamp),
+            metadata: json!({
+                "receive_id": self.receive_id,
+                "description": lightning.and_then(|lightning| lightning.description.clone()),
+            }),
+        })
+    }
+}
+
+impl Amount {
+    /// Millisatoshis, for amounts in BTC
+    pub fn msat(&self) -> Option<u64> {
+        if self.currency != "BTC" {
+            return None;
+        }
+
+        // Exact decimal arithmetic, 1 BTC being 10^11 msat
+        let (whole, fraction) = self.amount.trim().split_once('.').unwrap_or((self.amount.trim(), ""));
+        if fraction.len() > 11 || !whole.bytes().chain(fraction.bytes()).all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().ok()? };
+        let fraction: u64 = format!("{fraction:0<11}").parse().ok()?;
+        whole.checked_mul(100_000_000_000)?.checked_add(fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifier::{self, test_headers};
+
+    const SECRET: &str = "strike-webhook-secret";
+
+    const INVOICE_UPDATED: &str = r#"{
+  "id": "6c4fbcc4-8c4b-4a44-9d7c-1d6a2b5b5b0a",
+  "eventType": "invoice.updated",
+  "webhookVersion": "v1",
+  "data": {
+    "entityId": "bb4a3f2e-1f4e-4a2b-8c39-6a1c7b0b9d21",
+    "changes": ["state"]
+  },
+  "created": "2023-05-02T17:49:15.2686637+00:00",
+  "deliverySuccess": false
+}"#;
+
+    fn headers(signature: &str) -> Vec<(String, String)> {
+        test_headers(&[(STRIKE_SIG_HEADER, signature)])
+    }
+
+    #[test]
+    fn test_strike_verifier() {
+        let verifier = StrikeVerifier::new(SECRET);
+        let signature = sign_payload(INVOICE_UPDATED.as_bytes(), SECRET);
+
+        let event = verifier::verify(&verifier, &headers(&signature), INVOICE_UPDATED.as_bytes()).unwrap();
+        assert_eq!(event.event_type, strike_models::INVOICE_UPDATED);
+        assert_eq!(event.data.entity_id, "bb4a3f2e-1f4e-4a2b-8c39-6a1c7b0b9d21");
+        assert_eq!(event.data.changes, ["state"]);
+
+        // Strike sends upper case hex
+        assert!(verifier::verify(&verifier, &headers(&signature.to_uppercase()), INVOICE_UPDATED.as_bytes()).is_ok());
+
+        let other = sign_payload(INVOICE_UPDATED.as_bytes(), "other");
+        assert_eq!(verifier::verify(&verifier, &headers(&other), INVOICE_UPDATED.as_bytes()).unwrap_err(), VerifyFailure::BadSignature);
+        assert_eq!(verifier::verify(&verifier, &Vec::new(), INVOICE_UPDATED.as_bytes()).unwrap_err(), VerifyFailure::MissingHeader);
+        assert_eq!(verifier::verify(&verifier, &headers("not hex"), INVOICE_UPDATED.as_bytes()).unwrap_err(), VerifyFailure::BadHeader);
+        assert_eq!(verifier::verify(&verifier, &headers(&sign_payload(b"{}", SECRET)), b"{}").unwrap_err(), VerifyFailure::BadBody);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let invoice: Invoice = serde_json::from_value(json!({
+            "invoiceId": "bb4a3f2e-1f4e-4a2b-8c39-6a1c7b0b9d21",
+            "amount": {"amount": "0.00021", "currency": "BTC"},
+            "state": "PAID",
+            "created": "2023-05-02T17:49:15.2686637+00:00",
+            "correlationId": "order-23",
+            "description": "Article a1",
+            "issuerId": "bf909224-3432-400b-895a-3010302f80f5",
+            "receiverId": "bf909224-3432-400b-895a-3010302f80f5",
+        })).unwrap();
+        let event = invoice.normalize().unwrap();
+        assert_eq!(event.id, "bb4a3f2e-1f4e-4a2b-8c39-6a1c7b0b9d21:invoice.settled");
+        assert_eq!(event.event_type, EventType::InvoiceSettled);
+        assert_eq!(event.amount_msat, Some(21_000_000));
+        assert_eq!(event.timestamp, Some(1683049755));
+        assert_eq!(event.metadata["correlation_id"], "order-23");
+
+        let receive: Receive = serde_json::from_value(json!({
+            "receiveId": "0b0cc0a2-bf4b-4f18-a1c8-5b4e3a1e1c01",
+            "receiveRequestId": "1b7d1e0f-6a53-4c1f-9f8f-3a8a8b0f0e11",
+            "type": "LIGHTNING",
+            "state": "PENDING",
+            "amountReceived": {"amount": "0.0000001", "currency": "BTC"},
+            "created": "2023-05-02T18:49:15+01:00",
+            "lightning": {"invoice": "lnbc1...", "paymentHash": "5d7ee3af", "description": "tip"},
+        })).unwrap();
+        let event = receive.normalize().unwrap();
+        assert_eq!(event.event_type, EventType::PaymentReceived);
+        assert_eq!(event.invoice_id.as_deref(), Some("1b7d1e0f-6a53-4c1f-9f8f-3a8a8b0f0e11"));
+        assert_eq!(event.payment_hash.as_deref(), Some("5d7ee3af"));
+        assert_eq!(event.amount_msat, Some(10_000));
+        assert_eq!(event.timestamp, Some(1683049755));
+    }
+
+    #[test]
+    fn test_amounts() {
+        let amount = |amount: &str, currency: &str| Amount { amount: amount.to_string(), currency: currency.to_string() }.msat();
+        assert_eq!(amount("1", "BTC"), Some(100_000_000_000));
+        assert_eq!(amount("0.00000000001", "BTC"), Some(1));
+        assert_eq!(amount(".5", "BTC"), Some(50_000_000_000));
+        assert_eq!(amount("0.000000000001", "BTC"), None);
+        assert_eq!(amount("-1", "BTC"), None);
+        assert_eq!(amount("10.00", "USD"), None);
+    }
+
+    #[cfg(feature = "actix")]
+    #[actix_web::test]
+    async fn test_verify_webhook_middleware() {
+        use crate::verifier::VerifyWebhook;
+        use actix_web::{test, web, App, HttpResponse};
+
+        let app = test::init_service(App::new()
+            .service(web::scope("/strike").wrap(VerifyWebhook::new(StrikeVerifier::new(SECRET))).route("/webhook", web::post().to(
+                |event: web::ReqData<WebhookEvent>| async move {
+                    HttpResponse::Ok().body(event.data.entity_id.clone())
+                },
+            )))).await;
+
+        let req = test::TestRequest::post().uri("/strike/webhook")
+            .insert_header((STRIKE_SIG_HEADER, sign_payload(INVOICE_UPDATED.as_bytes(), SECRET)))
+            .set_payload(INVOICE_UPDATED)
+            .to_request();
+        assert_eq!(test::call_and_read_body(&app, req).await, "bb4a3f2e-1f4e-4a2b-8c39-6a1c7b0b9d21");
+
+        let req = test::TestRequest::post().uri("/strike/webhook")
+            .insert_header((STRIKE_SIG_HEADER, sign_payload(INVOICE_UPDATED.as_bytes(), "other")))
+            .set_payload(INVOICE_UPDATED)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+    }
+
+    // Against a local mock of the Strike API
+    #[cfg(all(feature = "actix", feature = "strike-api"))]
+    #[actix_web::test]
+    async fn test_strike_client() {
+        use actix_web::{web, HttpRequest, HttpResponse};
+        use std::sync::Mutex;
+
+        const API_KEY: &str = "strike-api-key";
+
+        fn authorized(req: &HttpRequest) -> bool {
+            req.headers().get("authorization").is_some_and(|value| value == &format!("Bearer {API_KEY}"))
+        }
+
+        fn not_found() -> HttpResponse {
+            HttpResponse::NotFound().json(json!({"traceId": "t1", "data": {"status": 404, "code": "NOT_FOUND", "message": "Entity not found"}}))
+        }
+
+        let subscriptions = web::Data::new(Mutex::new(Vec::<Subscription>::new()));
+        let url = verifier::mock_server(move |app| { app
+            .app_data(subscriptions.clone())
+            .route("/v1/invoices/{id}", web::get().to(|req: HttpRequest, id: web::Path<String>| async move {
+                match (authorized(&req), id.as_str()) {
+                    (false, _) => HttpResponse::Unauthorized().finish(),
+                    (true, "bb4a3f2e-1f4e-4a2b-8c39-6a1c7b0b9d21") => HttpResponse::Ok().json(json!({
+                        "invoiceId": *id,
+                        "amount": {"amount": "0.00021", "currency": "BTC"},
+                        "state": "PAID",
+                        "created": "2023-05-02T17:49:15.2686637+00:00",
+                        "correlationId": "order-23",
+                    })),
+                    (true, _) => not_found(),
+                }
+            }))
+            .route("/v1/receive-requests/receives/{id}", web::get().to(|id: web::Path<String>| async move {
+                HttpResponse::Ok().json(json!({
+                    "receiveId": *id,
+                    "receiveRequestId": "1b7d1e0f-6a53-4c1f-9f8f-3a8a8b0f0e11",
+                    "type": "LIGHTNING",
+                    "state": "COMPLETED",
+                    "amountReceived": {"amount": "0.00001", "currency": "BTC"},
+                    "created": "2023-05-02T17:49:15Z",
+                    "completed": "2023-05-02T17:49:16Z",
+                    "lightning": {"paymentHash": "5d7ee3af"},
+                }))
+            }))
+            .route("/v1/subscriptions", web::get().to(|subscriptions: web::Data<Mutex<Vec<Subscription>>>| async move {
+                HttpResponse::Ok().json(&*subscriptions.lock().unwrap())
+            }))
+            .route("/v1/subscriptions", web::post().to(
+                |subscriptions: web::Data<Mutex<Vec<Subscription>>>, new: web::Json<NewSubscription>| async move {
+                    let mut subscriptions = subscriptions.lock().unwrap();
+                    let subscription = Subscription {
+                        id: format!("sub-{}", subscriptions.len() + 1),
+                        webhook_url: new.webhook_url.clone(),
+                        webhook_version: new.webhook_version.clone(),
+                        enabled: new.enabled,
+                        created: None,
+                        event_types: new.event_types.clone(),
+                    };
+                    subscriptions.push(subscription.clone());
+                    HttpResponse::Created().json(subscription)
+                },
+            ))
+            .route("/v1/subscriptions/{id}", web::patch().to(
+                |subscriptions: web::Data<Mutex<Vec<Subscription>>>, id: web::Path<String>, new: web::Json<NewSubscription>| async move {
+                    let mut subscriptions = subscriptions.lock().unwrap();
+                    let Some(subscription) = subscriptions.iter_mut().find(|subscription| subscription.id == *id) else { return not_found() };
+                    subscription.enabled = new.enabled;
+                    subscription.event_types = new.event_types.clone();
+                    HttpResponse::Ok().json(subscription.clone())
+                },
+            ))
+            .route("/v1/subscriptions/{id}", web::delete().to(
+                |subscriptions: web::Data<Mutex<Vec<Subscription>>>, id: web::Path<String>| async move {
+                    let mut subscriptions = subscriptions.lock().unwrap();
+                    let before = subscriptions.len();
+                    subscriptions.retain(|subscription| subscription.id != *id);
+                    if subscriptions.len() == before { not_found() } else { HttpResponse::NoContent().finish() }
+                },
+            ));
+        });
+
+        let client = StrikeClient::new(&url, API_KEY);
+
+        // A webhook's entity is fetched and normalized
+        let event: WebhookEvent = serde_json::from_str(INVOICE_UPDATED).unwrap();
+        let normalized = client.fetch_event(&event).await.unwrap().unwrap();
+        assert_eq!(normalized.event_type, EventType::InvoiceSettled);
+        assert_eq!(normalized.amount_msat, Some(21_000_000));
+
+        let receive = client.get_receive("0b0cc0a2").await.unwrap().normalize().unwrap();
+        assert_eq!(receive.event_type, EventType::InvoiceSettled);
+        assert_eq!(receive.timestamp, Some(1683049756));
+
+        let err = client.get_invoice("missing").await.unwrap_err();
+        assert!(err.to_string().contains("Entity not found"), "{err}");
+        assert!(StrikeClient::new(&url, "other-key").get_invoice("bb4a3f2e-1f4e-4a2b-8c39-6a1c7b0b9d21").await.is_err());
+
+        // Subscriptions
+        let mut new = NewSubscription::new("https://example.com/strike/webhook", SECRET);
+        let subscription = client.create_subscription(&new).await.unwrap();
+        assert_eq!(subscription.event_types.len(), 4);
+
+        new.event_types = vec![strike_models::INVOICE_UPDATED.to_string()];
+        let updated = client.update_subscription(&subscription.id, &new).await.unwrap();
+        assert_eq!(updated.event_types, [strike_models::INVOICE_UPDATED]);
+        assert_eq!(client.list_subscriptions().await.unwrap().len(), 1);
+
+        client.delete_subscription(&subscription.id).await.unwrap();
+        assert!(client.list_subscriptions().await.unwrap().is_empty());
+        assert!(client.delete_subscription(&subscription.id).await.is_err());
+    }
+}
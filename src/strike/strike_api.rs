@@ -0,0 +1,123 @@
+use anyhow::{anyhow, bail, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::event::NormalizedEvent;
+use crate::strike::{strike_models, Invoice, NewSubscription, Receive, Subscription, WebhookEvent};
+use crate::telemetry;
+
+pub const STRIKE_HOST: &str = "https://api.strike.me";
+
+/// Client for the Strike API
+#[derive(Debug, Clone)]
+pub struct StrikeClient {
+    host: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+// Errors carry their details in `data`
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    data: Option<ErrorData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorData {
+    code: Option<String>,
+    message: Option<String>,
+}
+
+impl StrikeClient {
+    pub fn new(host: &str, api_key: &str) -> Self {
+        StrikeClient {
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Build a client from the `STRIKE_API_KEY` environment variable, and `STRIKE_HOST` if set
+    pub fn from_env() -> Result<Self> {
+        let api_key = std::env::var("STRIKE_API_KEY").map_err(|_| anyhow!("STRIKE_API_KEY must be set"))?;
+        let host = std::env::var("STRIKE_HOST").unwrap_or_else(|_| STRIKE_HOST.to_string());
+        Ok(StrikeClient::new(&host, &api_key))
+    }
+
+    #[tracing::instrument(name = "strike.get_invoice", skip(self))]
+    pub async fn get_invoice(&self, id: &str) -> Result<Invoice> {
+        self.send(self.client.get(format!("{}/v1/invoices/{id}", self.host))).await
+    }
+
+    #[tracing::instrument(name = "strike.get_receive", skip(self))]
+    pub async fn get_receive(&self, id: &str) -> Result<Receive> {
+        self.send(self.client.get(format!("{}/v1/receive-requests/receives/{id}", self.host))).await
+    }
+
+    /// The entity a webhook names, normalized. `None` for event types without a normalized form.
+    pub async fn fetch_event(&self, event: &WebhookEvent) -> Result<Option<NormalizedEvent>> {
+        match event.event_type.as_str() {
+            strike_models::INVOICE_CREATED | strike_models::INVOICE_UPDATED => {
+                Ok(self.get_invoice(&event.data.entity_id).await?.normalize())
+            },
+            strike_models::RECEIVE_PENDING | strike_models::RECEIVE_COMPLETED => {
+                Ok(self.get_receive(&event.data.entity_id).await?.normalize())
+            },
+            _ => Ok(None),
+        }
+    }
+
+    #[tracing::instrument(name = "strike.list_subscriptions", skip(self))]
+    pub async fn list_subscriptions(&self) -> Result<Vec<Subscription>> {
+        self.send(self.client.get(format!("{}/v1/subscriptions", self.host))).await
+    }
+
+    #[tracing::instrument(name = "strike.create_subscription", skip_all, fields(webhook_url = %subscription.webhook_url))]
+    pub async fn create_subscription(&self, subscription: &NewSubscription) -> Result<Subscription> {
+        let request = self.client.post(format!("{}/v1/subscriptions", self.host));
+        self.send(with_json(request, subscription)?).await
+    }
+
+    #[tracing::instrument(name = "strike.update_subscription", skip(self, subscription))]
+    pub async fn update_subscription(&self, id: &str, subscription: &NewSubscription) -> Result<Subscription> {
+        let request = self.client.patch(format!("{}/v1/subscriptions/{id}", self.host));
+        self.send(with_json(request, subscription)?).await
+    }
+
+    #[tracing::instrument(name = "strike.delete_subscription", skip(self))]
+    pub async fn delete_subscription(&self, id: &str) -> Result<()> {
+        let request = self.client.delete(format!("{}/v1/subscriptions/{id}", self.host));
+        let (status, body) = self.execute(request).await?;
+        if !status.is_success() {
+            bail!("delete subscription failed with {status}: {}", error_message(&body));
+        }
+        Ok(())
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T> {
+        let (status, body) = self.execute(request).await?;
+        if !status.is_success() {
+            bail!("strike request failed with {status}: {}", error_message(&body));
+        }
+        serde_json::from_str(&body).map_err(|err| anyhow!("strike response ({status}) does not parse: {err}"))
+    }
+
+    async fn execute(&self, request: reqwest::RequestBuilder) -> Result<(reqwest::StatusCode, String)> {
+        let request = request.bearer_auth(&self.api_key);
+        let response = telemetry::inject_trace_context(request).send().await?;
+        let status = response.status();
+        Ok((status, response.text().await?))
+    }
+}
+
+fn with_json(request: reqwest::RequestBuilder, body: &impl Serialize) -> Result<reqwest::RequestBuilder> {
+    Ok(request.header(reqwest::header::CONTENT_TYPE, "application/json").body(serde_json::to_vec(body)?))
+}
+
+fn error_message(body: &str) -> String {
+    let data = serde_json::from_str::<ErrorResponse>(body).ok().and_then(|response| response.data);
+    match data {
+        Some(ErrorData { message: Some(message), .. }) => message,
+        Some(ErrorData { code: Some(code), .. }) => code,
+        _ => body.to_string(),
+    }
+}
@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+// REF: https://docs.strike.me/webhooks/overview and https://docs.strike.me/api/
+
+pub const INVOICE_CREATED: &str = "invoice.created";
+pub const INVOICE_UPDATED: &str = "invoice.updated";
+pub const RECEIVE_PENDING: &str = "receive-request.receive-pending";
+pub const RECEIVE_COMPLETED: &str = "receive-request.receive-completed";
+
+/// A webhook delivery. It only names the changed entity, which has to be fetched for details.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEvent {
+    /// Unique per event, and kept by redeliveries
+    pub id: String,
+    pub event_type: String,
+    pub webhook_version: Option<String>,
+    pub data: EventData,
+    /// eg. `2023-05-02T17:49:15.123+00:00`
+    pub created: Option<String>,
+    pub delivery_success: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventData {
+    /// Invoice id for `invoice.*` events, receive id for `receive-request.*` events
+    pub entity_id: String,
+    /// Changed fields, eg. `["state"]`
+    #[serde(default)]
+    pub changes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Amount {
+    /// Decimal, eg. `0.00021`
+    pub amount: String,
+    /// eg. `BTC` or `USD`
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum InvoiceState {
+    Unpaid,
+    Pending,
+    Paid,
+    Cancelled,
+    #[serde(other)]
+    Unknown,
+}
+
+/// From `GET /v1/invoices/{invoiceId}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Invoice {
+    pub invoice_id: String,
+    pub amount: Amount,
+    pub state: InvoiceState,
+    pub created: Option<String>,
+    pub correlation_id: Option<String>,
+    pub description: Option<String>,
+    pub issuer_id: Option<String>,
+    pub receiver_id: Option<String>,
+    pub payer_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReceiveState {
+    Pending,
+    Completed,
+    #[serde(other)]
+    Unknown,
+}
+
+/// A payment into a receive request, from `GET /v1/receive-requests/receives/{receiveId}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Receive {
+    pub receive_id: String,
+    pub receive_request_id: String,
+    /// `LIGHTNING` or `ONCHAIN`
+    #[serde(rename = "type")]
+    pub receive_type: String,
+    pub state: ReceiveState,
+    pub amount_received: Amount,
+    pub amount_credited: Option<Amount>,
+    pub created: Option<String>,
+    pub completed: Option<String>,
+    pub lightning: Option<LightningReceive>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LightningReceive {
+    pub invoice: Option<String>,
+    pub preimage: Option<String>,
+    pub description: Option<String>,
+    pub payment_hash: Option<String>,
+}
+
+/// A webhook subscription, from `/v1/subscriptions`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    pub id: String,
+    pub webhook_url: String,
+    pub webhook_version: String,
+    pub enabled: bool,
+    pub created: Option<String>,
+    pub event_types: Vec<String>,
+}
+
+/// Body for creating or updating a subscription. The secret signs deliveries, see `StrikeVerifier`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewSubscription {
+    pub webhook_url: String,
+    pub webhook_version: String,
+    pub secret: String,
+    pub enabled: bool,
+    pub event_types: Vec<String>,
+}
+
+impl NewSubscription {
+    /// An enabled `v1` subscription to the invoice and receive events
+    pub fn new(webhook_url: &str, secret: &str) -> Self {
+        NewSubscription {
+            webhook_url: webhook_url.to_string(),
+            webhook_version: "v1".to_string(),
+            secret: secret.to_string(),
+            enabled: true,
+            event_types: [INVOICE_CREATED, INVOICE_UPDATED, RECEIVE_PENDING, RECEIVE_COMPLETED].map(str::to_string).to_vec(),
+        }
+    }
+}
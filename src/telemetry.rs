@@ -0,0 +1,151 @@
+// Tracing setup. Spans are always recorded through `tracing` (and reach `log` when no subscriber is
+// installed); the `telemetry` feature adds the subscriber, JSON logs and the OTLP exporter.
+
+use reqwest::RequestBuilder;
+
+#[cfg(feature = "telemetry")]
+use anyhow::Result;
+#[cfg(feature = "telemetry")]
+use opentelemetry::{propagation::Injector, trace::TracerProvider as _, KeyValue};
+#[cfg(feature = "telemetry")]
+use opentelemetry_otlp::WithExportConfig;
+#[cfg(feature = "telemetry")]
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::TracerProvider, Resource};
+#[cfg(feature = "telemetry")]
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+#[cfg(feature = "telemetry")]
+#[derive(Debug, Clone)]
+pub struct TelemetryConfig {
+    pub service_name: String,
+    /// OTLP gRPC endpoint, eg. `http://localhost:4317`. Spans are not exported when unset.
+    pub otlp_endpoint: Option<String>,
+    /// Write logs as one JSON object per line, including the current span fields
+    pub json_logs: bool,
+    /// `RUST_LOG` style filter
+    pub filter: String,
+}
+
+#[cfg(feature = "telemetry")]
+impl TelemetryConfig {
+    /// Read `OTEL_SERVICE_NAME`, `OTEL_EXPORTER_OTLP_ENDPOINT`, `LOG_FORMAT` (`json`) and `RUST_LOG`
+    pub fn from_env() -> Self {
+        TelemetryConfig {
+            service_name: std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "lightning-webhook".to_string()),
+            otlp_endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok(),
+            json_logs: std::env::var("LOG_FORMAT").map(|v| v == "json").unwrap_or(false),
+            filter: std::env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()),
+        }
+    }
+}
+
+/// Flushes pending spans when dropped, so keep it alive for the life of the server
+#[cfg(feature = "telemetry")]
+pub struct TelemetryGuard {
+    provider: Option<TracerProvider>,
+}
+
+#[cfg(feature = "telemetry")]
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(err) = provider.shutdown() {
+                eprintln!("Error flushing spans: {err:?}");
+            }
+        }
+    }
+}
+
+/// Install the global subscriber. Records from the `log` macros are captured too.
+#[cfg(feature = "telemetry")]
+pub fn init(config: TelemetryConfig) -> Result<TelemetryGuard> {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let fmt_layer = match config.json_logs {
+        true => tracing_subscriber::fmt::layer().json().with_current_span(true).with_span_list(false).boxed(),
+        false => tracing_subscriber::fmt::layer().boxed(),
+    };
+
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(endpoint)
+                .build()?;
+
+            Some(TracerProvider::builder()
+                .with_batch_exporter(exporter, opentelemetry_sdk::runtime::TokioCurrentThread)
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", config.service_name.clone())]))
+                .build())
+        },
+        None => None,
+    };
+
+    let otel_layer = provider.as_ref().map(|provider| {
+        opentelemetry::global::set_tracer_provider(provider.clone());
+        tracing_opentelemetry::layer().with_tracer(provider.tracer("lightning_rs_webhook"))
+    });
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_new(&config.filter)?)
+        .with(fmt_layer)
+        .with(otel_layer)
+        .try_init()?;
+
+    Ok(TelemetryGuard { provider })
+}
+
+/// Add the current span's W3C trace context (`traceparent`) to an outbound request, so the
+/// call shows up under the webhook that caused it. Does nothing without the `telemetry` feature.
+#[cfg(feature = "telemetry")]
+pub fn inject_trace_context(request: RequestBuilder) -> RequestBuilder {
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    let context = tracing::Span::current().context();
+    let mut headers = reqwest::header::HeaderMap::new();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    request.headers(headers)
+}
+
+#[cfg(not(feature = "telemetry"))]
+pub fn inject_trace_context(request: RequestBuilder) -> RequestBuilder {
+    request
+}
+
+#[cfg(feature = "telemetry")]
+struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+#[cfg(feature = "telemetry")]
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (reqwest::header::HeaderName::from_bytes(key.as_bytes()), value.parse()) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "telemetry"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inject_trace_context() {
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        let request = tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("btcpay.webhook").entered();
+            inject_trace_context(reqwest::Client::new().get("http://localhost/api/v1/health"))
+        });
+
+        let request = request.build().unwrap();
+        let traceparent = request.headers().get("traceparent").unwrap().to_str().unwrap();
+        assert!(traceparent.starts_with("00-"));
+        assert_eq!(traceparent.split('-').count(), 4);
+    }
+}
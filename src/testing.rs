@@ -0,0 +1,675 @@
+// Test helpers: event builders, signed requests and an in-process BTCPay Greenfield API, so
+// full-flow tests run without the network. Built with the `testing` feature.
+
+use actix_web::{
+    http::header::{ContentType, AUTHORIZATION},
+    test::TestRequest,
+    web, App, HttpRequest, HttpResponse, HttpServer,
+};
+use anyhow::Result;
+use bytes::Bytes;
+use reqwest::StatusCode;
+use serde_json::{json, Map, Value};
+use std::sync::Mutex;
+
+use crate::btcpay::{self, btcpay_middleware::BTCPAY_SIG_HEADER, sign_payload, BTCPayClient};
+use crate::lnbits::lnbits_models::{self, PaymentEvent};
+
+pub const STORE_ID: &str = "BJKmPvug3KHVWyu1ECEiAstAQXFjJD1fX87EcgEhHVLT";
+pub const INVOICE_ID: &str = "6wmoR7p5UFVzCYuwyiViKX";
+pub const DELIVERY_ID: &str = "WZbyGsmWGZvYjRsYCH7Vmt";
+pub const WEBHOOK_ID: &str = "AT7ogqNzXkjf12sLVWPDNS";
+pub const WALLET_ID: &str = "b59d05b23e184fa69a13a68c29d62df3";
+pub const PAYMENT_HASH: &str = "5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2";
+
+/// A BTCPay webhook body, with realistic defaults for every field
+///
+/// ```ignore
+/// let body = BTCPayEvent::invoice_settled().invoice_id("inv-1").pos_data(json!({"content_id": "a1"})).body();
+/// ```
+#[derive(Debug, Clone)]
+pub struct BTCPayEvent {
+    json: Map<String, Value>,
+}
+
+impl BTCPayEvent {
+    /// An event of any type, with only the fields shared by all events
+    pub fn new(event_type: &str) -> Self {
+        let json = json!({
+            "deliveryId": DELIVERY_ID,
+            "webhookId": WEBHOOK_ID,
+            "originalDeliveryId": DELIVERY_ID,
+            "isRedelivery": false,
+            "type": event_type,
+            "timestamp": 1683049755,
+            "storeId": STORE_ID,
+            "invoiceId": INVOICE_ID,
+        });
+
+        match json {
+            Value::Object(json) => BTCPayEvent { json },
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn invoice_created() -> Self {
+        BTCPayEvent::new("InvoiceCreated").metadata(json!({"orderId": "23"}))
+    }
+
+    pub fn invoice_received_payment() -> Self {
+        BTCPayEvent::new("InvoiceReceivedPayment")
+            .field("afterExpiration", false)
+            .field("paymentMethod", "BTC-LightningNetwork")
+    }
+
+    pub fn invoice_payment_settled() -> Self {
+        BTCPayEvent::new("InvoicePaymentSettled")
+            .field("afterExpiration", false)
+            .field("paymentMethod", "BTC-LightningNetwork")
+    }
+
+    pub fn invoice_processing() -> Self {
+        BTCPayEvent::new("InvoiceProcessing").field("overPaid", false)
+    }
+
+    pub fn invoice_settled() -> Self {
+        BTCPayEvent::new("InvoiceSettled")
+            .field("manuallyMarked", false)
+            .field("overPaid", false)
+            .metadata(json!({"orderId": "23", "physical": false}))
+    }
+
+    pub fn invoice_expired() -> Self {
+        BTCPayEvent::new("InvoiceExpired").field("partiallyPaid", false)
+    }
+
+    pub fn invoice_invalid() -> Self {
+        BTCPayEvent::new("InvoiceInvalid").field("manuallyMarked", false)
+    }
+
+    /// Also the original delivery id, unless this is a redelivery
+    pub fn delivery_id(mut self, delivery_id: &str) -> Self {
+        if self.json.get("isRedelivery") != Some(&Value::Bool(true)) {
+            self = self.field("originalDeliveryId", delivery_id);
+        }
+        self.field("deliveryId", delivery_id)
+    }
+
+    pub fn redelivery_of(self, original_delivery_id: &str) -> Self {
+        self.field("isRedelivery", true).field("originalDeliveryId", original_delivery_id)
+    }
+
+    pub fn store_id(self, store_id: &str) -> Self {
+        self.field("storeId", store_id)
+    }
+
+    pub fn invoice_id(self, invoice_id: &str) -> Self {
+        self.field("invoiceId", invoice_id)
+    }
+
+    pub fn timestamp(self, timestamp: i64) -> Self {
+        self.field("timestamp", timestamp)
+    }
+
+    pub fn metadata(self, metadata: Value) -> Self {
+        self.field("metadata", metadata)
+    }
+
+    /// Set `metadata.posData`, keeping the rest of the metadata
+    pub fn pos_data(mut self, pos_data: Value) -> Self {
+        let metadata = self.json.entry("metadata").or_insert_with(|| json!({}));
+        if let Value::Object(metadata) = metadata {
+            metadata.insert("posData".to_string(), pos_data);
+        }
+        self
+    }
+
+    /// Set any top level field, eg. one added in a newer BTCPay release
+    pub fn field(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.json.insert(name.to_string(), value.into());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.json.get(name).and_then(Value::as_str)
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(self.json.clone())
+    }
+
+    /// The body exactly as it will be signed and sent
+    pub fn body(&self) -> String {
+        self.to_json().to_string()
+    }
+
+    pub fn payload(&self) -> btcpay::WebhookPayload {
+        serde_json::from_value(self.to_json()).expect("BTCPay test event does not deserialize")
+    }
+
+    /// A signed POST of this event, see `signed_request`
+    pub fn request(&self, secret: &str) -> TestRequest {
+        signed_request(&self.body(), secret)
+    }
+
+    /// A signed `http::Request` of this event, see `signed_http_request`
+    pub fn http_request(&self, uri: &str, secret: &str) -> http::Request<Bytes> {
+        signed_http_request(uri, &self.body(), secret)
+    }
+}
+
+/// An LNbits payment webhook body
+#[derive(Debug)]
+pub struct LNbitsPayment {
+    event: PaymentEvent,
+}
+
+impl Default for LNbitsPayment {
+    fn default() -> Self {
+        LNbitsPayment::new()
+    }
+}
+
+impl LNbitsPayment {
+    pub fn new() -> Self {
+        LNbitsPayment {
+            event: PaymentEvent {
+                checking_id: PAYMENT_HASH.to_string(),
+                pending: false,
+                amount: 10000,
+                fee: 0,
+                memo: "test webhook".to_string(),
+                time: 1682947081,
+                bolt11: "lnbc100n1pjyl0qfsp533xd0y5zfakhm0ytyauhlgd0jvh0c03ucw7c7eeynaljgzh6yz9spp5t4lw8tmkuft80gpwesfjurjj064cllyjkzhj4ew0z64wwurwew3qdq5w3jhxapqwajky6r0da4sxqzjccqpjrzjqwz34f2ec60uwx0cfhmvfq9lw4j52ct98jr4p5nqwqluynewq7qkszl3wgqq9jqqqqqqqqqqqqqqqqcqjq9qyysgq70qtyljrcp64m7q8lfzezxp2zfasun9flx7mg6aej262gqxsrw94uj0w2y5664ymkapuwrv0gmdzctrjfx0j3xu9qjyeeze5yw0jkhcpqwasks".to_string(),
+                preimage: "0".repeat(64),
+                payment_hash: PAYMENT_HASH.to_string(),
+                expiry: 1682947681,
+                extra: json!({}),
+                wallet_id: WALLET_ID.to_string(),
+                webhook: "https://webhook.site/d7f622fa-616f-4af7-a7ed-0a52aa504ef4".to_string(),
+                webhook_status: None,
+            },
+        }
+    }
+
+    /// Also the checking id, as LNbits uses the payment hash for both
+    pub fn payment_hash(mut self, payment_hash: &str) -> Self {
+        self.event.payment_hash = payment_hash.to_string();
+        self.event.checking_id = payment_hash.to_string();
+        self
+    }
+
+    pub fn amount_msat(mut self, amount: u64) -> Self {
+        self.event.amount = amount;
+        self
+    }
+
+    /// Settled unless set
+    pub fn pending(mut self, pending: bool) -> Self {
+        self.event.pending = pending;
+        self
+    }
+
+    pub fn wallet_id(mut self, wallet_id: &str) -> Self {
+        self.event.wallet_id = wallet_id.to_string();
+        self
+    }
+
+    pub fn memo(mut self, memo: &str) -> Self {
+        self.event.memo = memo.to_string();
+        self
+    }
+
+    pub fn extra(mut self, extra: Value) -> Self {
+        self.event.extra = extra;
+        self
+    }
+
+    pub fn time(mut self, time: u64) -> Self {
+        self.event.time = time;
+        self
+    }
+
+    pub fn body(&self) -> String {
+        serde_json::to_string(&self.event).expect("LNbits test payment does not serialize")
+    }
+
+    pub fn event(self) -> PaymentEvent {
+        self.event
+    }
+
+    pub fn payload(self) -> lnbits_models::WebhookPayload {
+        lnbits_models::WebhookPayload::Payment(self.event)
+    }
+
+    /// An unsigned JSON POST, as LNbits sends them
+    pub fn request(&self) -> TestRequest {
+        TestRequest::post().insert_header(ContentType::json()).set_payload(self.body())
+    }
+}
+
+/// A JSON POST of `body` with its `BTCPay-Sig` header. Set the path with `.uri(..)`.
+/// The body is sent byte for byte, as re-serializing it (eg. `set_json`) would break the signature.
+pub fn signed_request(body: &str, secret: &str) -> TestRequest {
+    TestRequest::post()
+        .insert_header(ContentType::json())
+        .insert_header((BTCPAY_SIG_HEADER, sign_payload(body, secret)))
+        .set_payload(body.to_string())
+}
+
+/// `signed_request` as an `http::Request`, for the tower and axum adapters
+pub fn signed_http_request(uri: &str, body: &str, secret: &str) -> http::Request<Bytes> {
+    http::Request::post(uri)
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .header(BTCPAY_SIG_HEADER, sign_payload(body, secret))
+        .body(Bytes::from(body.to_string()))
+        .expect("signed request does not build")
+}
+
+/// An invoice served by `MockGreenfield`
+#[derive(Debug, Clone)]
+pub struct TestInvoice {
+    json: Map<String, Value>,
+}
+
+impl TestInvoice {
+    pub fn new(store_id: &str, invoice_id: &str) -> Self {
+        let json = json!({
+            "id": invoice_id,
+            "storeId": store_id,
+            "amount": "1000",
+            "currency": "SATS",
+            "type": "Standard",
+            "checkoutLink": format!("https://btcpay.example.com/i/{invoice_id}"),
+            "createdTime": 1683049700,
+            "expirationTime": 1683050600,
+            "monitoringExpiration": 1683136100,
+            "status": "Settled",
+            "additionalStatus": "None",
+            "metadata": {"orderId": "23"},
+        });
+
+        match json {
+            Value::Object(json) => TestInvoice { json },
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn amount(self, amount: &str) -> Self {
+        self.field("amount", amount)
+    }
+
+    pub fn currency(self, currency: &str) -> Self {
+        self.field("currency", currency)
+    }
+
+    /// eg. `New`, `Processing`, `Expired`, `Invalid` or `Settled`
+    pub fn status(self, status: &str) -> Self {
+        self.field("status", status)
+    }
+
+    /// Set `metadata.posData`. Stored as a JSON string, the way `InvoiceData` reads it.
+    pub fn pos_data(mut self, pos_data: Value) -> Self {
+        let pos_data = match pos_data {
+            Value::String(pos_data) => pos_data,
+            pos_data => pos_data.to_string(),
+        };
+        let metadata = self.json.entry("metadata").or_insert_with(|| json!({}));
+        if let Value::Object(metadata) = metadata {
+            metadata.insert("posData".to_string(), Value::String(pos_data));
+        }
+        self
+    }
+
+    pub fn field(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.json.insert(name.to_string(), value.into());
+        self
+    }
+
+    fn key(&self) -> (String, String) {
+        let get = |name| self.json.get(name).and_then(Value::as_str).unwrap_or_default().to_string();
+        (get("storeId"), get("id"))
+    }
+}
+
+/// A webhook sent with `MockGreenfield::deliver`
+#[derive(Debug, Clone)]
+pub struct MockDelivery {
+    pub id: String,
+    pub store_id: String,
+    pub webhook_id: String,
+    pub url: String,
+    pub body: String,
+    /// Response status, or None when the request failed
+    pub status: Option<u16>,
+}
+
+struct MockState {
+    api_key: String,
+    webhook_secret: String,
+    synchronized: bool,
+    invoices: Vec<TestInvoice>,
+    deliveries: Vec<MockDelivery>,
+}
+
+/// An in-process BTCPay Greenfield API for `BTCPayClient`, serving the health, API key, invoice and
+/// webhook delivery endpoints. It runs until the test's runtime stops.
+///
+/// ```ignore
+/// let greenfield = MockGreenfield::start();
+/// greenfield.add_invoice(TestInvoice::new(STORE_ID, INVOICE_ID).pos_data(json!({"pubkey": "..."})));
+/// let invoice = greenfield.client().get_invoice_data(STORE_ID, INVOICE_ID).await?;
+/// ```
+///
+/// `btcpay::get_invoice_data` reads `BTCPAY_HOST` and `BTCPAY_API_KEY` once, so to use it set them
+/// to `url()` and `api_key()` before its first call.
+pub struct MockGreenfield {
+    url: String,
+    state: web::Data<Mutex<MockState>>,
+    client: reqwest::Client,
+}
+
+impl MockGreenfield {
+    /// Bind to a free local port. Must be called from within an actix runtime, eg. `#[actix_web::test]`.
+    pub fn start() -> Self {
+        let state = web::Data::new(Mutex::new(MockState {
+            api_key: "test-api-key".to_string(),
+            webhook_secret: "test-webhook-secret".to_string(),
+            synchronized: true,
+            invoices: Vec::new(),
+            deliveries: Vec::new(),
+        }));
+
+        let app_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .route("/api/v1/health", web::get().to(health))
+                .route("/api/v1/api-keys/current", web::get().to(current_api_key))
+                .route("/api/v1/stores/{store_id}/invoices/{invoice_id}", web::get().to(invoice))
+                .route("/api/v1/stores/{store_id}/webhooks/{webhook_id}/deliveries", web::get().to(deliveries))
+                .route("/api/v1/stores/{store_id}/webhooks/{webhook_id}/deliveries/{delivery_id}/request", web::get().to(delivery_request))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("failed to bind mock Greenfield server");
+
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        MockGreenfield { url, state, client: reqwest::Client::new() }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn api_key(&self) -> String {
+        self.state.lock().unwrap().api_key.clone()
+    }
+
+    /// Secret used to sign deliveries
+    pub fn webhook_secret(&self) -> String {
+        self.state.lock().unwrap().webhook_secret.clone()
+    }
+
+    /// A client for this server, with its API key
+    pub fn client(&self) -> BTCPayClient {
+        BTCPayClient::new(&self.url, &self.api_key())
+    }
+
+    pub fn set_api_key(&self, api_key: &str) {
+        self.state.lock().unwrap().api_key = api_key.to_string();
+    }
+
+    pub fn set_webhook_secret(&self, webhook_secret: &str) {
+        self.state.lock().unwrap().webhook_secret = webhook_secret.to_string();
+    }
+
+    /// What `/api/v1/health` reports
+    pub fn set_synchronized(&self, synchronized: bool) {
+        self.state.lock().unwrap().synchronized = synchronized;
+    }
+
+    /// Serve an invoice, replacing any with the same store and invoice id
+    pub fn add_invoice(&self, invoice: TestInvoice) {
+        let mut state = self.state.lock().unwrap();
+        state.invoices.retain(|existing| existing.key() != invoice.key());
+        state.invoices.push(invoice);
+    }
+
+    /// POST a signed event to a webhook URL, as BTCPay does, and record the delivery
+    pub async fn deliver(&self, url: &str, event: &BTCPayEvent) -> Result<StatusCode> {
+        let body = event.body();
+        let response = self.client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(BTCPAY_SIG_HEADER, sign_payload(&body, &self.webhook_secret()))
+            .body(body.clone())
+            .send()
+            .await;
+
+        self.state.lock().unwrap().deliveries.push(MockDelivery {
+            id: event.get("deliveryId").unwrap_or_default().to_string(),
+            store_id: event.get("storeId").unwrap_or_default().to_string(),
+            webhook_id: event.get("webhookId").unwrap_or_default().to_string(),
+            url: url.to_string(),
+            body,
+            status: response.as_ref().ok().map(|response| response.status().as_u16()),
+        });
+
+        Ok(response?.status())
+    }
+
+    pub fn deliveries(&self) -> Vec<MockDelivery> {
+        self.state.lock().unwrap().deliveries.clone()
+    }
+}
+
+fn authorized(req: &HttpRequest, state: &MockState) -> bool {
+    req.headers().get(AUTHORIZATION).and_then(|value| value.to_str().ok()) == Some(&format!("token {}", state.api_key))
+}
+
+// Greenfield's error body
+fn greenfield_error(status: StatusCode, code: &str, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({ "code": code, "message": message }))
+}
+
+async fn health(state: web::Data<Mutex<MockState>>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "synchronized": state.lock().unwrap().synchronized }))
+}
+
+async fn current_api_key(req: HttpRequest, state: web::Data<Mutex<MockState>>) -> HttpResponse {
+    let state = state.lock().unwrap();
+    if !authorized(&req, &state) {
+        return greenfield_error(StatusCode::UNAUTHORIZED, "unauthenticated", "Authentication is required for accessing this endpoint");
+    }
+    HttpResponse::Ok().json(json!({ "apiKey": state.api_key, "label": "mock", "permissions": ["btcpay.store.canviewinvoices"] }))
+}
+
+async fn invoice(req: HttpRequest, path: web::Path<(String, String)>, state: web::Data<Mutex<MockState>>) -> HttpResponse {
+    let state = state.lock().unwrap();
+    if !authorized(&req, &state) {
+        return greenfield_error(StatusCode::UNAUTHORIZED, "unauthenticated", "Authentication is required for accessing this endpoint");
+    }
+
+    let key = path.into_inner();
+    match state.invoices.iter().find(|invoice| invoice.key() == key) {
+        Some(invoice) => HttpResponse::Ok().json(&invoice.json),
+        None => greenfield_error(StatusCode::NOT_FOUND, "invoice-not-found", "The invoice was not found"),
+    }
+}
+
+async fn deliveries(req: HttpRequest, path: web::Path<(String, String)>, state: web::Data<Mutex<MockState>>) -> HttpResponse {
+    let state = state.lock().unwrap();
+    if !authorized(&req, &state) {
+        return greenfield_error(StatusCode::UNAUTHORIZED, "unauthenticated", "Authentication is required for accessing this endpoint");
+    }
+
+    let (store_id, webhook_id) = path.into_inner();
+    let deliveries: Vec<Value> = state.deliveries.iter()
+        .filter(|delivery| delivery.store_id == store_id && delivery.webhook_id == webhook_id)
+        .map(|delivery| json!({
+            "id": delivery.id,
+            "httpCode": delivery.status,
+            "status": match delivery.status {
+                Some(status) if (200..300).contains(&status) => "HttpSuccess",
+                Some(_) => "HttpError",
+                None => "Failed",
+            },
+        }))
+        .collect();
+
+    HttpResponse::Ok().json(deliveries)
+}
+
+async fn delivery_request(req: HttpRequest, path: web::Path<(String, String, String)>, state: web::Data<Mutex<MockState>>) -> HttpResponse {
+    let state = state.lock().unwrap();
+    if !authorized(&req, &state) {
+        return greenfield_error(StatusCode::UNAUTHORIZED, "unauthenticated", "Authentication is required for accessing this endpoint");
+    }
+
+    let (store_id, webhook_id, delivery_id) = path.into_inner();
+    match state.deliveries.iter().find(|d| d.store_id == store_id && d.webhook_id == webhook_id && d.id == delivery_id) {
+        Some(delivery) => HttpResponse::Ok().content_type(ContentType::json()).body(delivery.body.clone()),
+        None => greenfield_error(StatusCode::NOT_FOUND, "webhookdelivery-not-found", "The webhook delivery was not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btcpay::btcpay_middleware::{BTCPayHeaderVerify, WebhookSecrets};
+    use crate::btcpay::WebhookPayload;
+    use crate::event::EventType;
+    use actix_web::test::{call_and_read_body, call_service, init_service};
+
+    #[test]
+    fn test_event_builders() {
+        for event in [
+            BTCPayEvent::invoice_created(),
+            BTCPayEvent::invoice_received_payment(),
+            BTCPayEvent::invoice_payment_settled(),
+            BTCPayEvent::invoice_processing(),
+            BTCPayEvent::invoice_settled(),
+            BTCPayEvent::invoice_expired(),
+            BTCPayEvent::invoice_invalid(),
+        ] {
+            assert!(!matches!(event.payload(), WebhookPayload::Unsupported), "{}", event.body());
+            assert!(event.payload().normalize().is_some());
+        }
+
+        let event = BTCPayEvent::invoice_settled().delivery_id("second").redelivery_of("first").pos_data(json!({"pubkey": "abc"}));
+        assert_eq!(event.payload().normalize().unwrap().id, "first");
+        assert_eq!(event.to_json()["metadata"], json!({"orderId": "23", "physical": false, "posData": {"pubkey": "abc"}}));
+
+        let payment = LNbitsPayment::new().payment_hash("ab12").amount_msat(21_000).payload();
+        let event = payment.normalize().unwrap();
+        assert_eq!(event.payment_hash.as_deref(), Some("ab12"));
+        assert_eq!(event.amount_msat, Some(21_000));
+        assert_eq!(event.event_type, EventType::InvoiceSettled);
+
+        let event = LNbitsPayment::new().payment_hash("ab12").pending(true).payload().normalize().unwrap();
+        assert_eq!(event.event_type, EventType::InvoiceCreated);
+        assert_eq!(event.id, "ab12:pending");
+    }
+
+    // Signed delivery through the verify middleware, then an invoice lookup, with no network
+    #[actix_web::test]
+    async fn test_full_flow() {
+        let greenfield = MockGreenfield::start();
+        greenfield.add_invoice(TestInvoice::new(STORE_ID, INVOICE_ID).pos_data(json!({"content_id": "a1"})));
+
+        let client = web::Data::new(greenfield.client());
+        let app = init_service(App::new()
+            .app_data(web::Data::new(WebhookSecrets::new(Some("secret".to_string()))))
+            .app_data(client)
+            .service(web::scope("/btcpay").wrap(BTCPayHeaderVerify).route("/webhook", web::post().to(
+                |payload: web::Json<WebhookPayload>, client: web::Data<BTCPayClient>| async move {
+                    let event = payload.normalize().unwrap();
+                    let invoice = client.get_invoice_data(&event.store_id.unwrap(), &event.invoice_id.unwrap()).await.unwrap();
+                    HttpResponse::Ok().body(invoice.metadata.unwrap().pos_data.unwrap())
+                },
+            )))).await;
+
+        let req = BTCPayEvent::invoice_settled().request("secret").uri("/btcpay/webhook").to_request();
+        let body = call_and_read_body(&app, req).await;
+        assert_eq!(body, r#"{"content_id":"a1"}"#);
+
+        let req = BTCPayEvent::invoice_settled().request("wrong").uri("/btcpay/webhook").to_request();
+        assert_eq!(call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    // `test_full_flow` through `BTCPayVerifyLayer` and the `BTCPayWebhook` extractor
+    #[cfg(feature = "axum")]
+    #[actix_web::test]
+    async fn test_full_flow_axum() {
+        use crate::btcpay::{BTCPayVerifyLayer, BTCPayWebhook, WebhookPayload};
+        use axum::{body::{to_bytes, Body}, extract::State, routing::post, Router};
+        use tower::ServiceExt;
+
+        let greenfield = MockGreenfield::start();
+        greenfield.add_invoice(TestInvoice::new(STORE_ID, INVOICE_ID).pos_data(json!({"content_id": "a1"})));
+
+        let app = Router::new()
+            .route("/btcpay/webhook", post(
+                |State(client): State<BTCPayClient>, BTCPayWebhook(payload): BTCPayWebhook<WebhookPayload>| async move {
+                    let event = payload.normalize().unwrap();
+                    let invoice = client.get_invoice_data(&event.store_id.unwrap(), &event.invoice_id.unwrap()).await.unwrap();
+                    invoice.metadata.unwrap().pos_data.unwrap()
+                },
+            ))
+            .layer(BTCPayVerifyLayer::new(WebhookSecrets::new(Some("secret".to_string()))))
+            .with_state(greenfield.client());
+
+        let req = BTCPayEvent::invoice_settled().http_request("/btcpay/webhook", "secret").map(Body::from);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(to_bytes(res.into_body(), usize::MAX).await.unwrap(), r#"{"content_id":"a1"}"#);
+
+        let req = BTCPayEvent::invoice_settled().http_request("/btcpay/webhook", "wrong").map(Body::from);
+        assert_eq!(app.oneshot(req).await.unwrap().status(), http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_mock_greenfield() {
+        let greenfield = MockGreenfield::start();
+        let client = greenfield.client();
+
+        assert!(client.health().await.unwrap());
+        greenfield.set_synchronized(false);
+        assert!(!client.health().await.unwrap());
+
+        client.validate_api_key().await.unwrap();
+        assert!(BTCPayClient::new(greenfield.url(), "revoked").validate_api_key().await.is_err());
+        assert!(client.get_invoice_data(STORE_ID, "missing").await.is_err());
+
+        // Deliveries are signed with the webhook secret and listed like BTCPay's
+        let secrets = web::Data::new(WebhookSecrets::new(Some(greenfield.webhook_secret())));
+        let server = HttpServer::new(move || App::new()
+            .app_data(secrets.clone())
+            .service(web::scope("/btcpay").wrap(BTCPayHeaderVerify).route("/webhook", web::post().to(HttpResponse::Ok))))
+            .workers(1)
+            .bind(("127.0.0.1", 0))
+            .unwrap();
+        let url = format!("http://{}/btcpay/webhook", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        let event = BTCPayEvent::invoice_expired().delivery_id("d1");
+        assert_eq!(greenfield.deliver(&url, &event).await.unwrap(), StatusCode::OK);
+
+        let delivery = &greenfield.deliveries()[0];
+        assert_eq!(delivery.id, "d1");
+        assert_eq!(delivery.body, event.body());
+
+        let request = reqwest::Client::new()
+            .get(format!("{}/api/v1/stores/{STORE_ID}/webhooks/{WEBHOOK_ID}/deliveries/d1/request", greenfield.url()))
+            .header(reqwest::header::AUTHORIZATION, format!("token {}", greenfield.api_key()))
+            .send().await.unwrap()
+            .text().await.unwrap();
+        assert_eq!(request, event.body());
+
+        // Signed with a secret the receiver does not know
+        greenfield.set_webhook_secret("rotated");
+        assert_eq!(greenfield.deliver(&url, &event.delivery_id("d2")).await.unwrap(), StatusCode::UNAUTHORIZED);
+    }
+}
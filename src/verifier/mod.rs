@@ -0,0 +1,238 @@
+// Verification for providers with a single secret (Strike, Alby, phoenixd, ...), independent of the web
+// framework. `VerifyWebhook` runs a `WebhookVerifier` as actix middleware, and `Verified` as an axum
+// extractor. BTCPay, with its per-store secrets, has its own in `webhook`.
+
+use crate::metrics::{self, VerifyFailure};
+
+#[cfg(feature = "svix")]
+pub mod svix;
+#[cfg(feature = "axum")]
+pub mod verifier_extract;
+#[cfg(feature = "actix")]
+pub mod verifier_middleware;
+
+#[cfg(feature = "axum")]
+pub use verifier_extract::Verified;
+#[cfg(feature = "actix")]
+pub use verifier_middleware::VerifyWebhook;
+
+/// Request headers, from whichever framework received the request
+pub trait Headers {
+    /// The first value of a header, by case insensitive name
+    fn header(&self, name: &str) -> Option<&[u8]>;
+}
+
+#[cfg(feature = "actix")]
+impl Headers for actix_web::http::header::HeaderMap {
+    fn header(&self, name: &str) -> Option<&[u8]> {
+        self.get(name).map(|value| value.as_bytes())
+    }
+}
+
+#[cfg(feature = "http")]
+impl Headers for http::HeaderMap {
+    fn header(&self, name: &str) -> Option<&[u8]> {
+        self.get(name).map(|value| value.as_bytes())
+    }
+}
+
+/// Headers as name and value pairs, eg. from a `recorder::RecordedRequest`
+impl Headers for Vec<(String, String)> {
+    fn header(&self, name: &str) -> Option<&[u8]> {
+        self.iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_bytes())
+    }
+}
+
+/// Checks a provider's webhook requests, returning their parsed payload. Run one with `VerifyWebhook`
+/// in actix, or `Verified` in axum. Provider verifiers are built from their secret with `new`, or with
+/// `from_env` from the environment variable named in the README (see `secret_from_env`).
+pub trait WebhookVerifier: Send + Sync + 'static {
+    type Payload: Clone + Send + Sync + 'static;
+
+    /// Used in metrics, spans and the inbox, eg. `strike`
+    fn provider(&self) -> &'static str;
+
+    fn verify(&self, headers: &dyn Headers, body: &[u8]) -> Result<Self::Payload, VerifyFailure>;
+
+    /// Label for the received webhooks metric
+    fn event_type(&self, payload: &Self::Payload) -> String;
+}
+
+/// A provider secret from the environment, or an error naming the missing variable
+pub fn secret_from_env(name: &str) -> anyhow::Result<String> {
+    std::env::var(name).map_err(|_| anyhow::anyhow!("{name} must be set"))
+}
+
+/// A check after `WebhookVerifier::verify` that needs a round trip, eg. fetching what an unsigned
+/// webhook names from the provider's API. `VerifyWebhook::confirm_with` runs one.
+pub trait ConfirmWebhook<P>: 'static {
+    /// The payload to hand on, which may be a fresher copy, or `None` if the provider does not know it.
+    /// Errors mean the provider could not be asked.
+    fn confirm(&self, payload: P) -> impl std::future::Future<Output = anyhow::Result<Option<P>>>;
+}
+
+/// Hands on every verified payload as is, for providers that sign their webhooks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoConfirm;
+
+impl<P> ConfirmWebhook<P> for NoConfirm {
+    async fn confirm(&self, payload: P) -> anyhow::Result<Option<P>> {
+        Ok(Some(payload))
+    }
+}
+
+/// `verifier.verify`, counting the outcome in the metrics
+pub fn verify<V: WebhookVerifier>(verifier: &V, headers: &dyn Headers, body: &[u8]) -> Result<V::Payload, VerifyFailure> {
+    record(verifier, verifier.verify(headers, body))
+}
+
+/// Count a verification outcome in the metrics
+pub(crate) fn record<V: WebhookVerifier>(verifier: &V, result: Result<V::Payload, VerifyFailure>) -> Result<V::Payload, VerifyFailure> {
+    match &result {
+        Ok(payload) => metrics::webhook_received(verifier.provider(), &verifier.event_type(payload)),
+        Err(reason) => {
+            error!("{} webhook failed verification: {}", verifier.provider(), reason.as_str());
+            metrics::verification_failed(verifier.provider(), *reason);
+        },
+    }
+    result
+}
+
+/// Parse a hex signature header value, ignoring case and an optional `sha256=` prefix. Values that are
+/// not hex are a `BadHeader`.
+pub fn hex_signature(value: &[u8]) -> Result<Vec<u8>, VerifyFailure> {
+    let value = std::str::from_utf8(value).map_err(|_| VerifyFailure::BadHeader)?.trim();
+    let value = value.strip_prefix("sha256=").unwrap_or(value);
+    hex::decode(value).map_err(|_| VerifyFailure::BadHeader)
+}
+
+/// Check `signature` is the HMAC-SHA256 of `message` with `secret`, in constant time
+pub fn verify_hmac_sha256(secret: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret);
+    ring::hmac::verify(&key, message, signature).is_ok()
+}
+
+/// The hex HMAC-SHA256 of `message` with `secret`, as providers send in their signature headers
+pub fn hmac_sha256_hex(secret: &[u8], message: &[u8]) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret);
+    hex::encode(ring::hmac::sign(&key, message).as_ref())
+}
+
+/// Check a hex HMAC-SHA256 signature header over `message`, see `hex_signature`
+pub fn check_hmac_sha256_hex(secret: &[u8], message: &[u8], signature_header: &[u8]) -> Result<(), VerifyFailure> {
+    let signature = hex_signature(signature_header)?;
+    if verify_hmac_sha256(secret, message, &signature) {
+        Ok(())
+    } else {
+        Err(VerifyFailure::BadSignature)
+    }
+}
+
+/// Headers from name and value pairs, for calling `WebhookVerifier::verify` in tests
+#[cfg(test)]
+pub(crate) fn test_headers(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+    headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+}
+
+/// Serve `config` on a local port for the length of the test, returning its base URL. For mocks of
+/// provider APIs.
+#[cfg(all(test, feature = "actix", any(feature = "strike-api", feature = "phoenixd-api", feature = "zbd-api", feature = "opennode-api", feature = "lnbits-api")))]
+pub(crate) fn mock_server(config: impl Fn(&mut actix_web::web::ServiceConfig) + Clone + Send + 'static) -> String {
+    let server = actix_web::HttpServer::new(move || actix_web::App::new().configure(config.clone()))
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+    let url = format!("http://{}", server.addrs()[0]);
+    actix_web::rt::spawn(server.run());
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Signs the body in an `X-Test-Signature` header
+    #[derive(Clone)]
+    struct TestVerifier;
+
+    impl WebhookVerifier for TestVerifier {
+        type Payload = String;
+
+        fn provider(&self) -> &'static str {
+            "test"
+        }
+
+        fn verify(&self, headers: &dyn Headers, body: &[u8]) -> Result<String, VerifyFailure> {
+            check_hmac_sha256_hex(b"secret", body, headers.header("X-Test-Signature").ok_or(VerifyFailure::MissingHeader)?)?;
+            String::from_utf8(body.to_vec()).map_err(|_| VerifyFailure::BadBody)
+        }
+
+        fn event_type(&self, _payload: &String) -> String {
+            "test".to_string()
+        }
+    }
+
+    fn sign(body: &[u8]) -> String {
+        format!("sha256={}", hmac_sha256_hex(b"secret", body).to_uppercase())
+    }
+
+    fn headers(signature: Option<&str>) -> Vec<(String, String)> {
+        test_headers(&signature.map(|signature| ("x-test-signature", signature)).into_iter().collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_verify() {
+        let signature = sign(b"hello");
+        assert_eq!(verify(&TestVerifier, &headers(Some(&signature)), b"hello"), Ok("hello".to_string()));
+        assert_eq!(verify(&TestVerifier, &headers(Some(&signature)), b"hullo"), Err(VerifyFailure::BadSignature));
+        assert_eq!(verify(&TestVerifier, &headers(Some("sha256=zz")), b"hello"), Err(VerifyFailure::BadHeader));
+        assert_eq!(verify(&TestVerifier, &headers(None), b"hello"), Err(VerifyFailure::MissingHeader));
+    }
+
+    // `VerifyWebhook` and `Verified` answer the same requests the same way
+    #[cfg(all(feature = "actix", feature = "axum"))]
+    #[actix_web::test]
+    async fn test_adapters_agree() {
+        use actix_web::{test, web, App, HttpResponse};
+        use axum::{routing::post, Extension, Router};
+        use tower::ServiceExt;
+
+        let actix = test::init_service(App::new()
+            .service(web::scope("/test").wrap(VerifyWebhook::new(TestVerifier)).route("/webhook", web::post().to(
+                |payload: web::ReqData<String>| async move { HttpResponse::Ok().body(payload.into_inner()) },
+            )))).await;
+
+        let axum = Router::new()
+            .route("/test/webhook", post(|Verified(payload): Verified<TestVerifier>| async move { payload }))
+            .layer(Extension(TestVerifier));
+
+        let hello = sign(b"hello");
+        let cases: [(Option<&str>, &[u8], u16); 4] = [
+            (Some(&hello), b"hello", 200),
+            (Some(&hello), b"hullo", 401),
+            (None, b"hello", 401),
+            (Some("sha256=zz"), b"hello", 401),
+        ];
+
+        for (signature, body, status) in cases {
+            let mut request = test::TestRequest::post().uri("/test/webhook").set_payload(body.to_vec());
+            if let Some(signature) = signature {
+                request = request.insert_header(("X-Test-Signature", signature));
+            }
+            let response = test::call_service(&actix, request.to_request()).await;
+            let actix_response = (response.status().as_u16(), test::read_body(response).await);
+            assert_eq!(actix_response.0, status, "{signature:?} {body:?}");
+
+            let mut request = http::Request::post("/test/webhook");
+            if let Some(signature) = signature {
+                request = request.header("X-Test-Signature", signature);
+            }
+            let response = axum.clone().oneshot(request.body(axum::body::Body::from(body.to_vec())).unwrap()).await.unwrap();
+            let status = response.status().as_u16();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert_eq!((status, body), actix_response);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::metrics::VerifyFailure;
+use crate::verifier::{verify_hmac_sha256, Headers};
+
+// REF: https://docs.svix.com/receiving/verifying-payloads/how-manual
+
+pub const SVIX_ID_HEADER: &str = "svix-id";
+pub const SVIX_TIMESTAMP_HEADER: &str = "svix-timestamp";
+pub const SVIX_SIGNATURE_HEADER: &str = "svix-signature";
+
+/// How far a message's timestamp may be from now, either way
+pub const DEFAULT_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// A Svix endpoint secret, checking the `svix-id`, `svix-timestamp` and `svix-signature` headers.
+/// Providers that deliver through Svix (eg. Alby) wrap one in their `WebhookVerifier`.
+#[derive(Clone)]
+pub struct SvixSecret {
+    key: Vec<u8>,
+    tolerance: Duration,
+}
+
+impl SvixSecret {
+    /// From the endpoint secret, eg. `whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw`
+    pub fn new(secret: &str) -> Result<Self> {
+        let encoded = secret.strip_prefix("whsec_").unwrap_or(secret);
+        let key = STANDARD.decode(encoded).map_err(|err| anyhow!("svix secret is not base64: {err}"))?;
+        Ok(SvixSecret { key, tolerance: DEFAULT_TOLERANCE })
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Duration) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// The `svix-signature` value for a message, eg. `v1,g0hM9SsE+OTPJTGt/tmIKtSyZlE3uFJELVlNIOLJ1OE=`
+    pub fn sign(&self, id: &str, timestamp: i64, body: &[u8]) -> String {
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &self.key);
+        let signature = ring::hmac::sign(&key, &signed_content(id, timestamp, body));
+        format!("v1,{}", STANDARD.encode(signature.as_ref()))
+    }
+
+    pub fn verify(&self, headers: &dyn Headers, body: &[u8]) -> Result<(), VerifyFailure> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+        self.verify_at(headers, body, now)
+    }
+
+    fn verify_at(&self, headers: &dyn Headers, body: &[u8], now: i64) -> Result<(), VerifyFailure> {
+        let header = |name| headers.header(name).and_then(|value| std::str::from_utf8(value).ok());
+        let (Some(id), Some(timestamp), Some(signatures)) =
+            (header(SVIX_ID_HEADER), header(SVIX_TIMESTAMP_HEADER), header(SVIX_SIGNATURE_HEADER))
+        else {
+            return Err(VerifyFailure::MissingHeader);
+        };
+
+        let timestamp: i64 = timestamp.trim().parse().map_err(|_| VerifyFailure::BadHeader)?;
+        if now.abs_diff(timestamp) > self.tolerance.as_secs() {
+            return Err(VerifyFailure::StaleTimestamp);
+        }
+
+        // Space separated `v1,<base64>` entries, several while a secret is being rotated
+        let content = signed_content(id, timestamp, body);
+        let valid = signatures.split_whitespace()
+            .filter_map(|entry| entry.strip_prefix("v1,"))
+            .filter_map(|signature| STANDARD.decode(signature).ok())
+            .any(|signature| verify_hmac_sha256(&self.key, &content, &signature));
+
+        if valid { Ok(()) } else { Err(VerifyFailure::BadSignature) }
+    }
+}
+
+fn signed_content(id: &str, timestamp: i64, body: &[u8]) -> Vec<u8> {
+    let mut content = format!("{id}.{timestamp}.").into_bytes();
+    content.extend_from_slice(body);
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifier::test_headers;
+
+    const SECRET: &str = "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw";
+    const BODY: &[u8] = br#"{"test": 2432232314}"#;
+
+    fn headers(id: &str, timestamp: i64, signature: &str) -> Vec<(String, String)> {
+        test_headers(&[(SVIX_ID_HEADER, id), (SVIX_TIMESTAMP_HEADER, &timestamp.to_string()), (SVIX_SIGNATURE_HEADER, signature)])
+    }
+
+    #[test]
+    fn test_svix_secret() {
+        let secret = SvixSecret::new(SECRET).unwrap();
+
+        // The example from the Svix docs
+        let signature = secret.sign("msg_p5jXN8AQM9LWM0D4loKWxJek", 1614265330, BODY);
+        assert_eq!(signature, "v1,g0hM9SsE+OTPJTGt/tmIKtSyZlE3uFJELVlNIOLJ1OE=");
+        let signed = headers("msg_p5jXN8AQM9LWM0D4loKWxJek", 1614265330, &signature);
+        assert_eq!(secret.verify_at(&signed, BODY, 1614265330 + 60), Ok(()));
+
+        // Any one of several signatures will do
+        let rotated = headers("msg_p5jXN8AQM9LWM0D4loKWxJek", 1614265330, &format!("v1,Zm9v v2,bar {signature}"));
+        assert_eq!(secret.verify_at(&rotated, BODY, 1614265330), Ok(()));
+
+        assert_eq!(secret.verify_at(&signed, b"{}", 1614265330), Err(VerifyFailure::BadSignature));
+        let other_id = headers("msg_other", 1614265330, &signature);
+        assert_eq!(secret.verify_at(&other_id, BODY, 1614265330), Err(VerifyFailure::BadSignature));
+        assert_eq!(secret.verify_at(&signed, BODY, 1614265330 + 301), Err(VerifyFailure::StaleTimestamp));
+        assert_eq!(secret.verify_at(&signed, BODY, 1614265330 - 301), Err(VerifyFailure::StaleTimestamp));
+        assert_eq!(secret.verify_at(&signed[..2].to_vec(), BODY, 1614265330), Err(VerifyFailure::MissingHeader));
+        let bad_timestamp = test_headers(&[(SVIX_ID_HEADER, "msg_p5jXN8AQM9LWM0D4loKWxJek"), (SVIX_TIMESTAMP_HEADER, "soon"), (SVIX_SIGNATURE_HEADER, &signature)]);
+        assert_eq!(secret.verify_at(&bad_timestamp, BODY, 1614265330), Err(VerifyFailure::BadHeader));
+
+        let lenient = SvixSecret::new(SECRET).unwrap().with_tolerance(Duration::from_secs(3600));
+        assert_eq!(lenient.verify_at(&signed, BODY, 1614265330 + 600), Ok(()));
+
+        assert!(SvixSecret::new("whsec_not base64!").is_err());
+    }
+}
@@ -0,0 +1,37 @@
+use axum::{
+    body::to_bytes,
+    extract::{FromRequest, Request},
+};
+
+use crate::metrics::{self, VerifyFailure};
+use crate::verifier::{self, WebhookVerifier};
+use crate::webhook::{Rejection, MAX_BODY_BYTES};
+
+/// A payload checked by the `V` in the request extensions, eg. from `.layer(Extension(StrikeVerifier::new(&secret)))`.
+/// Rejects like `VerifyWebhook`.
+#[derive(Debug, Clone)]
+pub struct Verified<V: WebhookVerifier>(pub V::Payload);
+
+impl<S: Send + Sync, V: WebhookVerifier + Clone> FromRequest<S> for Verified<V> {
+    type Rejection = Rejection;
+
+    async fn from_request(request: Request, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(verifier) = request.extensions().get::<V>().cloned() else {
+            error!("No {} verifier in the request extensions", std::any::type_name::<V>());
+            return Err(Rejection::unauthorized(VerifyFailure::BadSignature));
+        };
+
+        let (parts, body) = request.into_parts();
+        let body = match to_bytes(body, MAX_BODY_BYTES).await {
+            Ok(body) => body,
+            Err(_) => {
+                metrics::verification_failed(verifier.provider(), VerifyFailure::BadBody);
+                return Err(Rejection::unauthorized(VerifyFailure::BadBody));
+            },
+        };
+
+        verifier::verify(&verifier, &parts.headers, &body)
+            .map(Verified)
+            .map_err(Rejection::unauthorized)
+    }
+}
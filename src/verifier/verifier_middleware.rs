@@ -0,0 +1,142 @@
+use anyhow::Result;
+use actix_web::{
+    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    http::StatusCode, web, Error, HttpMessage, body::EitherBody,
+};
+use crate::error::problem_response;
+use crate::inbox;
+use crate::metrics::{self, VerifyFailure};
+use crate::recorder::bytes_to_payload;
+use crate::verifier::{self, ConfirmWebhook, NoConfirm, WebhookVerifier};
+use futures_util::future::LocalBoxFuture;
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+    sync::Arc,
+};
+use tracing::{field, Instrument};
+
+/// Runs a `WebhookVerifier` before the handler. Failures are answered with a 401 problem response, and
+/// verified requests get the verifier's payload in their extensions, eg. for `web::ReqData<Payload>`.
+///
+/// ```ignore
+/// web::scope("/strike").wrap(VerifyWebhook::new(StrikeVerifier::new(&secret)))
+/// ```
+pub struct VerifyWebhook<V, C = NoConfirm> {
+    verifier: Arc<V>,
+    confirm: Arc<C>,
+}
+
+impl<V, C> Clone for VerifyWebhook<V, C> {
+    fn clone(&self) -> Self {
+        VerifyWebhook { verifier: self.verifier.clone(), confirm: self.confirm.clone() }
+    }
+}
+
+impl<V> VerifyWebhook<V> {
+    pub fn new(verifier: V) -> Self {
+        VerifyWebhook { verifier: Arc::new(verifier), confirm: Arc::new(NoConfirm) }
+    }
+}
+
+impl<V, C> VerifyWebhook<V, C> {
+    /// Confirm verified payloads with `confirm` before the handler. Payloads it does not know are
+    /// answered with a 401 `unknown_object`, and a 503 if it fails, so the provider delivers again.
+    pub fn confirm_with<D>(self, confirm: D) -> VerifyWebhook<V, D> {
+        VerifyWebhook { verifier: self.verifier, confirm: Arc::new(confirm) }
+    }
+}
+
+impl<S: 'static, B, V: WebhookVerifier, C: ConfirmWebhook<V::Payload>> Transform<S, ServiceRequest> for VerifyWebhook<V, C>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = VerifyWebhookMiddleware<S, V, C>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(VerifyWebhookMiddleware {
+            service: Rc::new(service),
+            verifier: self.verifier.clone(),
+            confirm: self.confirm.clone(),
+        }))
+    }
+}
+
+pub struct VerifyWebhookMiddleware<S, V, C = NoConfirm> {
+    service: Rc<S>,
+    verifier: Arc<V>,
+    confirm: Arc<C>,
+}
+
+impl<S, B, V: WebhookVerifier, C: ConfirmWebhook<V::Payload>> Service<ServiceRequest> for VerifyWebhookMiddleware<S, V, C>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    dev::forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let svc = self.service.clone();
+        let verifier = self.verifier.clone();
+        let confirm = self.confirm.clone();
+
+        // Covers verification and the handler, like `btcpay.webhook` for BTCPay
+        let span = tracing::info_span!("webhook", provider = verifier.provider(), event_type = field::Empty);
+
+        Box::pin(async move {
+
+            let body_bytes = match req.extract::<web::Bytes>().await {
+                Ok(body_bytes) => body_bytes,
+                Err(_) => {
+                    metrics::verification_failed(verifier.provider(), VerifyFailure::BadBody);
+                    return Ok(return_unauthorized(req, verifier.provider(), VerifyFailure::BadBody))
+                },
+            };
+
+            req.set_payload(bytes_to_payload(body_bytes.clone()));
+
+            let result = match verifier.verify(req.headers(), &body_bytes) {
+                Ok(payload) => match confirm.confirm(payload).await {
+                    Ok(confirmed) => confirmed.ok_or(VerifyFailure::UnknownObject),
+                    Err(err) => {
+                        error!("Could not confirm {} webhook: {err:#}", verifier.provider());
+                        let response = problem_response(StatusCode::SERVICE_UNAVAILABLE, None).map_into_right_body();
+                        return Ok(req.into_response(response));
+                    },
+                },
+                Err(reason) => Err(reason),
+            };
+
+            let payload = match verifier::record(verifier.as_ref(), result) {
+                Ok(payload) => payload,
+                Err(reason) => return Ok(return_unauthorized(req, verifier.provider(), reason)),
+            };
+
+            tracing::Span::current().record("event_type", verifier.event_type(&payload).as_str());
+            req.extensions_mut().insert(payload);
+            req.extensions_mut().insert(inbox::Verified);
+
+            let res = svc.call(req).await?;
+            Ok(res.map_into_left_body())
+        }.instrument(span))
+    }
+}
+
+fn return_unauthorized<L>(req: ServiceRequest, provider: &'static str, reason: VerifyFailure) -> ServiceResponse<EitherBody<L>> {
+    inbox::count_verification_failure(req.request(), provider, reason);
+
+    let (request, _pl) = req.into_parts();
+    let response = problem_response(StatusCode::UNAUTHORIZED, Some(reason.as_str())).map_into_right_body::<L>();
+    ServiceResponse::new(request, response)
+}
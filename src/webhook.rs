@@ -0,0 +1,387 @@
+// Framework independent webhook handling on `http::Request<Bytes>`: signature verification, payload
+// parsing and handler dispatch. The actix middleware, tower layer and axum extractor are adapters
+// over this, so they accept and reject the same requests with the same responses.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{collections::HashMap, fmt, sync::Arc};
+use tracing::field;
+
+use crate::btcpay::verify_signature;
+use crate::error::{handler_response, problem_http_response, HandlerResult, Problem};
+use crate::event::{self, NormalizedEvent, Provider};
+use crate::metrics::{self, VerifyFailure};
+
+lazy_static! {
+    // Used when no `WebhookSecrets` are given
+    static ref BTCPAY_WEBHOOK_SECRET: Option<String> = std::env::var("BTCPAY_WEBHOOK_SECRET").ok();
+}
+
+pub const BTCPAY_SIG_HEADER: &str = "BTCPay-Sig";
+
+/// Largest body read for verification, the same as actix's default payload limit
+pub const MAX_BODY_BYTES: usize = 256 * 1024;
+
+/// Webhook secrets by store id. When given to a `Verifier` (eg. registered as `web::Data<WebhookSecrets>`
+/// for `BTCPayHeaderVerify`), signatures are checked with these rather than `BTCPAY_WEBHOOK_SECRET`.
+#[derive(Debug, Clone, Default)]
+pub struct WebhookSecrets {
+    default: Option<String>,
+    stores: HashMap<String, String>,
+}
+
+impl WebhookSecrets {
+    /// `default` is used for stores without their own secret
+    pub fn new(default: Option<String>) -> Self {
+        WebhookSecrets { default, stores: HashMap::new() }
+    }
+
+    pub fn with_store(mut self, store_id: &str, secret: &str) -> Self {
+        self.stores.insert(store_id.to_string(), secret.to_string());
+        self
+    }
+
+    pub fn secret_for(&self, store_id: Option<&str>) -> Option<&str> {
+        store_id
+            .and_then(|store_id| self.stores.get(store_id))
+            .or(self.default.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// Checks `BTCPay-Sig` signatures, with `WebhookSecrets` or else the `BTCPAY_WEBHOOK_SECRET` environment variable
+#[derive(Debug, Clone, Default)]
+pub struct Verifier {
+    secrets: Option<Arc<WebhookSecrets>>,
+}
+
+impl Verifier {
+    /// Takes `WebhookSecrets`, or an `Arc` of them (eg. from `web::Data::into_inner`)
+    pub fn new(secrets: impl Into<Arc<WebhookSecrets>>) -> Self {
+        Verifier { secrets: Some(secrets.into()) }
+    }
+
+    /// Use `BTCPAY_WEBHOOK_SECRET` for every store
+    pub fn from_env() -> Self {
+        Verifier::default()
+    }
+
+    /// Check a body against the `BTCPay-Sig` header value. `body` is `None` when it could not be read;
+    /// adapters only read it once the header is known to be there.
+    ///
+    /// Failures are counted in the metrics, and fields of the verified body recorded on the current span.
+    pub fn verify(&self, signature: Option<&[u8]>, body: Option<Bytes>) -> Result<Webhook, Rejection> {
+        let result = self.check(signature, body);
+        if let Err(rejection) = &result {
+            if let Some(reason) = rejection.reason {
+                metrics::verification_failed("btcpay", reason);
+            }
+        }
+        result
+    }
+
+    /// `verify` for a buffered request
+    pub fn verify_request(&self, request: &Request<Bytes>) -> Result<Webhook, Rejection> {
+        let signature = request.headers().get(BTCPAY_SIG_HEADER).map(|value| value.as_bytes());
+        self.verify(signature, Some(request.body().clone()))
+    }
+
+    fn check(&self, signature: Option<&[u8]>, body: Option<Bytes>) -> Result<Webhook, Rejection> {
+        let signature = signature.ok_or(Rejection::unauthorized(VerifyFailure::MissingHeader))?;
+        // Header values may hold any visible ASCII, like `HeaderValue::to_str`, and the signature is hex
+        let signature = std::str::from_utf8(signature).ok()
+            .filter(|value| value.bytes().all(|b| b == b'\t' || (b' '..=b'~').contains(&b)))
+            .filter(|value| hex::decode(value.trim_start_matches("sha256=")).is_ok())
+            .ok_or(Rejection::unauthorized(VerifyFailure::BadHeader))?;
+
+        let body = body.ok_or(Rejection::unauthorized(VerifyFailure::BadBody))?;
+        let body_str = std::str::from_utf8(&body).map_err(|_| Rejection::unauthorized(VerifyFailure::BadBody))?;
+
+        debug!("Body: {} bytes", body_str.len());
+
+        let json = serde_json::from_str::<Value>(body_str).unwrap_or_default();
+        let field = |name: &str| json.get(name).and_then(|v| v.as_str()).map(str::to_string);
+        let webhook = Webhook {
+            provider: Provider::BTCPay,
+            event_type: field("type"),
+            delivery_id: field("deliveryId"),
+            invoice_id: field("invoiceId"),
+            store_id: field("storeId"),
+            body: body.clone(),
+        };
+
+        let span = tracing::Span::current();
+        span.record("event_type", webhook.event_type.as_deref());
+        span.record("delivery_id", webhook.delivery_id.as_deref());
+        span.record("invoice_id", webhook.invoice_id.as_deref());
+        span.record("store_id", webhook.store_id.as_deref());
+
+        // The store id is not verified yet, it only picks the secret to check with
+        let secret = match &self.secrets {
+            Some(secrets) => secrets.secret_for(webhook.store_id.as_deref()),
+            None => BTCPAY_WEBHOOK_SECRET.as_deref(),
+        };
+
+        let secret = match secret {
+            Some(secret) => secret,
+            None => {
+                error!("No webhook secret configured for store {:?}", webhook.store_id);
+                return Err(Rejection::unauthorized(VerifyFailure::BadSignature));
+            },
+        };
+
+        if !verify_signature(body_str, secret, signature) {
+            error!("Bad signature. Check webhook secret, or unauthorised request.");
+            return Err(Rejection::unauthorized(VerifyFailure::BadSignature));
+        }
+
+        metrics::webhook_received("btcpay", webhook.event_type.as_deref().unwrap_or("unknown"));
+
+        Ok(webhook)
+    }
+}
+
+/// The span adapters run verification and the handler in, so everything the webhook causes is grouped under it
+pub fn span() -> tracing::Span {
+    tracing::info_span!(
+        "btcpay.webhook",
+        provider = "btcpay",
+        event_type = field::Empty,
+        delivery_id = field::Empty,
+        invoice_id = field::Empty,
+        store_id = field::Empty,
+    )
+}
+
+/// A request that passed verification, with a few well known fields of its body
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub provider: Provider,
+    pub event_type: Option<String>,
+    pub delivery_id: Option<String>,
+    pub invoice_id: Option<String>,
+    pub store_id: Option<String>,
+    /// The exact bytes that were signed, known to be UTF-8
+    pub body: Bytes,
+}
+
+impl Webhook {
+    pub fn body_str(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap_or_default()
+    }
+
+    /// Deserialize the body, eg. into `btcpay::WebhookPayload`. Fails with a 400, like actix's `web::Json`.
+    pub fn payload<T: DeserializeOwned>(&self) -> Result<T, Rejection> {
+        serde_json::from_slice(&self.body).map_err(|err| {
+            debug!("Webhook body does not parse: {err}");
+            Rejection::bad_request("body is not a valid payload")
+        })
+    }
+
+    /// The body as a provider independent event, see `event::normalize`
+    pub fn normalize(&self) -> Result<Option<NormalizedEvent>, Rejection> {
+        event::normalize(self.provider, self.body_str()).map_err(|_| Rejection::bad_request("body is not a valid payload"))
+    }
+}
+
+/// Why a request did not reach the handler. Responds with `application/problem+json`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    status: StatusCode,
+    detail: &'static str,
+    reason: Option<VerifyFailure>,
+}
+
+impl Rejection {
+    /// A failed verification, answered with 401
+    pub fn unauthorized(reason: VerifyFailure) -> Self {
+        Rejection { status: StatusCode::UNAUTHORIZED, detail: reason.as_str(), reason: Some(reason) }
+    }
+
+    pub fn bad_request(detail: &'static str) -> Self {
+        Rejection { status: StatusCode::BAD_REQUEST, detail, reason: None }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn detail(&self) -> &'static str {
+        self.detail
+    }
+
+    /// Set for failed verifications
+    pub fn reason(&self) -> Option<VerifyFailure> {
+        self.reason
+    }
+
+    pub fn problem(&self) -> Problem {
+        Problem::new(self.status, Some(self.detail))
+    }
+
+    pub fn into_response(self) -> Response<Bytes> {
+        problem_http_response(self.status, Some(self.detail))
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rejected with {}: {}", self.status, self.detail)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// Business logic for verified webhooks, run by `dispatch`
+#[async_trait]
+pub trait WebhookHandler: Send + Sync + 'static {
+    async fn handle(&self, webhook: &Webhook) -> HandlerResult;
+}
+
+/// Verify a request and run the handler on it. Answers like the actix routes: 401 for a failed
+/// verification, then 200, 503 or the handler's rejection (see `error::handler_response`).
+pub async fn dispatch(verifier: &Verifier, handler: &dyn WebhookHandler, request: Request<Bytes>) -> Response<Bytes> {
+    use tracing::Instrument;
+
+    async {
+        match verifier.verify_request(&request) {
+            Ok(webhook) => handler_response(&metrics::observe_handler("btcpay", handler.handle(&webhook)).await),
+            Err(rejection) => rejection.into_response(),
+        }
+    }.instrument(span()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btcpay::sign_payload;
+    use crate::error::HandlerError;
+
+    const BODY: &str = r#"{"type":"InvoiceSettled","storeId":"store-a","invoiceId":"inv-1"}"#;
+
+    fn request(signature: Option<&str>, body: &'static [u8]) -> Request<Bytes> {
+        let mut request = Request::post("/btcpay/webhook");
+        if let Some(signature) = signature {
+            request = request.header(BTCPAY_SIG_HEADER, signature);
+        }
+        request.body(Bytes::from_static(body)).unwrap()
+    }
+
+    struct RejectStore;
+
+    #[async_trait]
+    impl WebhookHandler for RejectStore {
+        async fn handle(&self, webhook: &Webhook) -> HandlerResult {
+            match webhook.store_id.as_deref() {
+                Some("store-c") => Err(HandlerError::rejected(StatusCode::UNPROCESSABLE_ENTITY, "unknown store")),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_request() {
+        let verifier = Verifier::new(WebhookSecrets::new(Some("secret".to_string())).with_store("store-b", "other"));
+
+        let webhook = verifier.verify_request(&request(Some(&sign_payload(BODY, "secret")), BODY.as_bytes())).unwrap();
+        assert_eq!(webhook.store_id.as_deref(), Some("store-a"));
+        assert_eq!(webhook.normalize().unwrap().unwrap().invoice_id.as_deref(), Some("inv-1"));
+        assert_eq!(webhook.payload::<Vec<u8>>().unwrap_err().status(), StatusCode::BAD_REQUEST);
+        assert!(verifier.verify_request(&request(Some(&sign_payload(BODY, "secret").to_uppercase().replace("SHA256=", "sha256=")), BODY.as_bytes())).is_ok());
+
+        let rejection = |signature: Option<&str>, body: &'static [u8]| verifier.verify_request(&request(signature, body)).unwrap_err();
+        assert_eq!(rejection(None, BODY.as_bytes()), Rejection::unauthorized(VerifyFailure::MissingHeader));
+        assert_eq!(rejection(Some("sha256=00"), BODY.as_bytes()), Rejection::unauthorized(VerifyFailure::BadSignature));
+        // A prefix or an extension of the right signature is the wrong length
+        let truncated = sign_payload(BODY, "secret")[..41].to_string();
+        assert_eq!(rejection(Some(&truncated), BODY.as_bytes()), Rejection::unauthorized(VerifyFailure::BadSignature));
+        let extended = format!("{}00", sign_payload(BODY, "secret"));
+        assert_eq!(rejection(Some(&extended), BODY.as_bytes()), Rejection::unauthorized(VerifyFailure::BadSignature));
+        assert_eq!(rejection(Some("sha256=not-hex"), BODY.as_bytes()), Rejection::unauthorized(VerifyFailure::BadHeader));
+        assert_eq!(rejection(Some(&sign_payload(BODY, "other")), BODY.as_bytes()), Rejection::unauthorized(VerifyFailure::BadSignature));
+        assert_eq!(rejection(Some("sha256=00"), b"\xff{}"), Rejection::unauthorized(VerifyFailure::BadBody));
+        assert_eq!(rejection(None, b"\xff{}").status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let verifier = Verifier::new(WebhookSecrets::new(Some("secret".to_string())));
+
+        let response = dispatch(&verifier, &RejectStore, request(Some(&sign_payload(BODY, "secret")), BODY.as_bytes())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = r#"{"storeId":"store-c"}"#;
+        let response = dispatch(&verifier, &RejectStore, request(Some(&sign_payload(body, "secret")), body.as_bytes())).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = dispatch(&verifier, &RejectStore, request(None, BODY.as_bytes())).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.body(), r#"{"type":"about:blank","title":"Unauthorized","status":401,"detail":"missing_header"}"#);
+    }
+
+    // The same requests through `BTCPayHeaderVerify`, `BTCPayVerifyLayer` and the axum extractor get the same responses
+    #[cfg(all(feature = "actix", feature = "axum"))]
+    #[actix_web::test]
+    async fn test_adapters_agree() {
+        use crate::btcpay::{btcpay_middleware::BTCPayHeaderVerify, BTCPayVerifyLayer};
+        use actix_web::{test, web, App, HttpResponse};
+        use axum::{routing::post, Extension, Router};
+        use tower::ServiceExt;
+
+        let secrets = WebhookSecrets::new(Some("secret".to_string())).with_store("store-b", "secret-b");
+        let store_b = r#"{"type":"InvoiceExpired","storeId":"store-b"}"#;
+        let store_c = r#"{"type":"InvoiceExpired","storeId":"store-c"}"#;
+
+        let cases = [
+            ("valid", Some(sign_payload(BODY, "secret").into_bytes()), BODY.as_bytes(), 200),
+            ("store secret", Some(sign_payload(store_b, "secret-b").into_bytes()), store_b.as_bytes(), 200),
+            ("default secret for store", Some(sign_payload(store_b, "secret").into_bytes()), store_b.as_bytes(), 401),
+            ("missing header", None, BODY.as_bytes(), 401),
+            ("bad signature", Some(b"sha256=BADSIG".to_vec()), BODY.as_bytes(), 401),
+            ("non-ASCII header", Some(b"sha256=\xff".to_vec()), BODY.as_bytes(), 401),
+            ("non-UTF-8 body", Some(b"sha256=00".to_vec()), &b"\xff{}"[..], 401),
+            ("handler rejection", Some(sign_payload(store_c, "secret").into_bytes()), store_c.as_bytes(), 422),
+        ];
+
+        let actix = test::init_service(App::new()
+            .app_data(web::Data::new(secrets.clone()))
+            .service(web::scope("/btcpay").wrap(BTCPayHeaderVerify).route("/webhook", web::post().to(
+                |webhook: web::ReqData<Webhook>| async move {
+                    RejectStore.handle(&webhook).await.map(|_| HttpResponse::Ok().finish())
+                },
+            )))).await;
+
+        let layered = Router::new()
+            .route("/btcpay/webhook", post(|Extension(webhook): Extension<Webhook>| async move { RejectStore.handle(&webhook).await }))
+            .layer(BTCPayVerifyLayer::new(secrets.clone()));
+
+        let extracted = Router::new()
+            .route("/btcpay/webhook", post(|webhook: Webhook| async move { RejectStore.handle(&webhook).await }))
+            .layer(Extension(secrets));
+
+        for (name, signature, body, status) in cases {
+            let mut request = test::TestRequest::post().uri("/btcpay/webhook").set_payload(body.to_vec());
+            if let Some(signature) = &signature {
+                request = request.insert_header((BTCPAY_SIG_HEADER, actix_web::http::header::HeaderValue::from_bytes(signature).unwrap()));
+            }
+            let response = test::call_service(&actix, request.to_request()).await;
+            let actix_response = (response.status().as_u16(), test::read_body(response).await);
+            assert_eq!(actix_response.0, status, "{name}");
+
+            for router in [&layered, &extracted] {
+                let mut request = Request::post("/btcpay/webhook");
+                if let Some(signature) = &signature {
+                    request = request.header(BTCPAY_SIG_HEADER, http::HeaderValue::from_bytes(signature).unwrap());
+                }
+                let response = router.clone().oneshot(request.body(axum::body::Body::from(body.to_vec())).unwrap()).await.unwrap();
+                let status = response.status().as_u16();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                assert_eq!((status, body), actix_response, "{name}");
+            }
+        }
+    }
+}
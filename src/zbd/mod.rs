@@ -0,0 +1,230 @@
+use serde_json::{json, Value};
+
+use crate::bolt11;
+use crate::event::{parse_timestamp, EventType, NormalizedEvent, Provider};
+
+#[cfg(feature = "zbd-api")]
+pub mod zbd_api;
+#[cfg(all(feature = "actix", feature = "zbd-api"))]
+pub mod zbd_middleware;
+pub mod zbd_models;
+
+#[cfg(feature = "zbd-api")]
+pub use zbd_api::ZbdClient;
+#[cfg(all(feature = "actix", feature = "zbd-api"))]
+pub use zbd_middleware::ZbdConfirm;
+pub use zbd_models::{Callback, Charge, CreateCharge, Status, WithdrawalRequest};
+
+impl Callback {
+    pub fn id(&self) -> &str {
+        match self {
+            Callback::Charge(charge) => &charge.id,
+            Callback::Withdrawal(withdrawal) => &withdrawal.id,
+        }
+    }
+
+    pub fn internal_id(&self) -> Option<&str> {
+        match self {
+            Callback::Charge(charge) => charge.internal_id.as_deref(),
+            Callback::Withdrawal(withdrawal) => withdrawal.internal_id.as_deref(),
+        }
+    }
+
+    /// eg. `charge.completed` or `withdrawal.expired`
+    pub fn event_type(&self) -> String {
+        match self {
+            Callback::Charge(charge) => format!("charge.{}", charge.status.as_str()),
+            Callback::Withdrawal(withdrawal) => format!("withdrawal.{}", withdrawal.status.as_str()),
+        }
+    }
+
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        match self {
+            Callback::Charge(charge) => charge.normalize(),
+            // Withdrawals are outgoing payments, which normalized events do not cover
+            Callback::Withdrawal(_) => None,
+        }
+    }
+}
+
+impl Charge {
+    pub fn normalize(&self) -> Option<NormalizedEvent> {
+        let event_type = match self.status {
+            Status::Pending => EventType::InvoiceCreated,
+            Status::Completed => EventType::InvoiceSettled,
+            Status::Expired => EventType::InvoiceExpired,
+            Status::Error => EventType::InvoiceInvalid,
+            Status::Unknown => return None,
+        };
+        let invoice = self.invoice.as_ref().and_then(|invoice| bolt11::decode(&invoice.request).ok());
+        let metadata = match (&self.internal_id, &self.description) {
+            (None, None) => Value::Null,
+            (internal_id, description) => json!({"internal_id": internal_id, "description": description}),
+        };
+
+        Some(NormalizedEvent {
+            // A charge calls back once per status
+            id: format!("{}:{}", self.id, self.status.as_str()),
+            provider: Provider::Zbd,
+            event_type,
+            store_id: None,
+            invoice_id: Some(self.id.clone()),
+            payment_hash: invoice.and_then(|invoice| invoice.payment_hash),
+            amount_msat: self.amount.trim().parse().ok(),
+            timestamp: self.confirmed_at.as_deref().or(self.created_at.as_deref()).and_then(parse_timestamp),
+            metadata,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHARGE_COMPLETED: &str = r#"{
+  "id": "6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50",
+  "unit": "msats",
+  "amount": "10000",
+  "createdAt": "2023-05-02T17:48:01.000Z",
+  "internalId": "order-23",
+  "callbackUrl": "https://example.com/zbd/callback",
+  "description": "Article a1",
+  "expiresAt": "2023-05-02T17:53:01.000Z",
+  "confirmedAt": "2023-05-02T17:49:15.000Z",
+  "status": "completed",
+  "invoice": {
+    "request": "lnbc100n1pjyl0qfsp533xd0y5zfakhm0ytyauhlgd0jvh0c03ucw7c7eeynaljgzh6yz9spp5t4lw8tmkuft80gpwesfjurjj064cllyjkzhj4ew0z64wwurwew3qdq5w3jhxapqwajky6r0da4sxqzjccqpjrzjqwz34f2ec60uwx0cfhmvfq9lw4j52ct98jr4p5nqwqluynewq7qkszl3wgqq9jqqqqqqqqqqqqqqqqcqjq9qyysgq70qtyljrcp64m7q8lfzezxp2zfasun9flx7mg6aej262gqxsrw94uj0w2y5664ymkapuwrv0gmdzctrjfx0j3xu9qjyeeze5yw0jkhcpqwasks",
+    "uri": "lightning:lnbc100n1..."
+  }
+}"#;
+
+    const WITHDRAWAL_EXPIRED: &str = r#"{
+  "id": "0d1e2f3a-4b5c-4d6e-8f70-8192a3b4c5d6",
+  "unit": "msats",
+  "amount": "5000",
+  "createdAt": "2023-05-02T17:48:01.000Z",
+  "expiresAt": "2023-05-02T17:53:01.000Z",
+  "internalId": "payout-7",
+  "description": "Tournament prize",
+  "callbackUrl": "https://example.com/zbd/callback",
+  "status": "expired",
+  "invoice": {
+    "request": "lnurl1dp68gurn8ghj7...",
+    "fastRequest": "lnurl1dp68gurn8ghj7...fast",
+    "uri": "lightning:lnurl1dp68gurn8ghj7...",
+    "fastUri": "lightning:lnurl1dp68gurn8ghj7...fast"
+  }
+}"#;
+
+    #[test]
+    fn test_parse_callbacks() {
+        let callback: Callback = serde_json::from_str(CHARGE_COMPLETED).unwrap();
+        let Callback::Charge(charge) = &callback else { panic!("not a charge: {callback:?}") };
+        assert_eq!(charge.status, Status::Completed);
+        assert_eq!(callback.internal_id(), Some("order-23"));
+        assert_eq!(callback.event_type(), "charge.completed");
+
+        let callback: Callback = serde_json::from_str(WITHDRAWAL_EXPIRED).unwrap();
+        assert!(matches!(callback, Callback::Withdrawal(_)), "not a withdrawal: {callback:?}");
+        assert_eq!(callback.event_type(), "withdrawal.expired");
+        assert!(callback.normalize().is_none());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let event = crate::event::normalize(Provider::Zbd, CHARGE_COMPLETED).unwrap().unwrap();
+        assert_eq!(event.id, "6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50:completed");
+        assert_eq!(event.event_type, EventType::InvoiceSettled);
+        assert_eq!(event.invoice_id.as_deref(), Some("6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50"));
+        assert_eq!(event.payment_hash.as_deref(), Some("5d7ee3af76e25677a02ecc132e0e527eab8ffc92b0af2ae5cf16aae7706ecba2"));
+        assert_eq!(event.amount_msat, Some(10_000));
+        assert_eq!(event.timestamp, Some(1683049755));
+        assert_eq!(event.metadata, json!({"internal_id": "order-23", "description": "Article a1"}));
+
+        assert_eq!(crate::event::normalize(Provider::Zbd, WITHDRAWAL_EXPIRED).unwrap(), None);
+
+        let expired = CHARGE_COMPLETED.replace(r#""status": "completed""#, r#""status": "expired""#);
+        assert_eq!(crate::event::normalize(Provider::Zbd, &expired).unwrap().unwrap().event_type, EventType::InvoiceExpired);
+    }
+
+    // Against a local mock of the ZBD API, which knows one charge and no withdrawals
+    #[cfg(all(feature = "actix", feature = "zbd-api"))]
+    #[actix_web::test]
+    async fn test_zbd_confirm() {
+        use crate::verifier;
+        use actix_web::{test, web, App, HttpRequest, HttpResponse};
+        use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+        const API_KEY: &str = "zbd-api-key";
+
+        let wallet_requested = Arc::new(AtomicBool::new(false));
+        let wallet = wallet_requested.clone();
+        let url = verifier::mock_server(move |app| {
+            let wallet = wallet.clone();
+            app.route("/v0/wallet", web::get().to(move || {
+                wallet.store(true, Ordering::SeqCst);
+                async { HttpResponse::Ok().json(json!({"success": true, "data": {"balance": "21000000"}})) }
+            }))
+            .route("/v0/charges", web::post().to(|req: HttpRequest, charge: web::Json<CreateCharge>| async move {
+                if req.headers().get("apikey").is_none_or(|key| key != API_KEY) {
+                    return HttpResponse::Unauthorized().json(json!({"success": false, "message": "Invalid API key"}));
+                }
+                let mut created: Charge = serde_json::from_str(CHARGE_COMPLETED).unwrap();
+                created.status = Status::Pending;
+                created.amount = charge.amount.clone();
+                created.internal_id = charge.internal_id.clone();
+                HttpResponse::Ok().json(json!({"success": true, "message": "Successfully created Charge.", "data": created}))
+            }))
+            .route("/v0/charges/{id}", web::get().to(|id: web::Path<String>| async move {
+                match id.as_str() {
+                    "6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50" => HttpResponse::Ok().body(format!(r#"{{"success": true, "data": {CHARGE_COMPLETED}}}"#)),
+                    "00000000-0000-4000-8000-000000000502" => HttpResponse::BadGateway().json(json!({"success": false, "message": "Upstream error"})),
+                    _ => HttpResponse::NotFound().json(json!({"success": false, "message": "Charge not found"})),
+                }
+            }))
+            .route("/v0/withdrawal-requests/{id}", web::get().to(|| async {
+                HttpResponse::NotFound().json(json!({"success": false, "message": "Withdrawal request not found"}))
+            }));
+        });
+
+        let client = ZbdClient::new(&url, API_KEY);
+        let charge = client.create_charge(&CreateCharge {
+            amount: "10000".to_string(),
+            description: "Article a1".to_string(),
+            internal_id: Some("order-24".to_string()),
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(charge.status, Status::Pending);
+        assert_eq!(charge.internal_id.as_deref(), Some("order-24"));
+        assert!(ZbdClient::new(&url, "other-key").create_charge(&CreateCharge::default()).await.unwrap_err().to_string().contains("Invalid API key"));
+        assert_eq!(client.get_charge("6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50").await.unwrap().status, Status::Completed);
+        assert!(client.get_charge("missing").await.is_err());
+
+        let app = test::init_service(App::new()
+            .service(web::scope("/zbd").wrap(ZbdConfirm::new(client)).route("/callback", web::post().to(
+                |callback: web::ReqData<Callback>| async move { HttpResponse::Ok().body(callback.event_type()) },
+            )))).await;
+        let callback = |body: &str| test::TestRequest::post().uri("/zbd/callback").set_payload(body.to_string()).to_request();
+
+        // The fetched charge is what the handler sees, not the callback's claims
+        let claims_pending = CHARGE_COMPLETED.replace(r#""status": "completed""#, r#""status": "pending""#);
+        assert_eq!(test::call_and_read_body(&app, callback(&claims_pending)).await, "charge.completed");
+
+        let forged = CHARGE_COMPLETED.replace("6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50", "00000000-0000-4000-8000-000000000404");
+        let response = test::call_service(&app, callback(&forged)).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert_eq!(test::read_body(response).await, r#"{"type":"about:blank","title":"Unauthorized","status":401,"detail":"unknown_object"}"#);
+        assert_eq!(test::call_service(&app, callback(WITHDRAWAL_EXPIRED)).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert_eq!(test::call_service(&app, callback("not json")).await.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+
+        // An id that walks to another endpoint is never sent with the API key
+        let forged_path = CHARGE_COMPLETED.replace("6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50", "../../v0/wallet");
+        let response = test::call_service(&app, callback(&forged_path)).await;
+        assert_eq!(response.status(), actix_web::http::StatusCode::UNAUTHORIZED);
+        assert_eq!(test::read_body(response).await, r#"{"type":"about:blank","title":"Unauthorized","status":401,"detail":"unknown_object"}"#);
+        assert!(!wallet_requested.load(Ordering::SeqCst));
+
+        let unreachable = CHARGE_COMPLETED.replace("6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50", "00000000-0000-4000-8000-000000000502");
+        assert_eq!(test::call_service(&app, callback(&unreachable)).await.status(), actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{anyhow, bail, Result};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::telemetry;
+use crate::zbd::{Callback, Charge, CreateCharge, WithdrawalRequest};
+
+pub const ZBD_HOST: &str = "https://api.zebedee.io";
+
+/// Client for the ZBD API
+#[derive(Debug, Clone)]
+pub struct ZbdClient {
+    host: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+// Results are wrapped in `data`, errors carry a `message`
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    success: Option<bool>,
+    data: Option<T>,
+    message: Option<String>,
+}
+
+impl ZbdClient {
+    pub fn new(host: &str, api_key: &str) -> Self {
+        ZbdClient {
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Build a client from the `ZBD_API_KEY` environment variable, and `ZBD_HOST` if set
+    pub fn from_env() -> Result<Self> {
+        let api_key = std::env::var("ZBD_API_KEY").map_err(|_| anyhow!("ZBD_API_KEY must be set"))?;
+        let host = std::env::var("ZBD_HOST").unwrap_or_else(|_| ZBD_HOST.to_string());
+        Ok(ZbdClient::new(&host, &api_key))
+    }
+
+    #[tracing::instrument(name = "zbd.create_charge", skip_all, fields(internal_id = charge.internal_id))]
+    pub async fn create_charge(&self, charge: &CreateCharge) -> Result<Charge> {
+        let request = self.client
+            .post(format!("{}/v0/charges", self.host))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(charge)?);
+        self.send(request).await?.ok_or_else(|| anyhow!("charge was not created"))
+    }
+
+    #[tracing::instrument(name = "zbd.get_charge", skip(self))]
+    pub async fn get_charge(&self, id: &str) -> Result<Charge> {
+        check_id(id)?;
+        self.send(self.client.get(format!("{}/v0/charges/{id}", self.host))).await?
+            .ok_or_else(|| anyhow!("charge {id} not found"))
+    }
+
+    #[tracing::instrument(name = "zbd.get_withdrawal_request", skip(self))]
+    pub async fn get_withdrawal_request(&self, id: &str) -> Result<WithdrawalRequest> {
+        check_id(id)?;
+        self.send(self.client.get(format!("{}/v0/withdrawal-requests/{id}", self.host))).await?
+            .ok_or_else(|| anyhow!("withdrawal request {id} not found"))
+    }
+
+    /// Callbacks are unsigned, so the charge or withdrawal is fetched with the API key and used in its
+    /// place. `None` when ZBD does not know it, ie. the callback was forged.
+    #[tracing::instrument(name = "zbd.confirm", skip_all, fields(id = callback.id()))]
+    pub async fn confirm(&self, callback: &Callback) -> Result<Option<Callback>> {
+        // ZBD ids are UUIDs, anything else would change the path the API key is sent to
+        if check_id(callback.id()).is_err() {
+            return Ok(None);
+        }
+        Ok(match callback {
+            Callback::Charge(charge) => self.send(self.client.get(format!("{}/v0/charges/{}", self.host, charge.id))).await?
+                .map(Callback::Charge),
+            Callback::Withdrawal(withdrawal) => self.send(self.client.get(format!("{}/v0/withdrawal-requests/{}", self.host, withdrawal.id))).await?
+                .map(Callback::Withdrawal),
+        })
+    }
+
+    // `None` for a 404
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<Option<T>> {
+        let request = request.header("apikey", &self.api_key);
+        let response = telemetry::inject_trace_context(request).send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = serde_json::from_str::<ApiResponse<T>>(&body)
+            .map_err(|err| anyhow!("zbd response ({status}) does not parse: {err}"))?;
+
+        match response.data {
+            Some(data) if status.is_success() && response.success != Some(false) => Ok(Some(data)),
+            _ => bail!("zbd request failed with {status}: {}", response.message.unwrap_or_default()),
+        }
+    }
+}
+
+// Ids go into the URL path, so only UUIDs, eg. `6a2f7a0e-3cb5-4a9c-9b8e-1f0c2d3e4f50`, are accepted
+fn check_id(id: &str) -> Result<()> {
+    let groups: Vec<&str> = id.split('-').collect();
+    let uuid = groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups.iter().all(|group| group.bytes().all(|b| b.is_ascii_hexdigit()));
+    if uuid { Ok(()) } else { bail!("not a zbd id: {id:?}") }
+}
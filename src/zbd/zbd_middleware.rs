@@ -0,0 +1,65 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    Error, body::EitherBody,
+};
+use crate::metrics::VerifyFailure;
+use crate::verifier::{verifier_middleware::VerifyWebhookMiddleware, ConfirmWebhook, Headers, VerifyWebhook, WebhookVerifier};
+use crate::zbd::{Callback, ZbdClient};
+
+/// Confirms ZBD callbacks by fetching the charge or withdrawal they name, as they are unsigned. The
+/// handler gets the fetched `Callback` in the request extensions, eg. for `web::ReqData<Callback>`.
+/// Callbacks ZBD does not know are answered with a 401, and a 503 if the API cannot be reached, so ZBD
+/// calls back again.
+pub struct ZbdConfirm {
+    verify: VerifyWebhook<ZbdCallbacks, ZbdClient>,
+}
+
+impl ZbdConfirm {
+    pub fn new(client: ZbdClient) -> Self {
+        ZbdConfirm { verify: VerifyWebhook::new(ZbdCallbacks).confirm_with(client) }
+    }
+}
+
+impl<S: 'static, B> Transform<S, ServiceRequest> for ZbdConfirm
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = VerifyWebhookMiddleware<S, ZbdCallbacks, ZbdClient>;
+    type Future = <VerifyWebhook<ZbdCallbacks, ZbdClient> as Transform<S, ServiceRequest>>::Future;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        self.verify.new_transform(service)
+    }
+}
+
+/// Only parses callbacks, which carry no signature. `ZbdConfirm` pairs it with the `ZbdClient`
+/// confirmation, and it should not be used without one.
+#[derive(Clone)]
+pub struct ZbdCallbacks;
+
+impl WebhookVerifier for ZbdCallbacks {
+    type Payload = Callback;
+
+    fn provider(&self) -> &'static str {
+        "zbd"
+    }
+
+    fn verify(&self, _headers: &dyn Headers, body: &[u8]) -> Result<Callback, VerifyFailure> {
+        serde_json::from_slice(body).map_err(|_| VerifyFailure::BadBody)
+    }
+
+    fn event_type(&self, callback: &Callback) -> String {
+        callback.event_type()
+    }
+}
+
+impl ConfirmWebhook<Callback> for ZbdClient {
+    async fn confirm(&self, callback: Callback) -> anyhow::Result<Option<Callback>> {
+        ZbdClient::confirm(self, &callback).await
+    }
+}
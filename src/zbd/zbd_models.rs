@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+// REF: https://zbd.dev/api-reference/payments/charges and https://zbd.dev/api-reference/payments/withdrawal-requests
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pending,
+    Completed,
+    Expired,
+    Error,
+    #[serde(other)]
+    Unknown,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Completed => "completed",
+            Status::Expired => "expired",
+            Status::Error => "error",
+            Status::Unknown => "unknown",
+        }
+    }
+}
+
+/// A charge, as posted to its `callbackUrl` and returned by `GET /v0/charges/{id}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Charge {
+    pub id: String,
+    /// `msats`
+    pub unit: Option<String>,
+    /// Millisatoshis, as a string
+    pub amount: String,
+    pub status: Status,
+    /// Set when the charge was created, see `CreateCharge`
+    pub internal_id: Option<String>,
+    pub description: Option<String>,
+    pub callback_url: Option<String>,
+    /// eg. `2023-05-02T17:49:15.000Z`
+    pub created_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub confirmed_at: Option<String>,
+    pub invoice: Option<ChargeInvoice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChargeInvoice {
+    /// The BOLT11 invoice
+    pub request: String,
+    pub uri: Option<String>,
+}
+
+/// A withdrawal request, as posted to its `callbackUrl` and returned by `GET /v0/withdrawal-requests/{id}`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawalRequest {
+    pub id: String,
+    pub unit: Option<String>,
+    pub amount: String,
+    pub status: Status,
+    pub internal_id: Option<String>,
+    pub description: Option<String>,
+    pub callback_url: Option<String>,
+    pub created_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub invoice: WithdrawalInvoice,
+}
+
+/// An LNURL-withdraw QR, where charges have a BOLT11 invoice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WithdrawalInvoice {
+    pub request: String,
+    pub fast_request: String,
+    pub uri: Option<String>,
+    pub fast_uri: Option<String>,
+}
+
+// Withdrawals are tried first, as only their invoice has a `fastRequest`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Callback {
+    Withdrawal(WithdrawalRequest),
+    Charge(Charge),
+}
+
+/// Body for `POST /v0/charges`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCharge {
+    /// Millisatoshis, as a string
+    pub amount: String,
+    pub description: String,
+    /// Seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal_id: Option<String>,
+}